@@ -1,7 +1,17 @@
+use std::cmp::{self, Ordering};
+use std::collections::{BTreeMap, HashSet};
 use std::io;
-use std::io::{Read, Write, Error ,ErrorKind};
+use std::mem;
+use std::io::{Read, Write, Error};
 use std::fs::File;
+use std::path::Path;
 use cursor::Cursor;
+use columns::{self, byte_index, line_len};
+use brackets;
+use config::{Config, Settings, LineEnding, Charset};
+use editorconfig::{self, Properties};
+use undo::{Edit, UndoHistory};
+use toml::value::{Table, Value};
 
 /// The Backend is responsible opening files and editing text.
 /// It does this by managing a Vec of Buffers that actually edit the text.
@@ -13,23 +23,117 @@ pub struct Backend {
     buffers: Vec<Buffer>,
     /// The index of the current Buffer
     current: usize,
+    /// The id to give the next Buffer that is opened.
+    next_id: usize,
+    /// The user's configuration, which decides the settings of each Buffer.
+    config: Config,
+}
+
+/// A Change is a single edit to the text of a Buffer.
+/// Buffers keep a list of their changes so that anything else pointing into
+/// the text (like the cursors of other panes) can keep up with them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Change {
+    /// A character was inserted at `line`, `column`.
+    InsertChar { line: usize, column: usize },
+    /// The character at `line`, `column` was deleted.
+    DeleteChar { line: usize, column: usize },
+    /// `line` was split in two at `column`.
+    SplitLine { line: usize, column: usize },
+    /// `line` was moved onto the end of the line before it,
+    /// which was `column` long.
+    JoinLine { line: usize, column: usize },
+    /// A new line was inserted before `line`.
+    InsertLine { line: usize },
+    /// `line` was deleted, and the line after it took its place.
+    DeleteLine { line: usize },
+    /// The text of `line` was replaced with text that is `len` characters long.
+    ReplaceLine { line: usize, len: usize },
+}
+
+/// A block of text that is selected by its columns rather than as a run of text,
+/// which is handy for tables. It covers the visual columns from `left` up to
+/// (but not including) `right` on each line from `first` to `last`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Block {
+    pub first: usize,
+    pub last: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+/// How `Buffer::sort_lines` puts the lines in order.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sort {
+    /// Sort by the first number in each line instead of by the text.
+    /// Lines without a number go first.
+    pub numeric: bool,
+    /// Put the lines in the opposite order.
+    pub reverse: bool,
+    /// Only keep the first of lines that are the same.
+    pub unique: bool,
 }
 
 impl Backend {
-    /// Constructs and returns a new Backend from the command line arguments
-    pub fn new(files: Vec<String>) -> Backend {
+    /// Constructs and returns a new Backend from the command line arguments.
+    /// Fails if one of the files exists but can't be read.
+    pub fn new(files: Vec<String>, config: Config) -> io::Result<Backend> {
         let buffers = if files.is_empty() {
             vec![Buffer::new()]
         } else {
             let mut buffers = Vec::with_capacity(files.len());
             for file in files {
-                buffers.push(Buffer::from_file(file).unwrap());
+                let buffer = Buffer::from_file(file.clone()).map_err(|e| {
+                    Error::new(e.kind(), format!("can't open {}: {}", file, e))
+                })?;
+                buffers.push(buffer);
             }
             buffers
         };
-        Backend {
-            buffers: buffers,
+        let mut backend = Backend {
+            buffers: Vec::with_capacity(buffers.len()),
             current: 0,
+            next_id: 0,
+            config: config,
+        };
+        for buffer in buffers {
+            let index = backend.buffers.len();
+            backend.add_buffer(index, buffer);
+        }
+        Ok(backend)
+    }
+    /// Gives the buffer an id and its settings, and inserts it at `index`.
+    fn add_buffer(&mut self, index: usize, mut buffer: Buffer) {
+        buffer.id = self.next_id;
+        buffer.update_settings(&self.config);
+        self.next_id += 1;
+        self.buffers.insert(index, buffer);
+    }
+    /// Replaces the configuration, and updates the settings of every buffer to match.
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+        for buffer in &mut self.buffers {
+            buffer.update_settings(&self.config);
+        }
+    }
+    /// Returns the index of the buffer with the given id, if it is still open.
+    pub fn buffer_index(&self, id: usize) -> Option<usize> {
+        self.buffers.iter().position(|b| b.id == id)
+    }
+    /// Returns the changes made to each buffer since the last time this was called,
+    /// along with the id of the buffer that was changed.
+    pub fn take_changes(&mut self) -> Vec<(usize, Change)> {
+        let mut changes = Vec::new();
+        for buffer in &mut self.buffers {
+            let id = buffer.id;
+            changes.extend(buffer.changes.drain(..).map(|c| (id, c)));
+        }
+        changes
+    }
+    /// Ends the undo group of every buffer, so that undo stops at what was done before now.
+    pub fn end_undo_groups(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.end_undo_group();
         }
     }
     /// Returns the lines of text from the buffer that is being edited
@@ -44,11 +148,15 @@ impl Backend {
     }
     /// Returns the length of the line in the current buffer
     pub fn length_of_line(&self, line: usize) -> usize {
-        self.current_buffer().lines[line].len()
+        line_len(&self.current_buffer().lines[line])
+    }
+    /// Inserts a newline at every cursor.
+    pub fn insert_newline(&mut self) {
+        self.at_every_cursor(Backend::newline_at_cursor);
     }
     /// Inserts a newline at the position given by the Cursor and updates
     /// the Cursor to reflect the new position
-    pub fn insert_newline(&mut self) {
+    fn newline_at_cursor(&mut self) {
         // Get the (x, y) location of the cursor.
         // This happens in a seperate block to keep the borrow checker happy
         let (x, y) = {
@@ -59,17 +167,41 @@ impl Backend {
             // Inserts the new line at the right place.
             // This is in a seperate block to make sure that buf gets destroyed
             // before we mutate self.cursor.
-            let mut buf = self.current_buffer_mut();
+            let buf = self.current_buffer_mut();
             buf.split_line_into_two_at(x, y);
         };
         // Updates the cursor to the new position
-        let mut cursor = self.cursor_mut();
+        let cursor = self.cursor_mut();
         cursor.line += 1;
         cursor.column = 0;
+        // Indent the new line to match the one it came from.
+        if !self.current_buffer().settings().auto_indent {
+            return;
+        }
+        let indent = self.current_buffer().indent_after(x, y);
+        for c in indent.chars() {
+            self.char_at_cursor(c);
+        }
+    }
+    /// Deletes the character before every cursor. With auto pairs on,
+    /// deleting the start of an empty pair like `()` deletes the end of it too,
+    /// if auto pairs put it there.
+    pub fn insert_backspace(&mut self) {
+        self.at_every_cursor(|backend| {
+            let Cursor { line, column, .. } = *backend.cursor();
+            if backend.current_buffer().settings.auto_pairs && column > 0
+                && backend.current_buffer().is_auto_closed(line, column) {
+                let chars: Vec<char> = backend.current_lines()[line].chars().collect();
+                if chars.get(column).is_some_and(|&after| brackets::is_empty_pair(chars[column - 1], after)) {
+                    backend.current_buffer_mut().delete_char_at(line, column);
+                }
+            }
+            backend.backspace_at_cursor();
+        });
     }
     /// Inserts a backspace at the position given by the Cursor and updates
     /// the Cursor to reflect the new position
-    pub fn insert_backspace(&mut self) {
+    fn backspace_at_cursor(&mut self) {
         // Get the (x, y) location of the cursor.
         // This happens in a seperate block to keep the borrow checker happy
         let (x, y) = {
@@ -83,7 +215,7 @@ impl Backend {
         if y == 0 {
             // If we are at the begining of the line we just move the current
             // line to the end of the previous line.
-            self.cursor_mut().column = self.current_buffer().lines[x - 1].len();
+            self.cursor_mut().column = line_len(&self.current_buffer().lines[x - 1]);
             self.current_buffer_mut().join_lines_at(x);
             self.cursor_mut().line -= 1;
         } else {
@@ -94,15 +226,35 @@ impl Backend {
             self.cursor_mut().column -= 1;
         }
     }
+    /// Types a character at every cursor. With auto pairs on, an opening bracket or
+    /// quote gets closed too, and typing the closing one when auto pairs put it
+    /// next to the cursor steps over it instead of adding another.
+    pub fn insert_char(&mut self, c: char) {
+        self.at_every_cursor(|backend| {
+            let Cursor { line, column, .. } = *backend.cursor();
+            if !backend.current_buffer().settings.auto_pairs {
+                return backend.char_at_cursor(c);
+            }
+            let chars: Vec<char> = backend.current_lines()[line].chars().collect();
+            let (before, after) = (column.checked_sub(1).map(|i| chars[i]), chars.get(column).cloned());
+            if brackets::is_closing(c) && after == Some(c) && backend.current_buffer_mut().take_auto_closed(line, column) {
+                backend.cursor_mut().set_column(column + 1);
+                return;
+            }
+            backend.char_at_cursor(c);
+            if let Some(close) = brackets::auto_close(c, before, after) {
+                backend.current_buffer_mut().insert_auto_closed(close, line, column + 1);
+            }
+        });
+    }
     /// Inserts a character at the position given by the Cursor and updates
     /// the Cursor to reflect the new position
-    // BUG: Doesn't work for most non-ascii utf8 text. :(
-    pub fn insert_char(&mut self, c: char) {
+    fn char_at_cursor(&mut self, c: char) {
         // Get the (x, y) location of the cursor.
         // This happens in a seperate block to keep the borrow checker happy
         let (x, y) = {
             let cursor = self.cursor();
-            (cursor.line as usize, cursor.column as usize)
+            (cursor.line, cursor.column)
         };
         {
             // Update the buffer in a seperate block to
@@ -112,6 +264,162 @@ impl Backend {
         // Update the cursor.
         self.cursor_mut().column += 1;
     }
+    /// Inserts a tab at every cursor. If the buffer is set to indent with spaces
+    /// then spaces are inserted up to the next multiple of the indent size instead.
+    pub fn insert_tab(&mut self) {
+        self.at_every_cursor(Backend::tab_at_cursor);
+    }
+    /// Inserts a tab, or spaces, at the cursor.
+    fn tab_at_cursor(&mut self) {
+        let settings = self.current_buffer().settings.clone();
+        if !settings.expand_tabs {
+            self.char_at_cursor('\t');
+            return;
+        }
+        let cursor = *self.cursor();
+        let visual = columns::visual_column(&self.current_lines()[cursor.line], cursor.column,
+                                            settings.tab_width);
+        for _ in 0..settings.indent_size - visual % settings.indent_size {
+            self.char_at_cursor(' ');
+        }
+    }
+    /// Does `f` at the main cursor and then at each of the other cursors.
+    /// While `f` works at one cursor, the buffer moves the others along with its edits.
+    fn at_every_cursor<F: Fn(&mut Backend)>(&mut self, f: F) {
+        f(self);
+        for i in 0..self.current_buffer().cursors.len() {
+            self.swap_cursor(i);
+            f(self);
+            self.swap_cursor(i);
+        }
+        self.current_buffer_mut().merge_cursors();
+    }
+    /// Swaps the main cursor with the other cursor at `index`.
+    fn swap_cursor(&mut self, index: usize) {
+        let buffer = self.current_buffer_mut();
+        mem::swap(&mut buffer.cursor, &mut buffer.cursors[index]);
+    }
+    /// Returns the cursors other than the main one in the current buffer.
+    pub fn other_cursors(&self) -> &[Cursor] {
+        &self.current_buffer().cursors
+    }
+    /// Adds a cursor at `at`, and makes it the main cursor.
+    pub fn add_cursor(&mut self, at: Cursor) {
+        let buffer = self.current_buffer_mut();
+        let main = buffer.cursor;
+        buffer.cursors.push(main);
+        buffer.cursor = at;
+        buffer.merge_cursors();
+    }
+    /// Goes back to just the main cursor. Returns false if there weren't any others.
+    pub fn clear_cursors(&mut self) -> bool {
+        let buffer = self.current_buffer_mut();
+        let had_others = !buffer.cursors.is_empty();
+        buffer.cursors.clear();
+        had_others
+    }
+    /// Adds a cursor on the line above the top cursor, or below the bottom
+    /// one if `up` is false, in the same column as the main cursor if it can.
+    /// Returns false if there is no line there.
+    pub fn add_cursor_vertically(&mut self, up: bool) -> bool {
+        let main = *self.cursor();
+        let lines = self.other_cursors().iter().map(|c| c.line).chain(Some(main.line));
+        let line = if up {
+            match lines.min().unwrap() {
+                0 => return false,
+                line => line - 1,
+            }
+        } else {
+            match lines.max().unwrap() + 1 {
+                line if line == self.number_of_lines() => return false,
+                line => line,
+            }
+        };
+        let column = cmp::min(main.column, self.length_of_line(line));
+        self.add_cursor(Cursor::new(line, column));
+        true
+    }
+    /// Adds a cursor at the next place that the word under the main cursor
+    /// appears as a whole word, going round to the start of the buffer if it
+    /// has to. Returns false if there is no word or it doesn't appear anywhere else.
+    pub fn add_cursor_at_next_match(&mut self) -> bool {
+        let main = *self.cursor();
+        let lines = self.current_lines();
+        let chars: Vec<char> = lines[main.line].chars().collect();
+        let mut start = main.column;
+        while start > 0 && is_word_char(chars[start - 1]) {
+            start -= 1;
+        }
+        let mut end = main.column;
+        while end < chars.len() && is_word_char(chars[end]) {
+            end += 1;
+        }
+        if start == end {
+            return false;
+        }
+        let word = &chars[start..end];
+        let mut found = None;
+        // Going all the way round brings us back to the main cursor's line,
+        // where we look before the word too.
+        'search: for step in 0..lines.len() + 1 {
+            let line = (main.line + step) % lines.len();
+            let text: Vec<char> = lines[line].chars().collect();
+            let mut column = if step == 0 { end } else { 0 };
+            while column + word.len() <= text.len() {
+                let whole = (column == 0 || !is_word_char(text[column - 1])) &&
+                    text.get(column + word.len()).is_none_or(|&c| !is_word_char(c));
+                let at = Cursor::new(line, column + main.column - start);
+                if whole && text[column..column + word.len()] == *word &&
+                    at != main && !self.other_cursors().contains(&at) {
+                    found = Some(at);
+                    break 'search;
+                }
+                column += 1;
+            }
+        }
+        match found {
+            Some(at) => {
+                self.add_cursor(at);
+                true
+            },
+            None => false,
+        }
+    }
+    /// Puts a cursor at the end of each line from `start` to `end`, except the
+    /// last line where it goes at `end`, which becomes the main cursor.
+    pub fn split_into_line_cursors(&mut self, start: Cursor, end: Cursor) {
+        let ends: Vec<Cursor> = (start.line..end.line)
+            .map(|line| Cursor::new(line, self.length_of_line(line)))
+            .collect();
+        let buffer = self.current_buffer_mut();
+        buffer.cursors = ends;
+        buffer.cursor = end;
+        buffer.merge_cursors();
+    }
+    /// Changes the indentation of every line in the current buffer to use only
+    /// spaces, or to use as many tabs as possible if `tabs` is true.
+    /// The buffer keeps indenting that way afterwards.
+    pub fn convert_indentation(&mut self, tabs: bool) {
+        let buffer = self.current_buffer_mut();
+        let tab_width = buffer.settings.tab_width;
+        for line in 0..buffer.lines.len() {
+            let old: String = buffer.lines[line].chars()
+                .take_while(|&c| c == ' ' || c == '\t')
+                .collect();
+            let width = columns::visual_width(&old, tab_width);
+            let new = if tabs {
+                format!("{}{}", "\t".repeat(width / tab_width), " ".repeat(width % tab_width))
+            } else {
+                " ".repeat(width)
+            };
+            if new != old {
+                buffer.replace_indent(line, line_len(&old), &new);
+            }
+        }
+        let mut table = Table::new();
+        table.insert(String::from("expand_tabs"), Value::Boolean(!tabs));
+        buffer.override_settings(table);
+    }
     /// Returns the current buffer
     // TODO: Does this need to be public?
     pub fn current_buffer(&self) -> &Buffer {
@@ -126,14 +434,77 @@ impl Backend {
     pub fn filename(&self) -> &Option<String> {
         &self.current_buffer().filename
     }
-    /// Sets the filename of the current buffer
+    /// Sets the filename of the current buffer.
+    /// The buffer's settings change to suit the new name.
     pub fn set_filename(&mut self, name: Option<String>) {
-        self.current_buffer_mut().filename = name
+        let config = self.config.clone();
+        let buffer = self.current_buffer_mut();
+        buffer.editorconfig = name.as_ref()
+            .map_or(Properties::new(), |name| editorconfig::properties(Path::new(name)));
+        buffer.filename = name;
+        buffer.update_settings(&config);
     }
     /// Saves the current buffer to a file
     pub fn save(&mut self) -> io::Result<()> {
         self.current_buffer_mut().save()
     }
+    /// Saves the current buffer under a new name.
+    /// The buffer will keep saving to the new name afterwards, unless it couldn't be saved there.
+    pub fn save_as(&mut self, name: String) -> io::Result<()> {
+        // The new name decides the settings that the file is written with,
+        // so it has to be set first and then put back if the save fails.
+        let old = self.filename().clone();
+        self.set_filename(Some(name));
+        let result = self.save();
+        if result.is_err() {
+            self.set_filename(old);
+        }
+        result
+    }
+    /// Writes a copy of the current buffer to `name` without changing
+    /// the file that the buffer saves to or its dirty state.
+    pub fn save_copy(&self, name: &str) -> io::Result<()> {
+        self.current_buffer().write_to(name)
+    }
+    /// Saves every dirty buffer.
+    /// Returns the name of each buffer that we tried to save along with
+    /// the result of saving it, so that the caller can report failures.
+    pub fn save_all(&mut self) -> Vec<(String, io::Result<()>)> {
+        let mut results = Vec::new();
+        for buffer in self.buffers.iter_mut().filter(|b| b.dirty) {
+            let name = buffer.filename.clone()
+                .unwrap_or_else(|| String::from("**no filename**"));
+            results.push((name, buffer.save()));
+        }
+        results
+    }
+    /// Returns all of the buffers being edited.
+    pub fn buffers(&self) -> &[Buffer] {
+        &self.buffers
+    }
+    /// Returns the index of the current buffer.
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+    /// Switches to the buffer at `index`.
+    pub fn switch_to_buffer(&mut self, index: usize) {
+        assert!(index < self.buffers.len(), "Tried to switch to a buffer that doesn't exist!");
+        self.current = index;
+    }
+    /// Moves the current buffer one place to the left in the list of buffers.
+    pub fn move_buffer_left(&mut self) {
+        if self.current > 0 {
+            self.buffers.swap(self.current, self.current - 1);
+            self.current -= 1;
+        }
+    }
+    /// Moves the current buffer one place to the right in the list of buffers.
+    pub fn move_buffer_right(&mut self) {
+        if self.current + 1 < self.buffers.len() {
+            self.buffers.swap(self.current, self.current + 1);
+            self.current += 1;
+        }
+    }
     /// Switches to the next buffer.
     pub fn switch_to_next_buffer(&mut self) {
         self.current = (self.current + 1) % self.buffers.len();
@@ -152,20 +523,37 @@ impl Backend {
     /// Removes the current buffer.
     /// If it is the only one it also adds a new empty buffer.
     pub fn remove_current_buffer(&mut self) {
-        self.buffers.remove(self.current);
+        let current = self.current;
+        self.remove_buffer(current);
+    }
+    /// Removes the buffer at `index`.
+    /// If it is the only one it also adds a new empty buffer.
+    pub fn remove_buffer(&mut self, index: usize) {
+        self.buffers.remove(index);
         if self.buffers.is_empty() {
-            self.buffers.push(Buffer::new());
+            self.add_buffer(0, Buffer::new());
+        }
+        // Keep the same buffer current if we removed one before it,
+        // and make sure we don't point past the end.
+        if index < self.current {
+            self.current -= 1;
+        }
+        if self.current >= self.buffers.len() {
+            self.current = self.buffers.len() - 1;
         }
     }
     /// Opens a new, empty buffer
     pub fn new_empty_buffer(&mut self) {
-        self.buffers.insert(self.current + 1, Buffer::new());
+        let index = self.current + 1;
+        self.add_buffer(index, Buffer::new());
         self.switch_to_next_buffer();
     }
     /// Opens a new buffer from a filename
-    pub fn new_buffer_from_filename(&mut self, name: String) {
-        self.buffers.insert(self.current + 1, Buffer::from_file(name).unwrap());
+    pub fn new_buffer_from_filename(&mut self, name: String) -> io::Result<()> {
+        let index = self.current + 1;
+        self.add_buffer(index, Buffer::from_file(name)?);
         self.switch_to_next_buffer();
+        Ok(())
     }
     /// Returns a reference to the current buffer's Cursor.
     pub fn cursor(&self) -> &Cursor {
@@ -179,25 +567,240 @@ impl Backend {
     // TODO: Make these function not need to clone the contents
     // of the text editor.
 
-    /// Moves the cursor up
+    /// Moves every cursor up
     pub fn move_up(&mut self) {
-        let lines = self.current_lines().clone();
-        self.cursor_mut().move_up(&lines)
+        self.at_every_cursor(|backend| {
+            let lines = backend.current_lines().clone();
+            let tab_width = backend.current_buffer().settings.tab_width;
+            backend.cursor_mut().move_up(&lines, tab_width)
+        });
     }
-    /// Moves the cursor down
+    /// Moves every cursor down
     pub fn move_down(&mut self) {
-        let lines = self.current_lines().clone();
-        self.cursor_mut().move_down(&lines)
+        self.at_every_cursor(|backend| {
+            let lines = backend.current_lines().clone();
+            let tab_width = backend.current_buffer().settings.tab_width;
+            backend.cursor_mut().move_down(&lines, tab_width)
+        });
     }
-    /// Moves the cursor left
+    /// Moves every cursor left
     pub fn move_left(&mut self) {
-        let lines = self.current_lines().clone();
-        self.cursor_mut().move_left(&lines)
+        self.at_every_cursor(|backend| {
+            let lines = backend.current_lines().clone();
+            backend.cursor_mut().move_left(&lines)
+        });
     }
-    /// Moves the cursor right
+    /// Moves every cursor right
     pub fn move_right(&mut self) {
-        let lines = self.current_lines().clone();
-        self.cursor_mut().move_right(&lines)
+        self.at_every_cursor(|backend| {
+            let lines = backend.current_lines().clone();
+            backend.cursor_mut().move_right(&lines)
+        });
+    }
+    /// Moves every cursor to the start of its line
+    pub fn move_line_start(&mut self) {
+        self.at_every_cursor(|backend| backend.cursor_mut().set_column(0));
+    }
+    /// Moves every cursor to the end of its line
+    pub fn move_line_end(&mut self) {
+        self.at_every_cursor(|backend| {
+            let line = backend.cursor().line;
+            let len = backend.length_of_line(line);
+            backend.cursor_mut().set_column(len);
+        });
+    }
+    /// Moves every cursor to the first character on its line that isn't whitespace,
+    /// or to the start of the line if it is already there.
+    pub fn move_smart_line_start(&mut self) {
+        self.at_every_cursor(|backend| {
+            let lines = backend.current_lines().clone();
+            backend.cursor_mut().move_smart_line_start(&lines)
+        });
+    }
+    /// Moves every cursor forward past the end of the next word.
+    pub fn move_word_forward(&mut self) {
+        self.at_every_cursor(|backend| {
+            let lines = backend.current_lines().clone();
+            backend.cursor_mut().move_word_forward(&lines)
+        });
+    }
+    /// Moves every cursor back to the start of the previous word.
+    pub fn move_word_backward(&mut self) {
+        self.at_every_cursor(|backend| {
+            let lines = backend.current_lines().clone();
+            backend.cursor_mut().move_word_backward(&lines)
+        });
+    }
+    /// Moves every cursor `count` lines up, or down if `down` is true.
+    pub fn move_lines(&mut self, count: usize, down: bool) {
+        self.at_every_cursor(|backend| {
+            let lines = backend.current_lines().clone();
+            let tab_width = backend.current_buffer().settings.tab_width;
+            backend.cursor_mut().move_lines(&lines, count, down, tab_width)
+        });
+    }
+    /// Moves every cursor to the blank line after the next paragraph.
+    pub fn move_paragraph_forward(&mut self) {
+        self.at_every_cursor(|backend| {
+            let lines = backend.current_lines().clone();
+            backend.cursor_mut().move_paragraph_forward(&lines)
+        });
+    }
+    /// Moves every cursor to the blank line before the previous paragraph.
+    pub fn move_paragraph_backward(&mut self) {
+        self.at_every_cursor(|backend| {
+            let lines = backend.current_lines().clone();
+            backend.cursor_mut().move_paragraph_backward(&lines)
+        });
+    }
+    /// Moves the cursor to the start of the buffer. The other cursors end up there too,
+    /// so they are merged into it.
+    pub fn move_to_start(&mut self) {
+        self.at_every_cursor(|backend| backend.cursor_mut().move_to_start());
+    }
+    /// Moves the cursor to the end of the buffer.
+    pub fn move_to_end(&mut self) {
+        self.at_every_cursor(|backend| {
+            let lines = backend.current_lines().clone();
+            backend.cursor_mut().move_to_end(&lines)
+        });
+    }
+    /// Moves the cursor to the first character that isn't whitespace on `line`.
+    pub fn go_to_line(&mut self, line: usize) {
+        let column = self.current_lines()[line].chars().take_while(|c| c.is_whitespace()).count();
+        *self.cursor_mut() = Cursor::new(line, column);
+    }
+    /// Deletes the character under every cursor.
+    pub fn delete_forward(&mut self) {
+        self.at_every_cursor(Backend::delete_forward_at_cursor);
+    }
+    /// Deletes the character under the cursor, or joins the next line onto
+    /// this one if the cursor is at the end of the line.
+    fn delete_forward_at_cursor(&mut self) {
+        let Cursor { line, column, .. } = *self.cursor();
+        if column < self.length_of_line(line) {
+            self.current_buffer_mut().delete_char_at(line, column);
+        } else if line + 1 < self.number_of_lines() {
+            self.current_buffer_mut().join_lines_at(line + 1);
+        }
+    }
+    /// Deletes from the cursor to the end of the line and returns what was deleted.
+    /// At the end of a line it deletes the newline instead, like Emacs' kill-line.
+    pub fn kill_line(&mut self) -> String {
+        let Cursor { line, column, .. } = *self.cursor();
+        let len = self.length_of_line(line);
+        if column < len {
+            let (start, end) = (Cursor::new(line, column), Cursor::new(line, len));
+            let text = self.current_buffer().text_between(start, end);
+            self.current_buffer_mut().delete_between(start, end);
+            text
+        } else if line + 1 < self.number_of_lines() {
+            self.current_buffer_mut().join_lines_at(line + 1);
+            String::from("\n")
+        } else {
+            String::new()
+        }
+    }
+    /// Inserts `text` at the cursor and moves the cursor to the end of it.
+    /// Returns where the text starts and ends.
+    pub fn insert_text(&mut self, text: &str) -> (Cursor, Cursor) {
+        let start = *self.cursor();
+        let end = self.current_buffer_mut().insert_text_at(start, text);
+        *self.cursor_mut() = end;
+        (start, end)
+    }
+    /// Starts selecting a block, from the cursor to wherever it moves.
+    pub fn start_block(&mut self) {
+        let buffer = self.current_buffer_mut();
+        buffer.block_anchor = Some(buffer.cursor);
+    }
+    /// Stops selecting a block. Returns false if there wasn't one.
+    pub fn end_block(&mut self) -> bool {
+        self.current_buffer_mut().block_anchor.take().is_some()
+    }
+    /// Returns the block that is selected in the current buffer.
+    pub fn block(&self) -> Option<Block> {
+        self.current_buffer().block()
+    }
+    /// Returns the text of each line of the selected block, with tabs
+    /// turned into spaces and short lines padded so that it stays a block.
+    pub fn copy_block(&self) -> Option<Vec<String>> {
+        let buffer = self.current_buffer();
+        buffer.block().map(|block| buffer.block_text(block))
+    }
+    /// Deletes the selected block and returns what was in it.
+    /// The cursor goes to the block's top left corner.
+    pub fn delete_block(&mut self) -> Option<Vec<String>> {
+        let block = self.block()?;
+        let buffer = self.current_buffer_mut();
+        let text = buffer.block_text(block);
+        let tab_width = buffer.settings.tab_width;
+        for line in block.first..block.last + 1 {
+            let start = columns::column_at_visual(&buffer.lines[line], block.left, tab_width);
+            let end = columns::column_at_visual(&buffer.lines[line], block.right, tab_width);
+            buffer.delete_between(Cursor::new(line, start), Cursor::new(line, end));
+        }
+        buffer.block_anchor = None;
+        let column = columns::column_at_visual(&buffer.lines[block.first], block.left, tab_width);
+        buffer.cursor = Cursor::new(block.first, column);
+        Some(text)
+    }
+    /// Puts a cursor at the left edge of the selected block on every line of it,
+    /// so that typing goes into every line. Lines that are too short are padded
+    /// with spaces. Returns false if there is no block.
+    pub fn insert_at_block(&mut self) -> bool {
+        let block = match self.block() {
+            Some(block) => block,
+            None => return false,
+        };
+        let buffer = self.current_buffer_mut();
+        let cursors: Vec<Cursor> = (block.first..block.last + 1)
+            .map(|line| Cursor::new(line, buffer.pad_to(line, block.left)))
+            .collect();
+        buffer.block_anchor = None;
+        buffer.cursor = cursors[cursors.len() - 1];
+        buffer.cursors = cursors;
+        buffer.merge_cursors();
+        true
+    }
+    /// Pastes `rows` as a block with its top left corner at the cursor.
+    /// Lines that are too short are padded with spaces, and lines are added
+    /// at the end of the buffer if the block doesn't fit.
+    pub fn paste_block(&mut self, rows: &[String]) {
+        let cursor = *self.cursor();
+        let buffer = self.current_buffer_mut();
+        let tab_width = buffer.settings.tab_width;
+        let left = columns::visual_column(&buffer.lines[cursor.line], cursor.column, tab_width);
+        for (i, row) in rows.iter().enumerate() {
+            let line = cursor.line + i;
+            if line == buffer.lines.len() {
+                buffer.push_newline(String::new());
+            }
+            let column = buffer.pad_to(line, left);
+            buffer.insert_text_at(Cursor::new(line, column), row);
+        }
+    }
+    /// Sets the mark called `name` in the current buffer to where the cursor is.
+    pub fn set_mark(&mut self, name: char) {
+        let cursor = Cursor::new(self.cursor().line, self.cursor().column);
+        self.current_buffer_mut().marks.insert(name, cursor);
+    }
+    /// Returns where the mark called `name` is in the current buffer.
+    pub fn mark(&self, name: char) -> Option<Cursor> {
+        self.current_buffer().marks.get(&name).cloned()
+    }
+    /// Returns the marks in the current buffer.
+    pub fn marks(&self) -> &BTreeMap<char, Cursor> {
+        &self.current_buffer().marks
+    }
+    /// Replaces the marks in the current buffer, like when they are loaded from an
+    /// earlier session. Marks past the end of the text are moved back into it.
+    pub fn set_marks(&mut self, marks: BTreeMap<char, Cursor>) {
+        let buffer = self.current_buffer_mut();
+        buffer.marks = marks;
+        for mark in buffer.marks.values_mut() {
+            mark.clamp(&buffer.lines);
+        }
     }
     /// Is the current buffer dirty (modified)?
     pub fn is_dirty(&self) -> bool {
@@ -209,6 +812,8 @@ impl Backend {
 /// It is also responsible for opening and saving files.
 #[derive(Debug)]
 pub struct Buffer {
+    /// A number that identifies the Buffer for as long as it is open.
+    id: usize,
     /// The filename that the Buffer gets saved to.
     pub filename: Option<String>,
     /// The lines of text.
@@ -218,57 +823,399 @@ pub struct Buffer {
     pub dirty: bool,
     /// The cursor position in the buffer.
     cursor: Cursor,
+    /// Any other cursors. Typing and deleting happen at these as well as at `cursor`,
+    /// and they move along with the edits made to the text.
+    cursors: Vec<Cursor>,
+    /// Where a block selection started, if one is being selected.
+    block_anchor: Option<Cursor>,
+    /// The named marks, which move along with the text they were set on.
+    marks: BTreeMap<char, Cursor>,
+    /// The changes made to the text that nobody has looked at yet.
+    changes: Vec<Change>,
+    /// The settings for the Buffer, which depend on what kind of file it is.
+    settings: Settings,
+    /// The EditorConfig properties for the file, which override the user's settings.
+    editorconfig: Properties,
+    /// The settings changed by hand for just this buffer, like with `:set`,
+    /// which override everything else and are kept when the config is reloaded.
+    overrides: Table,
+    /// Whether the file ended with a line ending when it was opened.
+    final_newline: bool,
+    /// The edits that can be undone and redone.
+    history: UndoHistory,
+    /// Where auto pairs put closing brackets and quotes, which typing
+    /// the same character steps over instead of adding another.
+    auto_closed: Vec<Cursor>,
 }
 impl Buffer {
     /// Constructs a new, empty buffer that doesn't have a filename to save to.
     pub fn new() -> Buffer {
         Buffer {
+            id: 0,
             filename: None,
             lines: vec![String::new()],
             dirty: false,
             cursor: Cursor::new(0, 0),
+            cursors: Vec::new(),
+            block_anchor: None,
+            marks: BTreeMap::new(),
+            changes: Vec::new(),
+            settings: Settings::new(),
+            editorconfig: Properties::new(),
+            overrides: Table::new(),
+            final_newline: false,
+            history: UndoHistory::new(),
+            auto_closed: Vec::new(),
         }
     }
     /// Contructs a new buffer from the contents of a file.
+    /// The file is read using the character set and line endings from
+    /// the project's EditorConfig files, if it has any.
+    /// A file that doesn't exist yet gives an empty buffer that will create it when saved.
     pub fn from_file(filename: String) -> io::Result<Buffer> {
-        let mut file = File::open(&filename)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-        let mut lines = Vec::new();
-        for line in contents.lines() {
-            lines.push(String::from(line))
+        let properties = editorconfig::properties(Path::new(&filename));
+        let mut settings = Settings::new();
+        editorconfig::apply(&properties, &mut settings);
+        let mut bytes = Vec::new();
+        match File::open(&filename) {
+            Ok(mut file) => {
+                file.read_to_end(&mut bytes)?;
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+        let contents = decode(bytes, settings.charset)?;
+        let mut lines: Vec<String> = if settings.line_ending == LineEnding::Cr {
+            contents.split_terminator('\r').map(String::from).collect()
+        } else {
+            contents.lines().map(String::from).collect()
+        };
+        let final_newline = contents.ends_with(['\n', '\r']);
+        if lines.is_empty() {
+            // Even an empty file has a line for the cursor to be on.
+            lines.push(String::new());
         }
         Ok(Buffer {
+            id: 0,
             filename: Some(filename),
             lines: lines,
             dirty: false,
-            cursor: Cursor::new(0, 0)
+            cursor: Cursor::new(0, 0),
+            cursors: Vec::new(),
+            block_anchor: None,
+            marks: BTreeMap::new(),
+            changes: Vec::new(),
+            settings: settings,
+            editorconfig: properties,
+            overrides: Table::new(),
+            final_newline: final_newline,
+            history: UndoHistory::new(),
+            auto_closed: Vec::new(),
         })
     }
+    /// Returns the number that identifies the buffer.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+    /// Returns the lines of text in the buffer.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+    /// Returns a short name for the buffer: the last part of its filename.
+    pub fn short_name(&self) -> String {
+        match self.filename {
+            Some(ref name) => Path::new(name).file_name()
+                .map_or(name.clone(), |n| n.to_string_lossy().into_owned()),
+            None => String::from("**no filename**"),
+        }
+    }
+    /// Returns the cursor position in the buffer.
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+    /// Returns the settings for the buffer.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+    /// Changes the settings in `table` for just this buffer, and keeps them
+    /// changed when the config is reloaded. Nothing is changed if any of
+    /// them are wrong, and a description of each problem is returned.
+    pub fn override_settings(&mut self, table: Table) -> Vec<String> {
+        let mut settings = self.settings.clone();
+        let mut errors = Vec::new();
+        settings.apply(&table, "", &mut errors);
+        if errors.is_empty() {
+            self.settings = settings;
+            self.overrides.extend(table);
+        }
+        errors
+    }
+    /// Works out the buffer's settings from the user's `config`
+    /// and the project's EditorConfig.
+    fn update_settings(&mut self, config: &Config) {
+        self.settings = config.settings_for(&self.filename);
+        editorconfig::apply(&self.editorconfig, &mut self.settings);
+        // The overrides were checked when they were set, so they can't go wrong here.
+        self.settings.apply(&self.overrides, "", &mut Vec::new());
+    }
+    /// Returns the indentation for a new line made by splitting `line` at `column`.
+    /// That is the same indentation as `line`, plus another level if the
+    /// line was split just after an opening bracket.
+    fn indent_after(&self, line: usize, column: usize) -> String {
+        let text = &self.lines[line];
+        let mut indent: String = text.chars().take_while(|&c| c == ' ' || c == '\t').collect();
+        if text[..byte_index(text, column)].trim_end().ends_with(['{', '(', '[']) {
+            if self.settings.expand_tabs {
+                indent.push_str(&" ".repeat(self.settings.indent_size));
+            } else {
+                indent.push('\t');
+            }
+        }
+        indent
+    }
     /// Saves the contents of the buffer to the file
     pub fn save(&mut self) -> io::Result<()> {
+        if self.settings.trim_trailing_whitespace {
+            self.trim_trailing_whitespace();
+        }
         if let Some(ref filename) = self.filename {
-            let mut file = File::create(filename)?;
-            for line in &self.lines {
-                writeln!(&mut file, "{}", line)?;
-            }
+            self.write_to(filename)?;
         } else {
-            return Err(Error::new(ErrorKind::Other, "No file to write to"));
+            return Err(Error::other("No file to write to"));
         }
         self.dirty = false;
+        self.history.mark_saved();
         Ok(())
     }
+    /// Writes the contents of the buffer to `filename`.
+    /// Unlike `save` this doesn't touch the filename or dirty state.
+    /// The buffer's settings decide the line endings and character set.
+    pub fn write_to(&self, filename: &str) -> io::Result<()> {
+        let ending = self.settings.line_ending.as_str();
+        let mut text = String::new();
+        for (i, line) in self.lines.iter().enumerate() {
+            if self.settings.trim_trailing_whitespace {
+                text.push_str(line.trim_end());
+            } else {
+                text.push_str(line);
+            }
+            // Without insert_final_newline, the file keeps the ending it had.
+            if i + 1 < self.lines.len() || self.settings.insert_final_newline || self.final_newline {
+                text.push_str(ending);
+            }
+        }
+        let bytes = encode(&text, self.settings.charset)?;
+        let mut file = File::create(filename)?;
+        file.write_all(&bytes)
+    }
+    /// Replaces the first `old_len` characters of `line` with `indent`,
+    /// keeping the cursor on the same text.
+    fn replace_indent(&mut self, line: usize, old_len: usize, indent: &str) {
+        for _ in 0..old_len {
+            self.delete_char_at(line, 0);
+        }
+        for (column, c) in indent.chars().enumerate() {
+            self.insert_char_at(c, line, column);
+        }
+        if self.cursor.line == line {
+            let new_len = line_len(indent);
+            self.cursor.column = if self.cursor.column < old_len {
+                self.cursor.column.min(new_len)
+            } else {
+                self.cursor.column - old_len + new_len
+            };
+        }
+    }
+    /// Removes the whitespace from the end of every line.
+    fn trim_trailing_whitespace(&mut self) {
+        for line in 0..self.lines.len() {
+            while self.lines[line].chars().next_back().is_some_and(|c| c.is_whitespace()) {
+                let column = line_len(&self.lines[line]) - 1;
+                self.delete_char_at(line, column);
+            }
+        }
+        let lines = self.lines.clone();
+        self.cursor.clamp(&lines);
+    }
+    /// Ends the group of edits that undo undoes together, so that the next edit starts
+    /// a new one. The editor does this between commands.
+    pub fn end_undo_group(&mut self) {
+        self.history.end_group();
+    }
+    /// Undoes the last group of edits. Returns false if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let group = match self.history.undo() {
+            Some(group) => group,
+            None => return false,
+        };
+        for edit in group.edits.into_iter().rev() {
+            self.apply(edit.inverse());
+        }
+        self.finish_undo(group.cursor);
+        true
+    }
+    /// Does the last group of edits that was undone again.
+    /// Returns false if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let group = match self.history.redo() {
+            Some(group) => group,
+            None => return false,
+        };
+        for edit in group.edits {
+            self.apply(edit);
+        }
+        self.finish_undo(group.cursor);
+        true
+    }
+    /// Puts the cursor back where it was before the edits that were undone or redone.
+    fn finish_undo(&mut self, cursor: Cursor) {
+        // The edits were already in the history, so they aren't recorded again.
+        self.history.discard();
+        self.cursor = cursor;
+        self.cursor.clamp(&self.lines);
+        self.dirty = !self.history.is_saved();
+    }
+    /// Makes `edit` to the text.
+    fn apply(&mut self, edit: Edit) {
+        match edit {
+            Edit::InsertChar { line, column, c } => self.insert_char_at(c, line, column),
+            Edit::DeleteChar { line, column, .. } => self.delete_char_at(line, column),
+            Edit::SplitLine { line, column } => self.split_line_into_two_at(line, column),
+            Edit::JoinLine { line, .. } => self.join_lines_at(line),
+            Edit::InsertLine { line, text } => self.insert_newline_at(line, text),
+            Edit::DeleteLine { line, .. } => self.delete_line_at(line),
+            Edit::ReplaceLine { line, new, .. } => self.replace_line_at(line, new),
+        }
+    }
+    /// Remembers a change to the text so that it can be undone,
+    /// and moves the other cursors to keep up with it.
+    fn record(&mut self, change: Change, edit: Edit) {
+        self.history.record(edit, self.cursor);
+        // A closer that was deleted isn't there to step over any more.
+        self.auto_closed.retain(|closer| match change {
+            Change::DeleteChar { line, column } => closer.line != line || closer.column != column,
+            Change::DeleteLine { line } | Change::ReplaceLine { line, .. } => closer.line != line,
+            _ => true,
+        });
+        let marks = self.marks.values_mut();
+        let closers = self.auto_closed.iter_mut();
+        for cursor in self.cursors.iter_mut().chain(self.block_anchor.as_mut()).chain(marks).chain(closers) {
+            cursor.adjust(&change);
+        }
+        // Editing the text forgets the columns that the cursors were aiming for.
+        self.cursor.desired = None;
+        for cursor in &mut self.cursors {
+            cursor.desired = None;
+        }
+        self.changes.push(change);
+    }
+    /// Returns the cursors other than the main one.
+    pub fn cursors(&self) -> &[Cursor] {
+        &self.cursors
+    }
+    /// Returns the block between the block anchor and the cursor, if a block is being selected.
+    /// The block includes the visual columns of both of them, so that a block
+    /// that starts and ends in the same column has something in it.
+    pub fn block(&self) -> Option<Block> {
+        let anchor = self.block_anchor?;
+        let tab_width = self.settings.tab_width;
+        // A cursor that went through short lines on its way down still marks its
+        // desired column, so that a block can reach past the end of short lines.
+        let visual = |at: Cursor| at.desired
+            .unwrap_or_else(|| columns::visual_column(&self.lines[at.line], at.column, tab_width));
+        let (a, b) = (visual(anchor), visual(self.cursor));
+        Some(Block {
+            first: cmp::min(anchor.line, self.cursor.line),
+            last: cmp::max(anchor.line, self.cursor.line),
+            left: cmp::min(a, b),
+            right: cmp::max(a, b) + 1,
+        })
+    }
+    /// Returns the text of each line of `block`, padded with spaces to the block's width.
+    /// Tabs and characters that stick out of the block become spaces.
+    fn block_text(&self, block: Block) -> Vec<String> {
+        let tab_width = self.settings.tab_width;
+        self.lines[block.first..block.last + 1].iter().map(|line| {
+            let mut text = String::new();
+            let mut at = 0;
+            for c in line.chars() {
+                let width = columns::char_width(c, at, tab_width);
+                let (start, end) = (at, at + width);
+                at = end;
+                if end <= block.left {
+                    continue;
+                }
+                if start >= block.right {
+                    break;
+                }
+                if c == '\t' || start < block.left || end > block.right {
+                    text.push_str(&" ".repeat(cmp::min(end, block.right) - cmp::max(start, block.left)));
+                } else {
+                    text.push(c);
+                }
+            }
+            let width = columns::visual_width(&text, tab_width);
+            text.push_str(&" ".repeat(block.right - block.left - width));
+            text
+        }).collect()
+    }
+    /// Pads `line` with spaces so that it reaches visual column `visual`,
+    /// and returns the column of the character at `visual`.
+    fn pad_to(&mut self, line: usize, visual: usize) -> usize {
+        let tab_width = self.settings.tab_width;
+        let width = columns::visual_width(&self.lines[line], tab_width);
+        for _ in width..visual {
+            let end = line_len(&self.lines[line]);
+            self.insert_char_at(' ', line, end);
+        }
+        columns::column_at_visual(&self.lines[line], visual, tab_width)
+    }
+    /// Removes any cursors that ended up in the same place as another one.
+    fn merge_cursors(&mut self) {
+        let mut merged: Vec<Cursor> = Vec::with_capacity(self.cursors.len());
+        for cursor in self.cursors.drain(..) {
+            if cursor != self.cursor && !merged.contains(&cursor) {
+                merged.push(cursor);
+            }
+        }
+        self.cursors = merged;
+    }
     /// Inserts a newline
     pub fn insert_newline_at(&mut self, index: usize, content: String) {
         if index == self.lines.len() {
             self.push_newline(content);
         } else {
-            self.lines.insert(index, content);
+            self.lines.insert(index, content.clone());
+            self.record(Change::InsertLine { line: index }, Edit::InsertLine { line: index, text: content });
+            self.dirty = true;
         }
     }
     /// Adds a new line at the end of the text
     pub fn push_newline(&mut self, content: String) {
-        self.lines.push(content);
+        self.lines.push(content.clone());
+        let line = self.lines.len() - 1;
+        self.record(Change::InsertLine { line: line }, Edit::InsertLine { line: line, text: content });
+        self.dirty = true;
+    }
+    /// Deletes the line at `line`, which can't be the only one.
+    pub fn delete_line_at(&mut self, line: usize) {
+        assert!(self.lines.len() > 1, "Tried to delete the only line!");
+        if line + 1 == self.lines.len() {
+            // There is no line to take its place, so what points into it
+            // goes to the end of the line before it instead.
+            self.replace_line_at(line, String::new());
+            self.join_lines_at(line);
+            return;
+        }
+        let text = self.lines.remove(line);
+        self.record(Change::DeleteLine { line: line }, Edit::DeleteLine { line: line, text: text });
+        self.dirty = true;
+    }
+    /// Replaces the text of the line at `line` with `text`.
+    pub fn replace_line_at(&mut self, line: usize, text: String) {
+        let len = line_len(&text);
+        let old = mem::replace(&mut self.lines[line], text.clone());
+        self.record(Change::ReplaceLine { line: line, len: len }, Edit::ReplaceLine { line: line, old: old, new: text });
         self.dirty = true;
     }
     /// Returns a line of text as a String
@@ -279,30 +1226,469 @@ impl Buffer {
     pub fn split_line_into_two_at(&mut self, line: usize, column: usize) {
         let (start, rest) = self.split_line_at(line, column);
         self.lines[line] = start;
-        self.insert_newline_at(line + 1, rest);
+        self.lines.insert(line + 1, rest);
+        self.record(Change::SplitLine { line: line, column: column }, Edit::SplitLine { line: line, column: column });
         self.dirty = true;
     }
     /// Splits a line into two Strings
     pub fn split_line_at(&self, line: usize, column: usize) -> (String, String) {
-        let (a, b) = self.lines[line].split_at(column);
+        let (a, b) = self.lines[line].split_at(byte_index(&self.lines[line], column));
         (String::from(a), String::from(b))
     }
     /// Inserts a char at a specific line, column
     pub fn insert_char_at(&mut self, c: char, line: usize, column: usize) {
-        // TODO fix for unicode
-        self.lines[line].insert(column, c);
+        let index = byte_index(&self.lines[line], column);
+        self.lines[line].insert(index, c);
+        self.record(Change::InsertChar { line: line, column: column },
+                    Edit::InsertChar { line: line, column: column, c: c });
         self.dirty = true;
     }
+    /// Inserts a closing bracket or quote that auto pairs added, which is
+    /// stepped over if the same character is typed in front of it.
+    fn insert_auto_closed(&mut self, c: char, line: usize, column: usize) {
+        self.insert_char_at(c, line, column);
+        self.auto_closed.push(Cursor::new(line, column));
+    }
+    /// Was the character at `line`, `column` put there by auto pairs?
+    fn is_auto_closed(&self, line: usize, column: usize) -> bool {
+        self.auto_closed.iter().any(|closer| closer.line == line && closer.column == column)
+    }
+    /// Forgets that auto pairs put the character at `line`, `column` there,
+    /// once it has been stepped over. Returns false if they didn't.
+    fn take_auto_closed(&mut self, line: usize, column: usize) -> bool {
+        let before = self.auto_closed.len();
+        self.auto_closed.retain(|closer| closer.line != line || closer.column != column);
+        self.auto_closed.len() < before
+    }
     /// Moves the line at `line` into the line before it and removes it.
     pub fn join_lines_at(&mut self, line: usize) {
         assert!(line > 0, "Tried to move first line to the -1 line!");
         let s = self.lines.remove(line);
+        let column = line_len(&self.lines[line - 1]);
         self.lines[line - 1].push_str(&s);
+        self.record(Change::JoinLine { line: line, column: column }, Edit::JoinLine { line: line, column: column });
         self.dirty = true;
     }
     /// Deletes the character at `line`, `column`.
     pub fn delete_char_at(&mut self, line: usize, column: usize) {
-        self.lines[line].remove(column);
+        let index = byte_index(&self.lines[line], column);
+        let c = self.lines[line].remove(index);
+        self.record(Change::DeleteChar { line: line, column: column },
+                    Edit::DeleteChar { line: line, column: column, c: c });
         self.dirty = true;
     }
+    /// Returns the text from `start` up to (but not including) `end`,
+    /// with a newline between each line.
+    pub fn text_between(&self, start: Cursor, end: Cursor) -> String {
+        if start.line == end.line {
+            return self.lines[start.line].chars()
+                .skip(start.column)
+                .take(end.column.saturating_sub(start.column))
+                .collect();
+        }
+        let mut text: String = self.lines[start.line].chars().skip(start.column).collect();
+        for line in &self.lines[start.line + 1..end.line] {
+            text.push('\n');
+            text.push_str(line);
+        }
+        text.push('\n');
+        text.extend(self.lines[end.line].chars().take(end.column));
+        text
+    }
+    /// Deletes the whole lines from `first` to `last`, and returns the line
+    /// that the cursor should end up on.
+    pub fn delete_lines(&mut self, first: usize, last: usize) -> usize {
+        let at_end = last + 1 == self.lines.len();
+        if first == 0 && at_end {
+            // There is always a line, so the last one is emptied instead.
+            for _ in 0..last {
+                self.delete_line_at(0);
+            }
+            self.replace_line_at(0, String::new());
+            return 0;
+        }
+        for _ in first..last + 1 {
+            self.delete_line_at(first);
+        }
+        // With no line after them, the cursor goes up to the line before them.
+        if at_end { first - 1 } else { first }
+    }
+    /// Puts a copy of the lines from `first` to `last` just after them.
+    pub fn duplicate_lines(&mut self, first: usize, last: usize) {
+        let copy = self.lines[first..last + 1].to_vec();
+        for (i, line) in copy.into_iter().enumerate() {
+            self.insert_newline_at(last + 1 + i, line);
+        }
+    }
+    /// Moves the lines from `first` to `last` up above the line before them, or
+    /// down below the line after them. Returns false if there is no line to go past.
+    pub fn move_lines(&mut self, first: usize, last: usize, up: bool) -> bool {
+        // The line they go past is moved to the other side of them, so that
+        // anything pointing into the moved lines goes along with them.
+        if up && first > 0 {
+            let above = self.lines[first - 1].clone();
+            self.delete_lines(first - 1, first - 1);
+            self.insert_newline_at(last, above);
+            true
+        } else if !up && last + 1 < self.lines.len() {
+            let below = self.lines[last + 1].clone();
+            self.delete_lines(last + 1, last + 1);
+            self.insert_newline_at(first, below);
+            true
+        } else {
+            false
+        }
+    }
+    /// Joins the `count` lines after `line` onto it, like vi's `J`. The indentation
+    /// of each joined line is dropped and a space goes in its place, unless the line
+    /// is empty or already ends in whitespace, or the joined line starts with `)`.
+    /// Returns the column of the last join, or None if there was no line to join.
+    pub fn join_lines(&mut self, line: usize, count: usize) -> Option<usize> {
+        let mut joined = None;
+        for _ in 0..count {
+            if line + 1 >= self.lines.len() {
+                break;
+            }
+            let next = self.lines[line + 1].clone();
+            let rest = next.trim_start();
+            let indent = line_len(&next) - line_len(rest);
+            self.delete_between(Cursor::new(line + 1, 0), Cursor::new(line + 1, indent));
+            let trimmed = self.lines[line].ends_with(char::is_whitespace);
+            let column = line_len(&self.lines[line]);
+            self.join_lines_at(line + 1);
+            if !rest.is_empty() && !trimmed && !rest.starts_with(')') && column > 0 {
+                self.insert_char_at(' ', line, column);
+            }
+            joined = Some(column);
+        }
+        joined
+    }
+    /// Sorts the lines from `first` to `last`, and returns how many lines
+    /// were dropped for being the same as another.
+    pub fn sort_lines(&mut self, first: usize, last: usize, sort: Sort) -> usize {
+        let mut lines = self.lines[first..last + 1].to_vec();
+        if sort.numeric {
+            // The sort is stable, so lines with the same number stay in the same order.
+            lines.sort_by(|a, b| {
+                first_number(a).partial_cmp(&first_number(b)).unwrap_or(Ordering::Equal)
+            });
+        } else {
+            lines.sort();
+        }
+        if sort.reverse {
+            lines.reverse();
+        }
+        if sort.unique {
+            // Lines that sort the same aren't always next to each other, like with
+            // numbers, so this keeps the first of each line wherever it is.
+            let mut seen = HashSet::new();
+            lines.retain(|line| seen.insert(line.clone()));
+        }
+        let dropped = last + 1 - first - lines.len();
+        self.replace_lines(first, last, lines);
+        dropped
+    }
+    /// Puts the lines from `first` to `last` in the opposite order.
+    pub fn reverse_lines(&mut self, first: usize, last: usize) {
+        let lines = self.lines[first..last + 1].iter().rev().cloned().collect();
+        self.replace_lines(first, last, lines);
+    }
+    /// Replaces the lines from `first` to `last` with `lines`, which can't be any longer.
+    /// Lines that are the same as before are left alone, so that marks on them stay put.
+    fn replace_lines(&mut self, first: usize, last: usize, lines: Vec<String>) {
+        let count = lines.len();
+        for (i, text) in lines.into_iter().enumerate() {
+            if self.lines[first + i] != text {
+                self.replace_line_at(first + i, text);
+            }
+        }
+        if first + count <= last {
+            self.delete_lines(first + count, last);
+        }
+    }
+    /// Indents the lines from `first` to `last` by one level. Empty lines are left empty.
+    pub fn indent_lines(&mut self, first: usize, last: usize) {
+        let indent = if self.settings.expand_tabs {
+            " ".repeat(self.settings.indent_size)
+        } else {
+            String::from("\t")
+        };
+        for line in first..last + 1 {
+            if !self.lines[line].is_empty() {
+                self.insert_text_at(Cursor::new(line, 0), &indent);
+            }
+        }
+    }
+    /// Takes up to one level of indentation off the lines from `first` to `last`.
+    pub fn outdent_lines(&mut self, first: usize, last: usize) {
+        let size = self.settings.indent_size;
+        for line in first..last + 1 {
+            let mut width = 0;
+            let mut count = 0;
+            for c in self.lines[line].chars() {
+                match c {
+                    '\t' => width = size,
+                    ' ' => width += 1,
+                    _ => break,
+                }
+                count += 1;
+                if width >= size {
+                    break;
+                }
+            }
+            self.delete_between(Cursor::new(line, 0), Cursor::new(line, count));
+        }
+    }
+    /// Deletes the text from `start` up to (but not including) `end`.
+    pub fn delete_between(&mut self, start: Cursor, end: Cursor) {
+        if start.line == end.line {
+            for _ in start.column..end.column {
+                self.delete_char_at(start.line, start.column);
+            }
+            return;
+        }
+        // Cut the first line short, empty the lines in the middle and the start
+        // of the last line, and then join them all onto the first line.
+        for _ in start.column..line_len(&self.lines[start.line]) {
+            self.delete_char_at(start.line, start.column);
+        }
+        for line in start.line + 1..end.line + 1 {
+            let count = if line == end.line { end.column } else { line_len(&self.lines[start.line + 1]) };
+            for _ in 0..count {
+                self.delete_char_at(start.line + 1, 0);
+            }
+            self.join_lines_at(start.line + 1);
+        }
+    }
+    /// Inserts `text` at `at`, and returns the position just after it.
+    pub fn insert_text_at(&mut self, at: Cursor, text: &str) -> Cursor {
+        let mut at = at;
+        for c in text.chars() {
+            if c == '\n' {
+                self.split_line_into_two_at(at.line, at.column);
+                at = Cursor::new(at.line + 1, 0);
+            } else {
+                self.insert_char_at(c, at.line, at.column);
+                at.column += 1;
+            }
+        }
+        at
+    }
+}
+
+/// Returns the first number in `line`, for sorting lines by their numbers.
+fn first_number(line: &str) -> Option<f64> {
+    let digits = |text: &str| text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let start = line.find(|c: char| c.is_ascii_digit())?;
+    let mut end = start + digits(&line[start..]);
+    if line[end..].starts_with('.') && line[end + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+        end += 1 + digits(&line[end + 1..]);
+    }
+    let start = if line[..start].ends_with('-') { start - 1 } else { start };
+    line[start..end].parse().ok()
+}
+
+/// Turns the bytes of a file into text using `charset`.
+fn decode(bytes: Vec<u8>, charset: Charset) -> io::Result<String> {
+    let invalid = || Error::new(io::ErrorKind::InvalidData, "the file isn't valid text");
+    match charset {
+        Charset::Utf8 | Charset::Utf8Bom => {
+            let mut text = String::from_utf8(bytes).map_err(|_| invalid())?;
+            if text.starts_with('\u{feff}') {
+                text.remove(0);
+            }
+            Ok(text)
+        },
+        Charset::Latin1 => Ok(bytes.iter().map(|&b| b as char).collect()),
+        Charset::Utf16Be | Charset::Utf16Le => {
+            let units: Vec<u16> = bytes.chunks(2).map(|pair| {
+                let pair = [pair[0], *pair.get(1).unwrap_or(&0)];
+                if charset == Charset::Utf16Be { u16::from_be_bytes(pair) } else { u16::from_le_bytes(pair) }
+            }).collect();
+            let text = String::from_utf16(&units).map_err(|_| invalid())?;
+            Ok(String::from(text.trim_start_matches('\u{feff}')))
+        },
+    }
+}
+
+/// Turns text into the bytes to save in a file using `charset`.
+fn encode(text: &str, charset: Charset) -> io::Result<Vec<u8>> {
+    match charset {
+        Charset::Utf8 => Ok(text.as_bytes().to_vec()),
+        Charset::Utf8Bom => {
+            let mut bytes = "\u{feff}".as_bytes().to_vec();
+            bytes.extend_from_slice(text.as_bytes());
+            Ok(bytes)
+        },
+        Charset::Latin1 => text.chars().map(|c| {
+            if (c as u32) < 256 {
+                Ok(c as u8)
+            } else {
+                Err(Error::new(io::ErrorKind::InvalidData,
+                               format!("{} can't be saved as latin1", c)))
+            }
+        }).collect(),
+        Charset::Utf16Be => Ok(text.encode_utf16().flat_map(|u| u.to_be_bytes()).collect()),
+        Charset::Utf16Le => Ok(text.encode_utf16().flat_map(|u| u.to_le_bytes()).collect()),
+    }
+}
+
+/// Is `c` part of a word, for moving by words?
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.insert_text_at(Cursor::new(0, 0), text);
+        buffer
+    }
+
+    fn sorted(text: &str, sort: Sort) -> (Vec<String>, usize) {
+        let mut buffer = buffer(text);
+        let last = buffer.lines().len() - 1;
+        let dropped = buffer.sort_lines(0, last, sort);
+        (buffer.lines().to_vec(), dropped)
+    }
+
+    #[test]
+    fn first_numbers() {
+        assert_eq!(first_number("abc"), None);
+        assert_eq!(first_number("x 12 y 3"), Some(12.0));
+        assert_eq!(first_number("v1.5."), Some(1.5));
+        assert_eq!(first_number("1."), Some(1.0));
+        assert_eq!(first_number("a -4"), Some(-4.0));
+        assert_eq!(first_number("a-b 2"), Some(2.0));
+    }
+
+    #[test]
+    fn sorts_by_text() {
+        let (lines, dropped) = sorted("b\na\nc\na", Sort::default());
+        assert_eq!(lines, ["a", "a", "b", "c"]);
+        assert_eq!(dropped, 0);
+    }
+
+    #[test]
+    fn sorts_by_number() {
+        let numeric = Sort { numeric: true, ..Sort::default() };
+        let (lines, _) = sorted("x 10\nnone\ny 9\nz -1\nw 9", numeric);
+        assert_eq!(lines, ["none", "z -1", "y 9", "w 9", "x 10"]);
+    }
+
+    #[test]
+    fn sorts_in_reverse() {
+        let reverse = Sort { reverse: true, ..Sort::default() };
+        let (lines, _) = sorted("b\na\nc", reverse);
+        assert_eq!(lines, ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn unique_drops_lines_that_are_apart() {
+        let unique = Sort { numeric: true, unique: true, ..Sort::default() };
+        let (lines, dropped) = sorted("x 1\ny 1\nx 1", unique);
+        assert_eq!(lines, ["x 1", "y 1"]);
+        assert_eq!(dropped, 1);
+        let unique = Sort { unique: true, ..Sort::default() };
+        let (lines, dropped) = sorted("b\na\nb\na", unique);
+        assert_eq!(lines, ["a", "b"]);
+        assert_eq!(dropped, 2);
+    }
+
+    /// Does `edit` to a buffer with `text` in it, and checks that undoing gets
+    /// `text` back in one go and that redoing gets what `edit` made.
+    fn undoes(text: &str, edit: fn(&mut Buffer)) -> Vec<String> {
+        let mut buffer = buffer(text);
+        buffer.end_undo_group();
+        edit(&mut buffer);
+        let edited = buffer.lines().to_vec();
+        assert!(buffer.undo());
+        assert_eq!(buffer.lines().join("\n"), text);
+        assert!(buffer.redo());
+        assert_eq!(buffer.lines(), &edited[..]);
+        edited
+    }
+
+    #[test]
+    fn line_operations_undo_at_once() {
+        assert_eq!(undoes("b\na\nb", |b| { b.sort_lines(0, 2, Sort { unique: true, ..Sort::default() }); }),
+                   ["a", "b"]);
+        assert_eq!(undoes("a\nb\nc", |b| b.reverse_lines(0, 2)), ["c", "b", "a"]);
+        assert_eq!(undoes("a\nb\nc", |b| b.duplicate_lines(0, 1)), ["a", "b", "a", "b", "c"]);
+        assert_eq!(undoes("a\nb\nc", |b| { b.move_lines(1, 1, true); }), ["b", "a", "c"]);
+        assert_eq!(undoes("a\n  b\nc", |b| { b.join_lines(0, 2); }), ["a b c"]);
+        assert_eq!(undoes("a\n\nb", |b| b.indent_lines(0, 2)), ["\ta", "", "\tb"]);
+        assert_eq!(undoes("\ta\n    b", |b| b.outdent_lines(0, 1)), ["a", "b"]);
+    }
+
+    #[test]
+    fn deletes_whole_lines() {
+        assert_eq!(undoes("a\nb\nc", |b| assert_eq!(b.delete_lines(0, 1), 0)), ["c"]);
+        assert_eq!(undoes("a\nb\nc", |b| assert_eq!(b.delete_lines(1, 2), 0)), ["a"]);
+        assert_eq!(undoes("a\nb\nc", |b| assert_eq!(b.delete_lines(0, 2), 0)), [""]);
+        assert_eq!(undoes("a\nb\nc", |b| assert_eq!(b.delete_lines(2, 2), 1)), ["a", "b"]);
+    }
+
+    #[test]
+    fn sorting_replaces_whole_lines() {
+        let mut buffer = buffer("c xxxxxxxx\nb\na yyyyyyyy\nb");
+        buffer.changes.clear();
+        buffer.sort_lines(0, 3, Sort { unique: true, ..Sort::default() });
+        assert_eq!(buffer.lines(), ["a yyyyyyyy", "b", "c xxxxxxxx"]);
+        // Two lines change, and the last one goes into the end of the line before it.
+        assert_eq!(buffer.changes.len(), 4);
+    }
+
+    #[test]
+    fn undo_and_redo_know_when_the_text_is_saved() {
+        // Like a file that was just opened.
+        let mut buffer = buffer("a");
+        buffer.dirty = false;
+        buffer.history = UndoHistory::new();
+        buffer.insert_char_at('b', 0, 1);
+        assert!(buffer.dirty);
+        assert!(buffer.undo());
+        assert!(!buffer.dirty);
+        assert_eq!(buffer.cursor, Cursor::new(0, 0));
+        assert!(!buffer.undo());
+        assert!(buffer.redo());
+        assert!(buffer.dirty);
+        assert!(!buffer.redo());
+    }
+
+    #[test]
+    fn sorts_only_the_range() {
+        let mut buffer = buffer("z\nc\nb\na\n0");
+        buffer.sort_lines(1, 3, Sort::default());
+        assert_eq!(buffer.lines(), ["z", "a", "b", "c", "0"]);
+    }
+
+    #[test]
+    fn auto_pairs_only_step_over_what_they_added() {
+        let mut backend = Backend::new(Vec::new(), Config::new()).unwrap();
+        backend.current_buffer_mut().settings.auto_pairs = true;
+        for c in "f((x".chars() {
+            backend.insert_char(c);
+        }
+        assert_eq!(backend.current_lines()[0], "f((x))");
+        backend.insert_char(')');
+        backend.insert_char(')');
+        assert_eq!(backend.current_lines()[0], "f((x))");
+        assert_eq!(backend.cursor().column, 6);
+        // The `)` typed by hand is not stepped over, or deleted with its `(`.
+        backend.cursor_mut().set_column(1);
+        backend.insert_char(')');
+        assert_eq!(backend.current_lines()[0], "f)((x))");
+        backend.cursor_mut().set_column(1);
+        backend.insert_char('(');
+        backend.insert_char('(');
+        assert_eq!(backend.current_lines()[0], "f(()))((x))");
+        backend.insert_backspace();
+        assert_eq!(backend.current_lines()[0], "f())((x))");
+        backend.insert_backspace();
+        assert_eq!(backend.current_lines()[0], "f)((x))");
+    }
 }
@@ -0,0 +1,133 @@
+use std::cmp;
+use cursor::Cursor;
+
+// Brackets in strings and comments are matched like any others,
+// because we don't know where the strings and comments are.
+
+/// The pairs of brackets, which are matched and closed automatically.
+pub static BRACKETS: &'static [(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+/// The quotes, which are closed automatically.
+pub static QUOTES: &'static [char] = &['"', '\'', '`'];
+
+/// The bracket at the cursor, and what it matches.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bracket {
+    /// The bracket at `at` is matched by the one at `partner`.
+    Matched { at: Cursor, partner: Cursor },
+    /// The bracket at the cursor doesn't have a partner.
+    Unmatched(Cursor),
+}
+
+/// Is `c` an opening or closing bracket?
+pub fn is_bracket(c: char) -> bool {
+    BRACKETS.iter().any(|&(open, close)| c == open || c == close)
+}
+
+/// Is `c` something that gets closed automatically, or steps over itself when it is typed?
+pub fn is_closing(c: char) -> bool {
+    BRACKETS.iter().any(|&(_, close)| c == close) || QUOTES.contains(&c)
+}
+
+/// Finds the bracket under `cursor`, or just before it if there isn't one under it,
+/// and the bracket that matches it, looking at most `limit` lines away for that.
+/// Returns None if there is no bracket there, or if the search gave up.
+pub fn find(lines: &[String], cursor: Cursor, limit: usize) -> Option<Bracket> {
+    let chars: Vec<char> = lines[cursor.line].chars().collect();
+    let column = if chars.get(cursor.column).is_some_and(|&c| is_bracket(c)) {
+        cursor.column
+    } else if cursor.column > 0 && chars.get(cursor.column - 1).is_some_and(|&c| is_bracket(c)) {
+        cursor.column - 1
+    } else {
+        return None;
+    };
+    let at = Cursor::new(cursor.line, column);
+    match search(lines, at, limit)? {
+        Some(partner) => Some(Bracket::Matched { at: at, partner: partner }),
+        None => Some(Bracket::Unmatched(at)),
+    }
+}
+
+/// Returns where the bracket that matches the one at `at` is.
+/// Returns None if there isn't a bracket at `at` or nothing matches it.
+pub fn partner(lines: &[String], at: Cursor) -> Option<Cursor> {
+    search(lines, at, usize::MAX)?
+}
+
+/// Looks for the bracket that matches the one at `at`, at most `limit` lines away.
+/// Returns Some(None) if nothing matches it, and None if there isn't a bracket
+/// at `at` or the search gave up.
+fn search(lines: &[String], at: Cursor, limit: usize) -> Option<Option<Cursor>> {
+    let c = lines[at.line].chars().nth(at.column)?;
+    let &(open, close) = BRACKETS.iter().find(|&&(open, close)| c == open || c == close)?;
+    let forward = c == open;
+    let last = if forward {
+        cmp::min(at.line.saturating_add(limit), lines.len() - 1)
+    } else {
+        at.line.saturating_sub(limit)
+    };
+    let mut depth = 0;
+    let mut line = at.line;
+    loop {
+        let text = &lines[line];
+        // The first line is only looked at from the bracket onwards.
+        let column = if forward {
+            let skip = if line == at.line { at.column } else { 0 };
+            scan(text.chars().enumerate().skip(skip), open, &mut depth)
+        } else {
+            let len = text.chars().count();
+            let skip = if line == at.line { len - at.column - 1 } else { 0 };
+            scan(text.chars().rev().enumerate().map(|(i, c)| (len - i - 1, c)).skip(skip), close, &mut depth)
+        };
+        if let Some(column) = column {
+            return Some(Some(Cursor::new(line, column)));
+        }
+        if line == last {
+            let edge = if forward { lines.len() - 1 } else { 0 };
+            return if line == edge { Some(None) } else { None };
+        }
+        if forward {
+            line += 1;
+        } else {
+            line -= 1;
+        }
+    }
+}
+
+/// Goes through `chars`, counting the brackets that `same` starts and the other kind ends,
+/// and returns the column where `depth` gets back to 0.
+fn scan<I: Iterator<Item = (usize, char)>>(chars: I, same: char, depth: &mut usize) -> Option<usize> {
+    let &(open, close) = BRACKETS.iter().find(|&&(open, close)| same == open || same == close)?;
+    for (column, c) in chars {
+        if c == same {
+            *depth += 1;
+        } else if c == open || c == close {
+            *depth -= 1;
+            if *depth == 0 {
+                return Some(column);
+            }
+        }
+    }
+    None
+}
+
+/// Returns the character that should be put after `c` when it is typed between
+/// `before` and `after`, if any. Brackets are only closed when they aren't typed
+/// in front of a word, and quotes aren't closed after a word either, so that
+/// typing `don't` doesn't add another `'`.
+pub fn auto_close(c: char, before: Option<char>, after: Option<char>) -> Option<char> {
+    let open_space = after.is_none_or(|c| c.is_whitespace() || is_closing(c));
+    if let Some(&(_, close)) = BRACKETS.iter().find(|&&(open, _)| open == c) {
+        return if open_space { Some(close) } else { None };
+    }
+    if QUOTES.contains(&c) && open_space && !before.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+        return Some(c);
+    }
+    None
+}
+
+/// Returns true if `before` and `after` are an empty pair that `auto_close` would make,
+/// which backspace deletes together.
+pub fn is_empty_pair(before: char, after: char) -> bool {
+    BRACKETS.contains(&(before, after)) || (QUOTES.contains(&before) && before == after)
+}
@@ -0,0 +1,80 @@
+use unicode_width::UnicodeWidthChar;
+
+// Columns in the buffer count characters, but on the screen a tab stretches
+// to the next tab stop and some characters (like CJK ones) take up two cells.
+// These functions convert between the two, and the screen ones are called
+// visual columns.
+
+/// Returns the number of characters in `line`.
+pub fn line_len(line: &str) -> usize {
+    line.chars().count()
+}
+
+/// Returns the byte index in `line` of the character at `column`.
+pub fn byte_index(line: &str, column: usize) -> usize {
+    line.char_indices().nth(column).map_or(line.len(), |(i, _)| i)
+}
+
+/// Returns how many cells `c` takes up when it starts at visual column `visual`.
+pub fn char_width(c: char, visual: usize, tab_width: usize) -> usize {
+    if c == '\t' {
+        tab_width - visual % tab_width
+    } else {
+        // Control characters are drawn as a single `?`.
+        c.width().unwrap_or(1)
+    }
+}
+
+/// Returns the visual column of the character at `column` in `line`.
+pub fn visual_column(line: &str, column: usize, tab_width: usize) -> usize {
+    line.chars().take(column).fold(0, |visual, c| visual + char_width(c, visual, tab_width))
+}
+
+/// Returns the width of the whole of `text` on the screen.
+pub fn visual_width(text: &str, tab_width: usize) -> usize {
+    visual_column(text, usize::MAX, tab_width)
+}
+
+/// Returns the column of the character that is drawn at visual column `visual`,
+/// or the end of the line if it doesn't reach that far.
+pub fn column_at_visual(line: &str, visual: usize, tab_width: usize) -> usize {
+    let mut at = 0;
+    for (column, c) in line.chars().enumerate() {
+        let width = char_width(c, at, tab_width);
+        if at + width > visual {
+            return column;
+        }
+        at += width;
+    }
+    line_len(line)
+}
+
+/// Returns the part of `line` that is visible from visual column `left`
+/// in a space `width` cells wide, with tabs turned into spaces.
+/// Characters that are cut off by either edge are drawn as spaces.
+pub fn render(line: &str, left: usize, width: usize, tab_width: usize) -> String {
+    let mut text = String::new();
+    let mut at = 0;
+    let right = left + width;
+    for c in line.chars() {
+        if at >= right {
+            break;
+        }
+        let w = char_width(c, at, tab_width);
+        let start = at;
+        at += w;
+        if at <= left {
+            continue;
+        }
+        if c == '\t' || start < left || at > right {
+            // Only draw the cells that are inside the space.
+            let cells = at.min(right) - start.max(left);
+            text.extend(::std::iter::repeat_n(' ', cells));
+        } else if c.is_control() {
+            text.push('?');
+        } else {
+            text.push(c);
+        }
+    }
+    text
+}
@@ -0,0 +1,119 @@
+/// Declares the Command enum along with the name and description of each command.
+macro_rules! commands {
+    ($($command:ident => $name:expr, $description:expr;)*) => {
+        /// The built in commands that can be bound to keys.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum Command {
+            $($command,)*
+        }
+
+        /// Every built in command, in the order they are listed to the user.
+        pub static ALL_COMMANDS: &'static [Command] = &[$(Command::$command,)*];
+
+        impl Command {
+            /// Returns the name used to refer to the command in config files.
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $(Command::$command => $name,)*
+                }
+            }
+            /// Returns a short description of what the command does.
+            pub fn description(&self) -> &'static str {
+                match *self {
+                    $(Command::$command => $description,)*
+                }
+            }
+        }
+    }
+}
+
+commands! {
+    Quit => "quit", "Quit trusty";
+    MoveUp => "move-up", "Move the cursor up a line";
+    MoveDown => "move-down", "Move the cursor down a line";
+    MoveLeft => "move-left", "Move the cursor left a character";
+    MoveRight => "move-right", "Move the cursor right a character";
+    MoveLineStart => "move-line-start", "Move the cursor to the start of the line";
+    MoveLineEnd => "move-line-end", "Move the cursor to the end of the line";
+    MoveWordForward => "move-word-forward", "Move the cursor to the end of the next word";
+    MoveWordBackward => "move-word-backward", "Move the cursor to the start of the previous word";
+    MoveSmartLineStart => "move-smart-line-start", "Move the cursor to the first non-blank character, or the start of the line";
+    MovePageUp => "move-page-up", "Move the cursor up a screenful of lines";
+    MovePageDown => "move-page-down", "Move the cursor down a screenful of lines";
+    MoveParagraphForward => "move-paragraph-forward", "Move the cursor to the blank line after the paragraph";
+    MoveParagraphBackward => "move-paragraph-backward", "Move the cursor to the blank line before the paragraph";
+    MoveBufferStart => "move-buffer-start", "Move the cursor to the start of the buffer";
+    MoveBufferEnd => "move-buffer-end", "Move the cursor to the end of the buffer";
+    GoToLine => "go-to-line", "Move the cursor to the line with the number you type";
+    SetMark => "set-mark", "Set a named mark where the cursor is";
+    JumpToMark => "jump-to-mark", "Move the cursor to a named mark";
+    JumpToMatchingBracket => "jump-to-matching-bracket", "Move the cursor to the bracket that matches the one at the cursor";
+    JumpBack => "jump-back", "Go back to where the cursor was before the last jump";
+    JumpForward => "jump-forward", "Go forward again to where jump-back came from";
+    InsertNewline => "insert-newline", "Split the line at the cursor";
+    DeleteBackward => "delete-backward", "Delete the character before the cursor";
+    DeleteForward => "delete-forward", "Delete the character under the cursor";
+    KillLine => "kill-line", "Cut the rest of the line into the kill ring";
+    Yank => "yank", "Paste the last text that was killed";
+    YankPop => "yank-pop", "Replace the text just yanked with the kill before it";
+    DeleteLine => "delete-line", "Cut the selected lines, or the cursor's line, into the kill ring";
+    DuplicateLines => "duplicate-lines", "Put a copy of the selection, or the cursor's line, after it";
+    MoveLinesUp => "move-lines-up", "Move the selected lines, or the cursor's line, up past the line above";
+    MoveLinesDown => "move-lines-down", "Move the selected lines, or the cursor's line, down past the line below";
+    JoinLines => "join-lines", "Join the selected lines into one, or the next line onto the cursor's line";
+    SortLines => "sort-lines", "Sort the selected lines, or the whole buffer";
+    ReverseLines => "reverse-lines", "Reverse the order of the selected lines, or the whole buffer";
+    IndentLines => "indent-lines", "Indent the selected lines, or the cursor's line, by a level";
+    OutdentLines => "outdent-lines", "Take a level of indentation off the selected lines, or the cursor's line";
+    Undo => "undo", "Undo the last change";
+    Redo => "redo", "Do the last change that was undone again";
+    Cancel => "cancel", "Stop typing a key sequence";
+    CommandLine => "command-line", "Type a command like :w, :42 or :s/old/new/g";
+    CommandPalette => "command-palette", "Search for a command and run it";
+    RecordMacro => "record-macro", "Start recording keys into a macro, or stop if one is recording";
+    PlayMacro => "play-macro", "Play a macro: `name`, `name 5` for five times or `name *` until it fails";
+    AddCursorAbove => "add-cursor-above", "Add a cursor on the line above the top cursor";
+    AddCursorBelow => "add-cursor-below", "Add a cursor on the line below the bottom cursor";
+    AddCursorAtNextMatch => "add-cursor-at-next-match", "Add a cursor where the word under the cursor appears next";
+    SplitSelectionIntoCursors => "split-selection-into-cursors", "Put a cursor on each line of the selection";
+    ClearCursors => "clear-cursors", "Go back to just one cursor";
+    BlockSelect => "block-select", "Start selecting a block of columns, or stop if one is selected";
+    BlockInsert => "block-insert", "Type at the left edge of the block on every line of it";
+    BlockDelete => "block-delete", "Delete the selected block";
+    BlockCopy => "block-copy", "Copy the selected block";
+    BlockPaste => "block-paste", "Paste the copied block with its top left corner at the cursor";
+    InsertTab => "insert-tab", "Insert a tab, or spaces if the buffer indents with spaces";
+    IndentWithSpaces => "indent-with-spaces", "Convert the buffer's indentation to spaces";
+    IndentWithTabs => "indent-with-tabs", "Convert the buffer's indentation to tabs";
+    Save => "save", "Save the current buffer";
+    SaveAs => "save-as", "Save the current buffer under a new name";
+    SaveCopy => "save-copy", "Save a copy of the current buffer somewhere else";
+    SaveAll => "save-all", "Save every buffer with unsaved changes";
+    NewBuffer => "new-buffer", "Open a new, empty buffer";
+    Open => "open", "Open a file by name";
+    FindFile => "find-file", "Fuzzy search for a file to open";
+    CloseBuffer => "close-buffer", "Close the current buffer";
+    NextBuffer => "next-buffer", "Switch to the next buffer";
+    PreviousBuffer => "previous-buffer", "Switch to the previous buffer";
+    BufferList => "buffer-list", "Pick a buffer from a list of open buffers";
+    MoveBufferLeft => "move-buffer-left", "Move the current buffer's tab to the left";
+    MoveBufferRight => "move-buffer-right", "Move the current buffer's tab to the right";
+    SplitHorizontal => "split-horizontal", "Split the pane into one above the other";
+    SplitVertical => "split-vertical", "Split the pane into one beside the other";
+    ClosePane => "close-pane", "Close the pane with focus";
+    CloseOtherPanes => "close-other-panes", "Close every pane except the one with focus";
+    NextPane => "next-pane", "Move focus to the next pane";
+    FocusLeft => "focus-left", "Move focus to the pane on the left";
+    FocusDown => "focus-down", "Move focus to the pane below";
+    FocusUp => "focus-up", "Move focus to the pane above";
+    FocusRight => "focus-right", "Move focus to the pane on the right";
+    GrowPane => "grow-pane", "Make the pane with focus bigger";
+    ShrinkPane => "shrink-pane", "Make the pane with focus smaller";
+}
+
+impl Command {
+    /// Finds the command with the given name.
+    pub fn from_name(name: &str) -> Option<Command> {
+        ALL_COMMANDS.iter().find(|c| c.name() == name).cloned()
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs;
+use paths;
+
+/// The result of completing a path.
+#[derive(Debug, PartialEq)]
+pub struct Completion {
+    /// The input with as much as possible filled in.
+    pub text: String,
+    /// The names that could come next if there was more than one.
+    pub candidates: Vec<String>,
+}
+
+/// Completes the path that the user has typed so far.
+/// Everything up to the last `/` is the directory to look in, and the rest
+/// is the start of the name to complete. Directories get a `/` added so that
+/// you can keep completing inside them.
+pub fn complete_path(input: &str) -> Completion {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(i) => input.split_at(i + 1),
+        None => ("", input),
+    };
+    let lookup = if dir.is_empty() { String::from(".") } else { paths::expand_tilde(dir) };
+    let mut candidates = Vec::new();
+    if let Ok(entries) = fs::read_dir(&lookup) {
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Only show hidden files if the user asked for them.
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                continue;
+            }
+            let is_dir = entry.path().is_dir();
+            candidates.push(if is_dir { name + "/" } else { name });
+        }
+    }
+    candidates.sort();
+    let text = match candidates.len() {
+        0 => String::from(input),
+        1 => format!("{}{}", dir, candidates[0]),
+        _ => format!("{}{}", dir, common_prefix(&candidates)),
+    };
+    if candidates.len() == 1 {
+        candidates.clear();
+    }
+    Completion {
+        text: text,
+        candidates: candidates,
+    }
+}
+
+/// Completes `input` as the start of one of `names`.
+pub fn complete_name(input: &str, names: &[&str]) -> Completion {
+    let mut candidates: Vec<String> = names.iter()
+        .filter(|name| name.starts_with(input))
+        .map(|&name| String::from(name))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    let text = match candidates.len() {
+        0 => String::from(input),
+        _ => common_prefix(&candidates),
+    };
+    if candidates.len() == 1 {
+        candidates.clear();
+    }
+    Completion {
+        text: text,
+        candidates: candidates,
+    }
+}
+
+/// Returns the longest string that all of the `names` start with.
+fn common_prefix(names: &[String]) -> String {
+    let mut prefix = names[0].clone();
+    for name in &names[1..] {
+        let len = prefix.chars()
+            .zip(name.chars())
+            .take_while(|&(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        prefix.truncate(len);
+    }
+    prefix
+}
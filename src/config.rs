@@ -0,0 +1,461 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use termion::color;
+use toml::value::{Table, Value};
+use paths;
+
+/// The name of the file that overrides the user's config for one project.
+/// trusty looks for it in the working directory and each of its parents.
+pub const PROJECT_CONFIG: &'static str = ".trusty.toml";
+
+/// The keymaps that the `keymap` setting can pick.
+static KEYMAPS: &'static [&'static str] = &["default", "vi", "emacs"];
+
+/// The ways that lines can end in a file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    /// Returns the characters that end a line.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+/// The character sets that files can be read and saved in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Charset {
+    Utf8,
+    /// UTF-8 with a byte order mark at the start.
+    Utf8Bom,
+    Latin1,
+    Utf16Be,
+    Utf16Le,
+}
+
+/// The settings that can be changed for each language.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    /// How many columns a tab takes up.
+    pub tab_width: usize,
+    /// Whether to indent with spaces instead of tabs.
+    pub expand_tabs: bool,
+    /// How many columns one level of indentation takes up.
+    pub indent_size: usize,
+    /// What to end lines with when saving.
+    pub line_ending: LineEnding,
+    /// The character set that the file is read and saved in.
+    pub charset: Charset,
+    /// Whether to remove whitespace from the ends of lines when saving.
+    pub trim_trailing_whitespace: bool,
+    /// Whether the file should end with a line ending.
+    pub insert_final_newline: bool,
+    /// Whether to show line numbers down the left of each pane.
+    pub line_numbers: bool,
+    /// Where to draw a margin, if anywhere. Lines that are longer than
+    /// this many characters run over the margin.
+    pub margin: Option<usize>,
+    /// Whether typing an opening bracket or quote adds the closing one too.
+    pub auto_pairs: bool,
+    /// Whether a new line starts with the indentation of the line it was split from.
+    pub auto_indent: bool,
+}
+
+impl Settings {
+    /// Creates the settings that trusty uses when nothing has been configured.
+    pub fn new() -> Settings {
+        Settings {
+            tab_width: 4,
+            expand_tabs: false,
+            indent_size: 4,
+            line_ending: LineEnding::Lf,
+            charset: Charset::Utf8,
+            trim_trailing_whitespace: false,
+            insert_final_newline: true,
+            line_numbers: true,
+            margin: None,
+            auto_pairs: false,
+            auto_indent: true,
+        }
+    }
+    /// Sets the settings found in `table`. `section` is the name of the table,
+    /// which is used to describe any problems in `errors`. It can be empty for
+    /// settings that didn't come from a file.
+    pub fn apply(&mut self, table: &Table, section: &str, errors: &mut Vec<String>) {
+        let section = if section.is_empty() { String::new() } else { format!("{}.", section) };
+        for (key, value) in table {
+            match key.as_str() {
+                "tab_width" => match value.as_integer() {
+                    Some(width) if (1..=16).contains(&width) => self.tab_width = width as usize,
+                    _ => errors.push(format!("{}tab_width should be a number from 1 to 16", section)),
+                },
+                "indent_size" => match value.as_integer() {
+                    Some(size) if (1..=16).contains(&size) => self.indent_size = size as usize,
+                    _ => errors.push(format!("{}indent_size should be a number from 1 to 16", section)),
+                },
+                "expand_tabs" | "trim_trailing_whitespace" | "insert_final_newline" | "line_numbers" |
+                "auto_pairs" | "auto_indent" => {
+                    let setting = match key.as_str() {
+                        "expand_tabs" => &mut self.expand_tabs,
+                        "trim_trailing_whitespace" => &mut self.trim_trailing_whitespace,
+                        "insert_final_newline" => &mut self.insert_final_newline,
+                        "auto_pairs" => &mut self.auto_pairs,
+                        "auto_indent" => &mut self.auto_indent,
+                        _ => &mut self.line_numbers,
+                    };
+                    match value.as_bool() {
+                        Some(value) => *setting = value,
+                        None => errors.push(format!("{}{} should be true or false", section, key)),
+                    }
+                },
+                "margin" => match value.as_integer() {
+                    // A margin of 0 turns it off.
+                    Some(0) => self.margin = None,
+                    Some(column) if column > 0 => self.margin = Some(column as usize),
+                    _ => errors.push(format!("{}margin should be a column number", section)),
+                },
+                key => errors.push(format!("unknown setting {}{}", section, key)),
+            }
+        }
+    }
+}
+
+/// A color that can be set in the config file.
+/// Colors are written as one of the names of the 16 terminal colors
+/// (like `cyan` or `light-black`), a number from 0 to 255 for the other
+/// colors that most terminals have, or `#rrggbb`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+static COLOR_NAMES: &'static [&'static str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    "light-black", "light-red", "light-green", "light-yellow",
+    "light-blue", "light-magenta", "light-cyan", "light-white",
+];
+
+impl Color {
+    fn parse(value: &Value) -> Option<Color> {
+        if let Some(n) = value.as_integer() {
+            return if (0..256).contains(&n) { Some(Color::Ansi(n as u8)) } else { None };
+        }
+        let text = value.as_str()?;
+        if let Some(hex) = text.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return None;
+            }
+            let part = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            return Some(Color::Rgb(part(0)?, part(2)?, part(4)?));
+        }
+        COLOR_NAMES.iter().position(|&name| name == text).map(|n| Color::Ansi(n as u8))
+    }
+}
+
+impl color::Color for Color {
+    fn write_fg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Color::Ansi(n) => color::AnsiValue(n).write_fg(f),
+            Color::Rgb(r, g, b) => color::Rgb(r, g, b).write_fg(f),
+        }
+    }
+    fn write_bg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Color::Ansi(n) => color::AnsiValue(n).write_bg(f),
+            Color::Rgb(r, g, b) => color::Rgb(r, g, b).write_bg(f),
+        }
+    }
+}
+
+/// The colors that trusty draws with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Colors {
+    /// The line numbers and the `~`s past the end of the buffer.
+    pub line_numbers: Color,
+    /// The background of the title bar of the pane with focus.
+    pub title: Color,
+    /// The background of the title bars of the other panes.
+    pub title_inactive: Color,
+    /// The text in the title bars and the tab bar.
+    pub title_text: Color,
+    /// The background of the tab bar.
+    pub tab_bar: Color,
+    /// The background of the tab of the current buffer.
+    pub tab_current: Color,
+    /// The background of the bottom line, where messages and prompts go.
+    pub status: Color,
+    /// The text on the bottom line.
+    pub status_text: Color,
+    /// The background of the margin column.
+    pub margin: Color,
+    /// The background of the bracket at the cursor and the one that matches it.
+    pub bracket: Color,
+    /// The background of a bracket at the cursor that nothing matches.
+    pub bracket_unmatched: Color,
+}
+
+impl Colors {
+    fn new() -> Colors {
+        Colors {
+            line_numbers: Color::Ansi(6),
+            title: Color::Ansi(7),
+            title_inactive: Color::Ansi(8),
+            title_text: Color::Ansi(0),
+            tab_bar: Color::Ansi(8),
+            tab_current: Color::Ansi(7),
+            status: Color::Ansi(7),
+            status_text: Color::Ansi(0),
+            margin: Color::Ansi(8),
+            bracket: Color::Ansi(4),
+            bracket_unmatched: Color::Ansi(1),
+        }
+    }
+    fn apply(&mut self, table: &Table, errors: &mut Vec<String>) {
+        for (key, value) in table {
+            let color = match key.as_str() {
+                "line_numbers" => &mut self.line_numbers,
+                "title" => &mut self.title,
+                "title_inactive" => &mut self.title_inactive,
+                "title_text" => &mut self.title_text,
+                "tab_bar" => &mut self.tab_bar,
+                "tab_current" => &mut self.tab_current,
+                "status" => &mut self.status,
+                "status_text" => &mut self.status_text,
+                "margin" => &mut self.margin,
+                "bracket" => &mut self.bracket,
+                "bracket_unmatched" => &mut self.bracket_unmatched,
+                key => {
+                    errors.push(format!("unknown color colors.{}", key));
+                    continue;
+                },
+            };
+            match Color::parse(value) {
+                Some(c) => *color = c,
+                None => errors.push(format!("colors.{} isn't a color: {}", key, value)),
+            }
+        }
+    }
+}
+
+/// Settings that only apply to files of one language.
+#[derive(Clone, Debug)]
+struct Language {
+    name: String,
+    /// The extensions of the language's files, without the dot.
+    extensions: Vec<String>,
+    /// The settings to change, which are checked when the config is loaded.
+    settings: Table,
+}
+
+/// A file that the config was read from, so that we can tell when it changes.
+#[derive(Clone, Debug)]
+struct Source {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+/// The Config holds everything that the user can configure.
+/// It is read from `config.toml` in trusty's config directory, and then
+/// from a `.trusty.toml` in the project, which overrides the user's settings.
+///
+/// The files look like this:
+///
+/// ```toml
+/// keymap = "vi"
+/// line_numbers = true
+/// margin = 80
+/// expand_tabs = true
+///
+/// [colors]
+/// line_numbers = "cyan"
+/// margin = "#303030"
+///
+/// [languages.rust]
+/// extensions = ["rs"]
+/// tab_width = 4
+/// margin = 100
+/// ```
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The settings for files that don't belong to a language.
+    pub settings: Settings,
+    pub colors: Colors,
+    /// Which keys to start with: "default", "vi" for the modal vi keys,
+    /// or "emacs" for Emacs style keys.
+    pub keymap: String,
+    languages: Vec<Language>,
+    /// The files that were read, whether or not they existed.
+    sources: Vec<Source>,
+    /// Problems found in the config files.
+    pub errors: Vec<String>,
+}
+
+impl Config {
+    /// Creates the config that trusty uses when there are no config files.
+    pub fn new() -> Config {
+        Config {
+            settings: Settings::new(),
+            colors: Colors::new(),
+            keymap: String::from("default"),
+            languages: Vec::new(),
+            sources: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+    /// Loads the user's config file and the project's config file.
+    /// Any problems with them end up in `errors`.
+    pub fn load() -> Config {
+        let mut config = Config::new();
+        if let Some(dir) = paths::config_dir() {
+            config.read(&dir.join("config.toml"));
+        }
+        if let Some(path) = find_project_config() {
+            config.read(&path);
+        }
+        config
+    }
+    /// Has any of the files the config was read from changed since then?
+    /// A file that didn't exist counts as changed once it's made, and so does
+    /// a project config that is found now but wasn't when the config was loaded.
+    pub fn changed(&self) -> bool {
+        self.sources.iter().any(|source| modified(&source.path) != source.modified)
+            || find_project_config().as_ref().is_some_and(|path| {
+                !self.sources.iter().any(|source| source.path == *path)
+            })
+    }
+    /// Returns the settings for the file called `filename`.
+    pub fn settings_for(&self, filename: &Option<String>) -> Settings {
+        let mut settings = self.settings.clone();
+        if let Some(language) = filename.as_ref().and_then(|f| self.language_for(f)) {
+            // These were checked when they were loaded, so there aren't any errors.
+            settings.apply(&language.settings, &language.name, &mut Vec::new());
+        }
+        settings
+    }
+    fn language_for(&self, filename: &str) -> Option<&Language> {
+        let extension = Path::new(filename).extension()?.to_str()?;
+        // Later languages override earlier ones, so look from the end.
+        self.languages.iter().rev().find(|language| {
+            language.extensions.iter().any(|e| e == extension)
+        })
+    }
+    /// Reads the config file at `path` on top of this config.
+    fn read(&mut self, path: &Path) {
+        self.sources.push(Source {
+            path: path.to_path_buf(),
+            modified: modified(path),
+        });
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            // Not having a config file is fine.
+            Err(_) => return,
+        };
+        let table = match text.parse::<Value>() {
+            Ok(Value::Table(table)) => table,
+            Ok(_) => return,
+            Err(e) => {
+                self.errors.push(format!("{}: {}", path.display(), e));
+                return;
+            },
+        };
+        let mut errors = Vec::new();
+        let mut settings = Table::new();
+        for (key, value) in table {
+            match (key.as_str(), value) {
+                ("colors", Value::Table(colors)) => self.colors.apply(&colors, &mut errors),
+                ("languages", Value::Table(languages)) => {
+                    for (name, value) in languages {
+                        match value {
+                            Value::Table(table) => self.read_language(name, table, &mut errors),
+                            _ => errors.push(format!("languages.{} should be a table", name)),
+                        }
+                    }
+                },
+                ("colors", _) | ("languages", _) => errors.push(format!("{} should be a table", key)),
+                ("keymap", Value::String(ref name)) if KEYMAPS.contains(&name.as_str()) => {
+                    self.keymap = name.clone();
+                },
+                ("keymap", _) => errors.push(format!("keymap should be one of {}", KEYMAPS.join(", "))),
+                (_, value) => {
+                    settings.insert(key, value);
+                },
+            }
+        }
+        self.settings.apply(&settings, "settings", &mut errors);
+        for error in errors {
+            self.errors.push(format!("{}: {}", path.display(), error));
+        }
+    }
+    /// Adds the language called `name`, or changes it if an earlier file had it.
+    fn read_language(&mut self, name: String, mut table: Table, errors: &mut Vec<String>) {
+        let section = format!("languages.{}", name);
+        let extensions = match table.remove("extensions") {
+            Some(Value::Array(values)) => {
+                let extensions: Option<Vec<String>> = values.iter()
+                    .map(|v| v.as_str().map(|e| String::from(e.trim_start_matches('.'))))
+                    .collect();
+                if extensions.is_none() {
+                    errors.push(format!("{}.extensions should be a list of strings", section));
+                }
+                extensions
+            },
+            Some(_) => {
+                errors.push(format!("{}.extensions should be a list of strings", section));
+                None
+            },
+            None => None,
+        };
+        // Check the settings now, and leave out any that are wrong.
+        let mut checked = Table::new();
+        for (key, value) in table {
+            let mut single = Table::new();
+            single.insert(key.clone(), value.clone());
+            let before = errors.len();
+            Settings::new().apply(&single, &section, errors);
+            if errors.len() == before {
+                checked.insert(key, value);
+            }
+        }
+        match self.languages.iter_mut().find(|language| language.name == name) {
+            Some(language) => {
+                if let Some(extensions) = extensions {
+                    language.extensions = extensions;
+                }
+                language.settings.extend(checked);
+            },
+            None => {
+                if extensions.is_none() {
+                    errors.push(format!("{} needs a list of extensions", section));
+                }
+                self.languages.push(Language {
+                    name: name,
+                    extensions: extensions.unwrap_or_default(),
+                    settings: checked,
+                });
+            },
+        }
+    }
+}
+
+/// Looks for the project config file in the working directory and its parents.
+fn find_project_config() -> Option<PathBuf> {
+    let dir = env::current_dir().ok()?;
+    dir.ancestors().map(|d| d.join(PROJECT_CONFIG)).find(|path| path.is_file())
+}
+
+/// Returns when the file at `path` was last changed, if it exists.
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
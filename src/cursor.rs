@@ -1,8 +1,24 @@
-#[derive(Clone, Copy, Debug, PartialEq)]
+use std::cmp;
+use unicode_segmentation::UnicodeSegmentation;
+use backend::Change;
+use columns::{self, line_len};
+
+#[derive(Clone, Copy, Debug)]
 /// The Cursor holds the line and column of the users cursor.
 pub struct Cursor {
     pub line: usize,
     pub column: usize,
+    /// The visual column that moving up and down aims for. The first move up or down
+    /// sets it, so that going through a short line doesn't lose the column we came from,
+    /// and moving sideways or editing the text forgets it.
+    pub desired: Option<usize>,
+}
+
+// Two cursors are in the same place even if they are aiming for different columns.
+impl PartialEq for Cursor {
+    fn eq(&self, other: &Cursor) -> bool {
+        self.line == other.line && self.column == other.column
+    }
 }
 
 impl Cursor {
@@ -10,27 +26,36 @@ impl Cursor {
         Cursor {
             line: line,
             column: column,
+            desired: None,
         }
     }
-    pub fn move_up(&mut self, lines: &[String]) {
+    pub fn move_up(&mut self, lines: &[String], tab_width: usize) {
         if self.line != 0 {
-            self.line -= 1;
-            let len = lines[self.line].len();
-            if self.column > len {
-                self.column = len;
-            }
+            let line = self.line - 1;
+            self.move_to_line(lines, line, tab_width);
         }
     }
-    pub fn move_down(&mut self, lines: &[String]) {
+    pub fn move_down(&mut self, lines: &[String], tab_width: usize) {
         if self.line + 1 != lines.len() {
-            self.line += 1;
-            let len = lines[self.line].len();
-            if self.column > len {
-                self.column = len;
-            }
+            let line = self.line + 1;
+            self.move_to_line(lines, line, tab_width);
         }
     }
+    /// Moves the cursor to `line`, as close to the desired column as that line reaches.
+    fn move_to_line(&mut self, lines: &[String], line: usize, tab_width: usize) {
+        let desired = self.desired
+            .unwrap_or_else(|| columns::visual_column(&lines[self.line], self.column, tab_width));
+        self.line = line;
+        self.column = columns::column_at_visual(&lines[line], desired, tab_width);
+        self.desired = Some(desired);
+    }
+    /// Moves the cursor to `column` on the same line.
+    pub fn set_column(&mut self, column: usize) {
+        self.column = column;
+        self.desired = None;
+    }
     pub fn move_left(&mut self, lines: &[String]) {
+        self.desired = None;
         // If we are at the top left corner...
         if self.column == 0 && self.line == 0 {
             // return without doing anything
@@ -40,20 +65,21 @@ impl Cursor {
         if self.column == 0 {
             // Move to the end of the next line up
             self.line -= 1;
-            self.column = lines[self.line].len();
+            self.column = line_len(&lines[self.line]);
         } else {
             self.column -= 1;
         }
     }
     pub fn move_right(&mut self, lines: &[String]) {
-        let line_len = lines[self.line].len();
+        self.desired = None;
+        let len = line_len(&lines[self.line]);
         // If we are at the bottom right corner...
-        if self.column == line_len && self.line + 1 == lines.len() {
+        if self.column == len && self.line + 1 == lines.len() {
             // return without doing anything
             return;
         }
         // If we are at the end of the line...
-        if self.column == line_len {
+        if self.column == len {
             // Move to the begining of the next line down
             self.line += 1;
             self.column = 0
@@ -61,4 +87,170 @@ impl Cursor {
             self.column += 1;
         }
     }
+    /// Moves the cursor `count` lines up, or down if `down` is true,
+    /// stopping at the first or last line.
+    pub fn move_lines(&mut self, lines: &[String], count: usize, down: bool, tab_width: usize) {
+        let line = if down {
+            cmp::min(self.line.saturating_add(count), lines.len() - 1)
+        } else {
+            self.line.saturating_sub(count)
+        };
+        if line != self.line {
+            self.move_to_line(lines, line, tab_width);
+        }
+    }
+    /// Moves the cursor to the first character on the line that isn't whitespace,
+    /// or to the start of the line if it is already there.
+    pub fn move_smart_line_start(&mut self, lines: &[String]) {
+        let first = lines[self.line].chars().take_while(|c| c.is_whitespace()).count();
+        let column = if self.column == first { 0 } else { first };
+        self.set_column(column);
+    }
+    /// Moves the cursor past the end of the next word, going on to later lines if it has to.
+    /// Words are found with the Unicode rules for word boundaries.
+    pub fn move_word_forward(&mut self, lines: &[String]) {
+        self.desired = None;
+        loop {
+            if let Some(&(_, end)) = words(&lines[self.line]).iter().find(|&&(_, end)| end > self.column) {
+                self.column = end;
+                return;
+            }
+            if self.line + 1 == lines.len() {
+                self.column = line_len(&lines[self.line]);
+                return;
+            }
+            self.line += 1;
+            self.column = 0;
+        }
+    }
+    /// Moves the cursor back to the start of the previous word, going back to earlier lines if it has to.
+    pub fn move_word_backward(&mut self, lines: &[String]) {
+        self.desired = None;
+        loop {
+            if let Some(&(start, _)) = words(&lines[self.line]).iter().rev().find(|&&(start, _)| start < self.column) {
+                self.column = start;
+                return;
+            }
+            if self.line == 0 {
+                self.column = 0;
+                return;
+            }
+            self.line -= 1;
+            self.column = line_len(&lines[self.line]);
+        }
+    }
+    /// Moves the cursor to the blank line after the next paragraph,
+    /// or to the end of the buffer if that paragraph is the last one.
+    pub fn move_paragraph_forward(&mut self, lines: &[String]) {
+        let blank = |line: usize| lines[line].trim().is_empty();
+        let mut line = self.line;
+        while line < lines.len() && blank(line) {
+            line += 1;
+        }
+        while line < lines.len() && !blank(line) {
+            line += 1;
+        }
+        if line == lines.len() {
+            self.move_to_end(lines);
+        } else {
+            *self = Cursor::new(line, 0);
+        }
+    }
+    /// Moves the cursor to the blank line before the previous paragraph,
+    /// or to the start of the buffer if that paragraph is the first one.
+    pub fn move_paragraph_backward(&mut self, lines: &[String]) {
+        let blank = |line: usize| lines[line].trim().is_empty();
+        let mut line = self.line;
+        while line > 0 && blank(line) {
+            line -= 1;
+        }
+        while line > 0 && !blank(line) {
+            line -= 1;
+        }
+        *self = Cursor::new(line, 0);
+    }
+    /// Moves the cursor to the start of the buffer.
+    pub fn move_to_start(&mut self) {
+        *self = Cursor::new(0, 0);
+    }
+    /// Moves the cursor to the end of the buffer.
+    pub fn move_to_end(&mut self, lines: &[String]) {
+        let line = lines.len() - 1;
+        *self = Cursor::new(line, line_len(&lines[line]));
+    }
+    /// Moves the cursor back into the text if it is past the end of it.
+    pub fn clamp(&mut self, lines: &[String]) {
+        if self.line >= lines.len() {
+            self.line = lines.len() - 1;
+        }
+        let len = line_len(&lines[self.line]);
+        if self.column > len {
+            self.column = len;
+        }
+    }
+    /// Moves the cursor so that it stays on the same text after a change
+    /// somewhere else in the buffer.
+    pub fn adjust(&mut self, change: &Change) {
+        match *change {
+            Change::InsertChar { line, column } => {
+                if self.line == line && self.column >= column {
+                    self.column += 1;
+                }
+            },
+            Change::DeleteChar { line, column } => {
+                if self.line == line && self.column > column {
+                    self.column -= 1;
+                }
+            },
+            Change::SplitLine { line, column } => {
+                if self.line == line && self.column >= column {
+                    self.line += 1;
+                    self.column -= column;
+                } else if self.line > line {
+                    self.line += 1;
+                }
+            },
+            Change::JoinLine { line, column } => {
+                if self.line == line {
+                    self.line -= 1;
+                    self.column += column;
+                } else if self.line > line {
+                    self.line -= 1;
+                }
+            },
+            Change::InsertLine { line } => {
+                if self.line >= line {
+                    self.line += 1;
+                }
+            },
+            Change::DeleteLine { line } => {
+                if self.line == line {
+                    self.column = 0;
+                } else if self.line > line {
+                    self.line -= 1;
+                }
+            },
+            Change::ReplaceLine { line, len } => {
+                if self.line == line {
+                    self.column = cmp::min(self.column, len);
+                }
+            },
+        }
+    }
+}
+
+/// Returns the start and end columns of the words in `line`.
+/// Anything between Unicode word boundaries that has a letter or a number in it counts as a word,
+/// so `don't` is one word and each CJK character is a word of its own.
+fn words(line: &str) -> Vec<(usize, usize)> {
+    let mut column = 0;
+    let mut words = Vec::new();
+    for part in line.split_word_bounds() {
+        let len = line_len(part);
+        if part.chars().any(char::is_alphanumeric) {
+            words.push((column, column + len));
+        }
+        column += len;
+    }
+    words
 }
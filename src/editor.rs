@@ -1,85 +1,1164 @@
-use frontend::Frontend;
-use backend::Backend;
-use std::io::stdin;
-use termion::input::TermRead;
+use frontend::{self, Frontend, Highlight, HighlightKind};
+use backend::{Backend, Change, Sort};
+use std::cell::RefCell;
+use std::cmp;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
 use termion::event::*;
+use picker::{Picker, PickerItem, Picked};
+use finder;
+use window::{Layout, Split, Direction, Rect};
+use commands::{self, Command};
+use keymap;
+use keymap::{Keymap, Binding};
+use paths;
+use config::Config;
+use columns;
+use vi::{self, Vi};
+use kill_ring::KillRing;
+use cursor::Cursor;
+use ex::{self, Ex};
+use scripting::{self, Scripts, ScriptCommand};
+use hooks::{self, Hook, Hooks, Handler};
+use macros::Macros;
+use marks::{self, Marks, Jump, JumpList};
+use brackets::{self, Bracket};
 
 static SAVE_PROMPT: &'static str = "Enter the filename to save to";
+static SAVE_AS_PROMPT: &'static str = "Save as";
+static SAVE_COPY_PROMPT: &'static str = "Save a copy to";
 static REMOVE_WHILE_DIRTY: &'static str = "Do you really want to lose all your work?";
+static SORT_PROMPT: &'static str = "Sort lines (n: by number, r: reversed, u: unique)";
+
+/// How often to check whether the config files have changed while the user isn't typing.
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// How many lines past the edge of the screen to look for the bracket that
+/// matches the one at the cursor when drawing.
+const BRACKET_SEARCH_MARGIN: usize = 100;
 
 /// The Editor struct is responsible recieving events
 /// from the user and directing the frontend and backend.
 pub struct Editor<'a> {
     frontend: &'a mut Frontend,
     backend: &'a mut Backend,
+    /// A message to show the user the next time the editor is drawn.
+    message: Option<String>,
+    /// The panes that the screen is split into.
+    layout: Layout,
+    /// Maps the keys that the user types to commands.
+    keymap: Keymap,
+    /// The keys typed so far of a binding that is more than one key long.
+    pending: Vec<Key>,
+    /// Set when the editor should stop.
+    quit: bool,
+    /// The user's configuration.
+    config: Config,
+    /// The vi keys, when the keymap is "vi".
+    vi: Option<Vi>,
+    /// The keymap given on the command line, which wins over the config's.
+    keymap_override: Option<String>,
+    /// The name of the keymap in use.
+    keymap_name: String,
+    /// Text cut by kill-line, ready to be yanked back.
+    kill_ring: KillRing,
+    /// Where the text from the last yank went, so that yank-pop can replace it.
+    yanked: Option<(Cursor, Cursor)>,
+    /// The command that ran last, unless something else has happened since.
+    last_command: Option<Command>,
+    /// Set when the last key typed a character, so that a run of typing is undone at once.
+    typing: bool,
+    /// Set while the save hooks run, so that a hook which saves doesn't fire them again.
+    saving: bool,
+    /// The user's scripts, and the commands they add.
+    scripts: Scripts,
+    /// The keys that scripts have bound, which stay bound when the keymap is rebuilt.
+    script_bindings: Vec<(Vec<Key>, String)>,
+    /// The handlers written in Rust for each hook.
+    hooks: Hooks,
+    /// The keyboard macros.
+    macros: Macros,
+    /// The name of the macro being recorded.
+    recording_macro: Option<String>,
+    /// How many keys had been recorded when the key sequence being handled started,
+    /// so that the keys which stop a recording can be left out of it.
+    sequence_start: usize,
+    /// The keys of a macro that is played over and over until it fails.
+    repeat_macro: Option<Vec<Key>>,
+    /// Set when the key being handled couldn't be done, which stops macros.
+    failed: bool,
+    /// The block that was copied or deleted last, one string per line.
+    block_register: Vec<String>,
+    /// The saved marks of every file. The hooks for opening and saving buffers use them too.
+    marks: Rc<RefCell<Marks>>,
+    /// The places that the cursor jumped away from.
+    jumps: JumpList,
+}
+
+/// What the editor looked like before an event, so that we can tell which hooks to fire.
+struct State {
+    /// The id and filename of each buffer.
+    buffers: Vec<(usize, Option<String>)>,
+    /// The id of the current buffer.
+    current: usize,
+    cursor: Cursor,
+    mode: Option<vi::Mode>,
 }
 
 impl<'a> Editor<'a> {
-    /// Constructs a new editor instance from the given Frontend and Backend.
-    pub fn new(frontend: &'a mut Frontend, backend: &'a mut Backend) -> Editor<'a> {
-        Editor {
+    /// Constructs a new editor instance from the given Frontend, Backend and Config.
+    pub fn new(frontend: &'a mut Frontend, backend: &'a mut Backend, config: Config) -> Editor<'a> {
+        let layout = Layout::new(backend.current_buffer().id(), *backend.cursor());
+        let mut errors = config.errors.clone();
+        let mut scripts = Scripts::new();
+        let requests = match paths::config_dir() {
+            Some(dir) => {
+                let (script_errors, requests) = scripts.load(&dir.join("scripts"), backend, frontend);
+                errors.extend(script_errors);
+                requests
+            },
+            None => Vec::new(),
+        };
+        let (keymap, keymap_errors) = load_keymap(&config.keymap, &scripts, &[]);
+        errors.extend(keymap_errors);
+        let mut editor = Editor {
             frontend: frontend,
             backend: backend,
+            message: if errors.is_empty() { None } else { Some(errors.join("; ")) },
+            layout: layout,
+            keymap: keymap,
+            pending: Vec::new(),
+            quit: false,
+            vi: if config.keymap == "vi" { Some(Vi::new()) } else { None },
+            keymap_override: None,
+            keymap_name: config.keymap.clone(),
+            config: config,
+            kill_ring: KillRing::new(),
+            yanked: None,
+            last_command: None,
+            typing: false,
+            saving: false,
+            scripts: scripts,
+            script_bindings: Vec::new(),
+            hooks: Hooks::new(),
+            macros: Macros::load(),
+            recording_macro: None,
+            sequence_start: 0,
+            repeat_macro: None,
+            failed: false,
+            block_register: Vec::new(),
+            marks: Rc::new(RefCell::new(Marks::load())),
+            jumps: JumpList::load(),
+        };
+        // Buffers get back the marks they had when they are opened,
+        // and the marks are saved along with the text.
+        let marks = editor.marks.clone();
+        editor.on_hook(Hook::BufferOpened, Box::new(move |event, backend| {
+            if let Some(ref filename) = event.filename {
+                backend.set_marks(marks.borrow().get(filename));
+            }
+        }));
+        let marks = editor.marks.clone();
+        editor.on_hook(Hook::AfterSave, Box::new(move |event, backend| {
+            if let Some(ref filename) = event.filename {
+                marks.borrow_mut().set(filename, backend.marks());
+            }
+        }));
+        editor.handle_script_requests(requests);
+        if !editor.script_bindings.is_empty() {
+            // The user's keymap file should win over the scripts.
+            let (keymap, _) = load_keymap(&editor.keymap_name, &editor.scripts, &editor.script_bindings);
+            editor.keymap = keymap;
+        }
+        editor
+    }
+    /// Uses the keymap called `name` whatever the config says, like when it
+    /// is given on the command line. Returns false if there is no such keymap.
+    pub fn set_keymap(&mut self, name: &str) -> bool {
+        if Keymap::preset(name).is_none() {
+            return false;
+        }
+        self.keymap_override = Some(String::from(name));
+        self.switch_keymap();
+        true
+    }
+    /// Switches to the keymap that the command line or the config asks for,
+    /// if it isn't the one in use already.
+    fn switch_keymap(&mut self) {
+        let name = self.keymap_override.clone().unwrap_or_else(|| self.config.keymap.clone());
+        if name == self.keymap_name {
+            return;
         }
+        let (keymap, errors) = load_keymap(&name, &self.scripts, &self.script_bindings);
+        if !errors.is_empty() {
+            self.message = Some(errors.join("; "));
+        }
+        self.keymap = keymap;
+        self.vi = if name == "vi" { Some(Vi::new()) } else { None };
+        self.pending.clear();
+        self.keymap_name = name;
+    }
+    /// Adds a handler that runs every time `hook` fires.
+    /// Scripts can add handlers too, with `on(hook, function)`.
+    pub fn on_hook(&mut self, hook: Hook, handler: Handler) {
+        self.hooks.on(hook, handler);
     }
     /// Starts the event loop.
     /// This function doesn't return until the program should end.
     pub fn start(&mut self) {
+        // The buffers that trusty started with have been opened too.
+        let ids: Vec<usize> = self.backend.buffers().iter().map(|buffer| buffer.id()).collect();
+        for id in ids {
+            self.fire(Hook::BufferOpened, id);
+        }
         // First, we need to render the editor before we start
         // the event loop so that the user can see the editor
         // before they do anything.
         self.draw();
         // This is the event loop. We go through each event
-        // in stdin().event() (provided by termion) and run
+        // from the frontend (provided by termion) and run
         // the appropiate action in response.
-        for event in stdin().events() {
+        loop {
+            if let (Some(ref keys), false) = (&self.repeat_macro, self.frontend.is_replaying()) {
+                // Round we go again.
+                self.frontend.replay(keys);
+            }
+            let event = match self.frontend.next_event_timeout(CONFIG_CHECK_INTERVAL) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => {
+                    // The user isn't doing anything, so it's a good time
+                    // to see if the config needs to be reloaded.
+                    if self.reload_config() {
+                        self.draw();
+                    }
+                    continue;
+                },
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            // Messages only stay up until the next thing the user does.
+            self.message = None;
+            if self.frontend.take_interrupted() {
+                // The user typed something while a macro was playing.
+                self.repeat_macro = None;
+                self.message = Some(String::from("Stopped the macro"));
+            }
+            // Each key's changes are undone together, except that a run of typed characters
+            // is undone at once, and so is everything typed in vi's insert mode.
+            let inserting = self.vi.as_ref().is_some_and(|vi| vi.mode() == vi::Mode::Insert);
+            let typing = self.typing && matches!(event, Ok(Event::Key(Key::Char(c))) if c != '\n');
+            if !inserting && !typing {
+                self.backend.end_undo_groups();
+            }
+            self.typing = false;
+            let before = self.state();
+            self.failed = false;
+            let vi_pending = self.vi.as_ref().is_some_and(|vi| vi.is_pending());
+            if self.pending.is_empty() && !vi_pending {
+                self.sequence_start = self.frontend.recorded_len().unwrap_or(1).saturating_sub(1);
+            }
             match event {
-                Ok(ev) => match ev {
-                    // Escape quits the program.
-                    // TODO: Make it prompt to save the text before you leave?
-                    Event::Key(Key::Esc) => break,
-                    // You can move the cursor around with the arrow keys.
-                    Event::Key(Key::Up) => self.backend.move_up(),
-                    Event::Key(Key::Down) => self.backend.move_down(),
-                    Event::Key(Key::Left) => self.backend.move_left(),
-                    Event::Key(Key::Right) => self.backend.move_right(),
-                    // Enter inserts a neline like you would expect.
-                    Event::Key(Key::Char('\n')) => self.backend.insert_newline(),
-                    // Backspace also works like you would expect.
-                    Event::Key(Key::Backspace) => self.backend.insert_backspace(),
+                Ok(Event::Key(key)) => self.handle_key(key),
+                Ok(_) => {},
+                // If an error occured, panic!
+                // TODO: Change this to return an Err result.
+                Err(e) => panic!("Error: {}", e),
+            }
+            if self.quit {
+                break;
+            }
+            if self.failed {
+                // A macro stops at the first key that fails.
+                self.frontend.stop_replay();
+                self.repeat_macro = None;
+            }
+            // vi's selection belongs to the buffer it was made in.
+            if self.backend.current_buffer().id() != before.current {
+                if let Some(vi) = self.vi.as_mut() {
+                    vi.end_visual();
+                }
+            }
+            let changes = self.fire_hooks(before);
+            self.sync_panes(changes);
+            // Render the new state of the editor to the screen,
+            // unless a macro is still playing.
+            if !self.frontend.is_replaying() && self.repeat_macro.is_none() {
+                self.draw();
+            }
+        }
+    }
+    /// Looks up a key press in the keymap and does whatever it is bound to.
+    /// When vi is on, it gets to see the key first.
+    fn handle_key(&mut self, key: Key) {
+        if let (Some(ref mut vi), true) = (&mut self.vi, self.pending.is_empty()) {
+            if self.recording_macro.is_some() && key == Key::Char('q') &&
+                vi.mode() == vi::Mode::Normal && !vi.is_pending() {
+                self.stop_recording();
+                return;
+            }
+            match vi.handle_key(key, self.backend) {
+                vi::Outcome::Handled => {
+                    self.last_command = None;
+                    return;
+                },
+                vi::Outcome::Pass(_) => {},
+                vi::Outcome::CommandLine => {
+                    self.command_line();
+                    return;
+                },
+                vi::Outcome::Message(message) => {
+                    self.message = Some(message);
+                    return;
+                },
+                vi::Outcome::Failed => {
+                    self.failed = true;
+                    self.last_command = None;
+                    return;
+                },
+                vi::Outcome::RecordMacro(register) => {
+                    self.record_macro(&register.to_string());
+                    return;
+                },
+                vi::Outcome::PlayMacro(register, count) => {
+                    let name = match register {
+                        '@' => match self.macros.last() {
+                            Some(name) => String::from(name),
+                            None => {
+                                self.message = Some(String::from("No macro has been played yet"));
+                                self.failed = true;
+                                return;
+                            },
+                        },
+                        _ => register.to_string(),
+                    };
+                    self.play_macro(&name, Some(count));
+                    return;
+                },
+                vi::Outcome::Jumped(from) => {
+                    if from.line != self.backend.cursor().line {
+                        let jump = self.jump_from(from);
+                        self.jumps.push(jump);
+                    }
+                    return;
+                },
+                vi::Outcome::SetMark(name) => {
+                    self.set_mark(name);
+                    return;
+                },
+                vi::Outcome::JumpToMark(name, exact) => {
+                    self.jump_to_mark(name, exact);
+                    return;
+                },
+                vi::Outcome::JumpBack => {
+                    self.jump_back();
+                    return;
+                },
+                vi::Outcome::JumpForward => {
+                    self.jump_forward();
+                    return;
+                },
+            }
+        }
+        if !self.pending.is_empty() && self.keymap.lookup(&[key]) == Binding::Command("cancel") {
+            // Give up on the key sequence that was being typed.
+            self.pending.clear();
+            self.message = Some(String::from("Cancelled"));
+            return;
+        }
+        self.pending.push(key);
+        let command = match self.keymap.lookup(&self.pending) {
+            Binding::Command(command) => String::from(command),
+            Binding::Prefix => {
+                // Wait for the rest of the keys, and show what has been typed so far.
+                self.message = Some(format!("{}-", keymap::keys_name(&self.pending)));
+                return;
+            },
+            Binding::Unbound => {
+                if let [Key::Char(c)] = self.pending[..] {
                     // Any other normal character just types that character.
-                    Event::Key(Key::Char(c)) => self.backend.insert_char(c),
-                    // Ctrl-s saves the current buffer.
-                    Event::Key(Key::Ctrl('s')) => {
-                        if self.backend.filename().is_some() {
-                            self.backend.save().unwrap();
-                        } else if let Some(name) = self.frontend.prompt_for_text(SAVE_PROMPT) {
-                            self.backend.set_filename(Some(name));
-                            self.backend.save().unwrap();
-                        }
+                    self.backend.insert_char(c);
+                    self.last_command = None;
+                    self.typing = true;
+                } else {
+                    if self.pending.len() > 1 {
+                        self.message = Some(format!("{} is not bound",
+                                                    keymap::keys_name(&self.pending)));
+                    }
+                    self.failed = true;
+                }
+                self.pending.clear();
+                return;
+            },
+        };
+        self.pending.clear();
+        self.run_command(&command);
+    }
+    /// Runs the command with the given name, which can be built in or from a script.
+    pub fn run_command(&mut self, name: &str) {
+        if let Some(command) = Command::from_name(name) {
+            self.run(command);
+        } else if let Some(command) = self.scripts.command(name) {
+            self.run_script_command(&command);
+        } else {
+            self.message = Some(format!("Unknown command: {}", name));
+            self.failed = true;
+        }
+    }
+    /// Is there a command called `name`, either built in or from a script?
+    fn is_command(&self, name: &str) -> bool {
+        is_command(&self.scripts, name)
+    }
+    /// Runs a command that a script added, and does what it asks for.
+    fn run_script_command(&mut self, command: &ScriptCommand) {
+        self.last_command = None;
+        match self.scripts.run_command(command, self.backend, self.frontend) {
+            Ok(requests) => self.handle_script_requests(requests),
+            Err(e) => {
+                self.message = Some(e);
+                self.failed = true;
+            },
+        }
+    }
+    /// Does what the scripts asked for while they were running.
+    fn handle_script_requests(&mut self, requests: Vec<scripting::Request>) {
+        let mut messages: Vec<String> = self.message.take().into_iter().collect();
+        for request in requests {
+            match request {
+                scripting::Request::Message(text) => messages.push(text),
+                scripting::Request::RunCommand(name) => {
+                    self.run_command(&name);
+                    messages.extend(self.message.take());
+                },
+                scripting::Request::Bind(keys, command) => {
+                    let keys = match keymap::parse_keys(&keys) {
+                        Ok(keys) => keys,
+                        Err(e) => {
+                            messages.push(format!("Can't bind {}: {}", keys, e));
+                            continue;
+                        },
+                    };
+                    if !self.is_command(&command) {
+                        messages.push(format!("Can't bind {}: unknown command `{}`",
+                                              keymap::keys_name(&keys), command));
+                        continue;
+                    }
+                    self.keymap.bind(keys.clone(), command.clone());
+                    self.script_bindings.retain(|(k, _)| *k != keys);
+                    self.script_bindings.push((keys, command));
+                },
+                scripting::Request::Save(buffer, name) => {
+                    let index = match self.backend.buffer_index(buffer) {
+                        Some(index) => index,
+                        None => {
+                            messages.push(String::from("Can't save a buffer that was closed"));
+                            continue;
+                        },
+                    };
+                    let current = self.backend.current_buffer().id();
+                    self.backend.switch_to_buffer(index);
+                    self.save(name);
+                    messages.extend(self.message.take());
+                    if let Some(index) = self.backend.buffer_index(current) {
+                        self.backend.switch_to_buffer(index);
+                    }
+                },
+            }
+        }
+        if !messages.is_empty() {
+            self.message = Some(messages.join("; "));
+        }
+    }
+    /// Shows every command with its keys and description, and runs the one the user picks.
+    fn command_palette(&mut self) {
+        let script_commands = self.scripts.commands();
+        let mut commands: Vec<(&str, String, &str)> = commands::ALL_COMMANDS.iter().map(|command| {
+            (command.name(), self.keymap.keys_for(command.name()).join(", "), command.description())
+        }).collect();
+        commands.extend(script_commands.iter().map(|command| {
+            (&command.name[..], self.keymap.keys_for(&command.name).join(", "), &command.description[..])
+        }));
+        match self.frontend.pick_command(&commands) {
+            Some(index) if index < commands::ALL_COMMANDS.len() => self.run(commands::ALL_COMMANDS[index]),
+            Some(index) => self.run_script_command(&script_commands[index - commands::ALL_COMMANDS.len()]),
+            None => {},
+        }
+    }
+    /// Asks for a command line, like `:w` or `:s/a/b/g`, and runs it.
+    fn command_line(&mut self) {
+        if let Some(line) = self.frontend.prompt_with_completion(":", "command", ex::complete) {
+            self.run_ex(&line);
+        }
+    }
+    /// Runs a command line typed at the `:` prompt.
+    pub fn run_ex(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        let cursor_line = self.backend.cursor().line;
+        let command = match ex::parse(line, cursor_line, self.backend.number_of_lines()) {
+            Ok(command) => command,
+            Err(e) => {
+                self.message = Some(e);
+                self.failed = true;
+                return;
+            },
+        };
+        match command {
+            Ex::Goto(line) => self.jump(|editor| editor.backend.go_to_line(line)),
+            Ex::Write(None) => self.run(Command::Save),
+            Ex::Write(Some(name)) => self.save(Some(name)),
+            Ex::WriteAll => self.run(Command::SaveAll),
+            Ex::WriteQuit(only_if_changed) => {
+                if !only_if_changed || self.backend.is_dirty() {
+                    self.run(Command::Save);
+                }
+                if !self.backend.is_dirty() {
+                    self.quit = true;
+                }
+            },
+            Ex::Quit(force) => {
+                if !force && self.backend.buffers().iter().any(|buffer| buffer.dirty) {
+                    self.message = Some(String::from("There are unsaved changes (add ! to quit anyway)"));
+                } else {
+                    self.quit = true;
+                }
+            },
+            Ex::Edit(name) => self.open(name),
+            Ex::Substitute(range, substitution) => {
+                let (count, last) = ex::substitute(self.backend.current_buffer_mut(), range, &substitution);
+                match last {
+                    Some(line) => {
+                        *self.backend.cursor_mut() = Cursor::new(line, 0);
+                        self.message = Some(format!("{} substitution{}", count, if count == 1 { "" } else { "s" }));
                     },
-                    Event::Key(Key::Ctrl('n')) => self.backend.new_empty_buffer(),
-                    Event::Key(Key::Ctrl('o')) => {
-                        if let Some(name) = self.frontend.prompt_for_text("Enter filename to open") {
-                            self.backend.new_buffer_from_filename(name);
-                        }
+                    None => {
+                        self.message = Some(format!("Pattern not found: {}", substitution.regex));
+                        self.failed = true;
+                    },
+                }
+            },
+            Ex::Sort(range, sort) => self.sort_lines(range.start, range.end, sort),
+            Ex::Set(arguments) => {
+                match ex::set(self.backend.current_buffer_mut(), &arguments) {
+                    Ok(shown) => self.message = shown,
+                    Err(e) => self.message = Some(e),
+                }
+            },
+            Ex::NextBuffer => self.run(Command::NextBuffer),
+            Ex::PreviousBuffer => self.run(Command::PreviousBuffer),
+            Ex::DeleteBuffer(force) => {
+                if self.backend.is_dirty() && !force {
+                    self.message = Some(String::from("The buffer has unsaved changes (add ! to close it anyway)"));
+                } else {
+                    self.backend.remove_current_buffer();
+                }
+            },
+            Ex::Buffer(number) => {
+                if number <= self.backend.buffers().len() {
+                    self.jump(|editor| editor.backend.switch_to_buffer(number - 1));
+                } else {
+                    self.message = Some(format!("There is no buffer {}", number));
+                }
+            },
+            Ex::ListBuffers => self.jump(Editor::pick_buffer),
+            Ex::Command(name) => {
+                if self.is_command(&name) {
+                    self.run_command(&name);
+                } else {
+                    self.message = Some(format!("Not an editor command: {}", name));
+                    self.failed = true;
+                }
+            },
+        }
+    }
+    /// Runs a built in command.
+    fn run(&mut self, command: Command) {
+        let last_command = self.last_command.take();
+        let cursor = *self.backend.cursor();
+        match command {
+            // TODO: Make it prompt to save the text before you leave?
+            Command::Quit => self.quit = true,
+            Command::MoveUp => self.backend.move_up(),
+            Command::MoveDown => self.backend.move_down(),
+            Command::MoveLeft => self.backend.move_left(),
+            Command::MoveRight => self.backend.move_right(),
+            Command::MoveLineStart => self.backend.move_line_start(),
+            Command::MoveLineEnd => self.backend.move_line_end(),
+            Command::MoveWordForward => self.backend.move_word_forward(),
+            Command::MoveWordBackward => self.backend.move_word_backward(),
+            Command::MoveSmartLineStart => self.backend.move_smart_line_start(),
+            Command::MovePageUp => self.move_page(false),
+            Command::MovePageDown => self.move_page(true),
+            Command::MoveParagraphForward => self.backend.move_paragraph_forward(),
+            Command::MoveParagraphBackward => self.backend.move_paragraph_backward(),
+            Command::MoveBufferStart => self.jump(|editor| editor.backend.move_to_start()),
+            Command::MoveBufferEnd => self.jump(|editor| editor.backend.move_to_end()),
+            Command::GoToLine => self.go_to_line(),
+            Command::SetMark => {
+                if let Some(name) = self.prompt_for_mark("Set mark") {
+                    self.set_mark(name);
+                }
+            },
+            Command::JumpToMark => {
+                if let Some(name) = self.prompt_for_mark("Jump to mark") {
+                    self.jump_to_mark(name, true);
+                }
+            },
+            Command::JumpToMatchingBracket => {
+                match brackets::find(self.backend.current_lines(), *self.backend.cursor(), usize::MAX) {
+                    Some(Bracket::Matched { partner, .. }) => self.jump(|editor| *editor.backend.cursor_mut() = partner),
+                    Some(Bracket::Unmatched(_)) => {
+                        self.message = Some(String::from("Nothing matches this bracket"));
+                        self.failed = true;
+                    },
+                    None => {
+                        self.message = Some(String::from("There is no bracket at the cursor"));
+                        self.failed = true;
+                    },
+                }
+            },
+            Command::JumpBack => self.jump_back(),
+            Command::JumpForward => self.jump_forward(),
+            Command::InsertNewline => self.backend.insert_newline(),
+            Command::DeleteBackward => self.backend.insert_backspace(),
+            Command::DeleteForward => self.backend.delete_forward(),
+            Command::KillLine => {
+                let text = self.backend.kill_line();
+                // Killing lines one after another builds up one kill.
+                if last_command == Some(Command::KillLine) {
+                    self.kill_ring.append(&text);
+                } else if !text.is_empty() {
+                    self.kill_ring.push(text);
+                }
+            },
+            Command::Yank => {
+                self.yanked = match self.kill_ring.yank() {
+                    Some(text) => Some(self.backend.insert_text(text)),
+                    None => {
+                        self.message = Some(String::from("Nothing has been killed yet"));
+                        None
+                    },
+                };
+            },
+            Command::YankPop => {
+                let yanked = match (last_command, self.yanked) {
+                    (Some(Command::Yank), Some(yanked)) | (Some(Command::YankPop), Some(yanked)) => yanked,
+                    _ => {
+                        self.message = Some(String::from("The last command wasn't a yank"));
+                        return;
+                    },
+                };
+                if let Some(text) = self.kill_ring.rotate() {
+                    let (start, end) = yanked;
+                    self.backend.current_buffer_mut().delete_between(start, end);
+                    *self.backend.cursor_mut() = start;
+                    self.yanked = Some(self.backend.insert_text(text));
+                }
+            },
+            Command::DeleteLine => {
+                let (first, last) = self.selected_lines().unwrap_or((cursor.line, cursor.line));
+                self.end_selection();
+                let buffer = self.backend.current_buffer_mut();
+                let end = Cursor::new(last, columns::line_len(&buffer.lines()[last]));
+                let text = buffer.text_between(Cursor::new(first, 0), end) + "\n";
+                let line = buffer.delete_lines(first, last);
+                self.backend.go_to_line(line);
+                // Deleting lines one after another builds up one kill, like kill-line.
+                if last_command == Some(Command::DeleteLine) {
+                    self.kill_ring.append(&text);
+                } else {
+                    self.kill_ring.push(text);
+                }
+            },
+            Command::DuplicateLines => {
+                let selection = self.vi.as_ref()
+                    .and_then(|vi| vi.selection(cursor, self.backend.current_lines()));
+                match selection {
+                    // Part of a line is copied on its own, just after itself.
+                    Some((start, end, false)) => {
+                        let buffer = self.backend.current_buffer_mut();
+                        let text = buffer.text_between(start, end);
+                        buffer.insert_text_at(end, &text);
+                        *self.backend.cursor_mut() = end;
                     },
-                    Event::Key(Key::Ctrl('x')) => {
-                        if !self.backend.is_dirty() ||
-                            self.frontend.prompt_for_bool(REMOVE_WHILE_DIRTY) {
-                            self.backend.remove_current_buffer();
+                    _ => {
+                        let (first, last) = self.selected_lines().unwrap_or((cursor.line, cursor.line));
+                        self.backend.current_buffer_mut().duplicate_lines(first, last);
+                        *self.backend.cursor_mut() = Cursor::new(cursor.line + last + 1 - first, cursor.column);
+                    },
+                }
+                self.end_selection();
+            },
+            Command::MoveLinesUp | Command::MoveLinesDown => {
+                let up = command == Command::MoveLinesUp;
+                let (first, last) = self.selected_lines().unwrap_or((cursor.line, cursor.line));
+                // The selection moves along with the lines, so that they can be moved again.
+                if self.backend.current_buffer_mut().move_lines(first, last, up) {
+                    let line = if up { cursor.line - 1 } else { cursor.line + 1 };
+                    *self.backend.cursor_mut() = Cursor { line: line, ..cursor };
+                } else {
+                    self.failed = true;
+                }
+            },
+            Command::JoinLines => {
+                let (first, last) = self.selected_lines().unwrap_or((cursor.line, cursor.line));
+                self.end_selection();
+                // Without a selection the next line is joined onto the cursor's line.
+                let count = cmp::max(last - first, 1);
+                match self.backend.current_buffer_mut().join_lines(first, count) {
+                    Some(column) => *self.backend.cursor_mut() = Cursor::new(first, column),
+                    None => self.failed = true,
+                }
+            },
+            Command::SortLines => {
+                let (first, last) = self.selected_lines().unwrap_or((0, self.backend.number_of_lines() - 1));
+                if let Some(flags) = self.frontend.prompt_for_text(SORT_PROMPT) {
+                    match ex::parse_sort(&flags) {
+                        Ok(sort) => {
+                            self.end_selection();
+                            self.sort_lines(first, last, sort);
+                        },
+                        Err(e) => {
+                            self.message = Some(e);
+                            self.failed = true;
+                        },
+                    }
+                }
+            },
+            Command::ReverseLines => {
+                let (first, last) = self.selected_lines().unwrap_or((0, self.backend.number_of_lines() - 1));
+                self.end_selection();
+                self.backend.current_buffer_mut().reverse_lines(first, last);
+                self.backend.go_to_line(first);
+            },
+            Command::IndentLines | Command::OutdentLines => {
+                let (first, last) = self.selected_lines().unwrap_or((cursor.line, cursor.line));
+                let before = self.backend.length_of_line(cursor.line);
+                let buffer = self.backend.current_buffer_mut();
+                if command == Command::IndentLines {
+                    buffer.indent_lines(first, last);
+                } else {
+                    buffer.outdent_lines(first, last);
+                }
+                // Keep the cursor on the same character, and the selection too.
+                let after = self.backend.length_of_line(cursor.line);
+                self.backend.cursor_mut().column = (cursor.column + after).saturating_sub(before);
+            },
+            Command::Undo => {
+                if !self.backend.current_buffer_mut().undo() {
+                    self.message = Some(String::from("There is nothing to undo"));
+                    self.failed = true;
+                }
+            },
+            Command::Redo => {
+                if !self.backend.current_buffer_mut().redo() {
+                    self.message = Some(String::from("There is nothing to redo"));
+                    self.failed = true;
+                }
+            },
+            Command::Cancel => {
+                // With nothing else to cancel, stop selecting a block or go back to one cursor.
+                if !self.backend.end_block() && !self.backend.clear_cursors() {
+                    self.message = Some(String::from("Cancelled"));
+                }
+            },
+            Command::AddCursorAbove => {
+                if !self.backend.add_cursor_vertically(true) {
+                    self.failed = true;
+                }
+            },
+            Command::AddCursorBelow => {
+                if !self.backend.add_cursor_vertically(false) {
+                    self.failed = true;
+                }
+            },
+            Command::AddCursorAtNextMatch => {
+                if !self.backend.add_cursor_at_next_match() {
+                    self.message = Some(String::from("The word under the cursor doesn't appear anywhere else"));
+                    self.failed = true;
+                }
+            },
+            Command::SplitSelectionIntoCursors => {
+                let cursor = *self.backend.cursor();
+                let selection = self.vi.as_ref()
+                    .and_then(|vi| vi.selection(cursor, self.backend.current_lines()));
+                match selection {
+                    Some((start, mut end, linewise)) => {
+                        if linewise {
+                            end.column = self.backend.length_of_line(end.line);
                         }
+                        self.backend.split_into_line_cursors(start, end);
+                        self.vi.as_mut().unwrap().reset();
                     },
-                    Event::Key(Key::Ctrl('l')) => self.backend.switch_to_next_buffer(),
-                    Event::Key(Key::Ctrl('k')) => self.backend.switch_to_previous_buffer(),
-                    _ => {},
-                },
-                // If an error occured, panic!
-                // TODO: Change this to return an Err result.
-                Err(e) => panic!("Error: {}", e),
+                    None => self.message = Some(String::from("There is no selection to split")),
+                }
+            },
+            Command::ClearCursors => {
+                self.backend.clear_cursors();
+            },
+            Command::BlockSelect => {
+                if !self.backend.end_block() {
+                    self.backend.start_block();
+                }
+            },
+            Command::BlockInsert => {
+                if !self.backend.insert_at_block() {
+                    self.no_block();
+                }
+            },
+            Command::BlockDelete => {
+                match self.backend.delete_block() {
+                    Some(block) => self.block_register = block,
+                    None => self.no_block(),
+                }
+            },
+            Command::BlockCopy => {
+                match self.backend.copy_block() {
+                    Some(block) => {
+                        self.block_register = block;
+                        self.backend.end_block();
+                    },
+                    None => self.no_block(),
+                }
+            },
+            Command::BlockPaste => {
+                if self.block_register.is_empty() {
+                    self.message = Some(String::from("No block has been copied"));
+                    self.failed = true;
+                } else {
+                    self.backend.paste_block(&self.block_register);
+                }
+            },
+            Command::CommandLine => self.command_line(),
+            Command::CommandPalette => self.command_palette(),
+            Command::InsertTab => self.backend.insert_tab(),
+            Command::IndentWithSpaces => self.backend.convert_indentation(false),
+            Command::IndentWithTabs => self.backend.convert_indentation(true),
+            Command::Save => {
+                if self.backend.filename().is_some() {
+                    self.save(None);
+                } else if let Some(name) = self.frontend.prompt_for_path(SAVE_PROMPT, "", "save-as") {
+                    self.save(Some(name));
+                }
+            },
+            Command::SaveAs => {
+                let current = self.backend.filename().clone().unwrap_or_default();
+                if let Some(name) = self.frontend.prompt_for_path(SAVE_AS_PROMPT, &current, "save-as") {
+                    self.save(Some(name));
+                }
+            },
+            Command::SaveCopy => {
+                if let Some(name) = self.frontend.prompt_for_path(SAVE_COPY_PROMPT, "", "save-as") {
+                    self.message = Some(match self.backend.save_copy(&name) {
+                        Ok(()) => format!("Saved a copy to {}", name),
+                        Err(e) => format!("Failed to save {}: {}", name, e),
+                    });
+                }
+            },
+            Command::SaveAll => self.save_all(),
+            Command::NewBuffer => self.backend.new_empty_buffer(),
+            Command::Open => {
+                if let Some(name) = self.frontend.prompt_for_path("Enter filename to open", "", "open") {
+                    self.jump(|editor| editor.open(name));
+                }
+            },
+            Command::FindFile => self.jump(Editor::find_file),
+            Command::CloseBuffer => {
+                if !self.backend.is_dirty() ||
+                    self.frontend.prompt_for_bool(REMOVE_WHILE_DIRTY) {
+                    self.backend.remove_current_buffer();
+                }
+            },
+            Command::NextBuffer => self.jump(|editor| editor.backend.switch_to_next_buffer()),
+            Command::PreviousBuffer => self.jump(|editor| editor.backend.switch_to_previous_buffer()),
+            Command::BufferList => self.jump(Editor::pick_buffer),
+            Command::MoveBufferLeft => self.backend.move_buffer_left(),
+            Command::MoveBufferRight => self.backend.move_buffer_right(),
+            Command::SplitHorizontal => self.layout.split(Split::Horizontal),
+            Command::SplitVertical => self.layout.split(Split::Vertical),
+            Command::ClosePane => {
+                if !self.change_focus(|layout| layout.close()) {
+                    self.message = Some(String::from("Can't close the last pane"));
+                }
+            },
+            Command::CloseOtherPanes => self.layout.close_others(),
+            Command::NextPane => {
+                self.change_focus(|layout| { layout.focus_next(); true });
+            },
+            Command::FocusLeft => self.focus_direction(Direction::Left),
+            Command::FocusDown => self.focus_direction(Direction::Down),
+            Command::FocusUp => self.focus_direction(Direction::Up),
+            Command::FocusRight => self.focus_direction(Direction::Right),
+            Command::GrowPane => self.layout.resize(5),
+            Command::ShrinkPane => self.layout.resize(-5),
+            Command::RecordMacro => {
+                if self.recording_macro.is_some() {
+                    self.stop_recording();
+                } else {
+                    let default = String::from(self.macros.last().unwrap_or("q"));
+                    if let Some(name) = self.frontend.prompt_for_text_with_default("Record a macro called", &default) {
+                        self.record_macro(name.trim());
+                    }
+                }
+            },
+            Command::PlayMacro => {
+                let default = String::from(self.macros.last().unwrap_or(""));
+                if let Some(answer) = self.frontend.prompt_for_text_with_default("Play macro", &default) {
+                    let mut words = answer.split_whitespace();
+                    let name = words.next().unwrap_or("");
+                    match words.next() {
+                        None => self.play_macro(name, Some(1)),
+                        Some("*") => self.play_macro(name, None),
+                        Some(count) => match count.parse() {
+                            Ok(count) => self.play_macro(name, Some(count)),
+                            Err(_) => self.message = Some(format!("{} isn't a number of times", count)),
+                        },
+                    }
+                }
+            },
+        }
+        // Moving into the edge of the text fails, which stops macros.
+        let moves = [Command::MoveUp, Command::MoveDown, Command::MoveLeft, Command::MoveRight,
+                     Command::MoveWordForward, Command::MoveWordBackward, Command::MovePageUp,
+                     Command::MovePageDown, Command::MoveParagraphForward, Command::MoveParagraphBackward];
+        if moves.contains(&command) && *self.backend.cursor() == cursor {
+            self.failed = true;
+        }
+        self.last_command = Some(command);
+    }
+    /// Moves the cursor a screenful of lines down, or up if `down` is false,
+    /// and scrolls the focused pane by the same amount so that the text moves a page.
+    fn move_page(&mut self, down: bool) {
+        let area = self.layout.areas(self.screen_area())[self.layout.focused()];
+        let rows = cmp::max(area.height.saturating_sub(1), 1);
+        let last = self.backend.number_of_lines() - 1;
+        self.backend.move_lines(rows, down);
+        let pane = self.layout.focused_pane_mut();
+        pane.top = if down { cmp::min(pane.top + rows, last) } else { pane.top.saturating_sub(rows) };
+    }
+    /// Asks for a line number and moves the cursor to it.
+    fn go_to_line(&mut self) {
+        let lines = self.backend.number_of_lines();
+        let answer = match self.frontend.prompt_for_text(&format!("Go to line (1-{})", lines)) {
+            Some(answer) => answer,
+            None => return,
+        };
+        match answer.trim().parse::<usize>() {
+            Ok(line) if line >= 1 => self.jump(|editor| editor.backend.go_to_line(cmp::min(line, lines) - 1)),
+            _ => {
+                self.message = Some(format!("{} isn't a line number", answer.trim()));
+                self.failed = true;
+            },
+        }
+    }
+    /// Returns a jump to `at` in the current buffer.
+    fn jump_from(&self, at: Cursor) -> Jump {
+        Jump {
+            buffer: Some(self.backend.current_buffer().id()),
+            filename: self.backend.filename().as_ref().map(|name| marks::file_key(name)),
+            cursor: at,
+        }
+    }
+    /// Runs `f`, and if it takes the cursor to another line or buffer,
+    /// remembers where it was in the jump list.
+    fn jump<F: FnOnce(&mut Editor<'a>)>(&mut self, f: F) {
+        let from = self.jump_from(*self.backend.cursor());
+        f(self);
+        let to = self.jump_from(*self.backend.cursor());
+        if to.buffer != from.buffer || to.cursor.line != from.cursor.line {
+            self.jumps.push(from);
+        }
+    }
+    /// Goes back to where the cursor was before the last jump.
+    fn jump_back(&mut self) {
+        let from = self.jump_from(*self.backend.cursor());
+        let mut next = self.jumps.back(from.clone());
+        // Skip over jumps into buffers that have gone.
+        while let Some(jump) = next {
+            if self.go_to_jump(&jump) {
+                return;
+            }
+            next = self.jumps.back(from.clone());
+        }
+        self.message = Some(String::from("There is nowhere further back to jump to"));
+        self.failed = true;
+    }
+    /// Goes forward to where the cursor was before jumping back.
+    fn jump_forward(&mut self) {
+        while let Some(jump) = self.jumps.forward() {
+            if self.go_to_jump(&jump) {
+                return;
+            }
+        }
+        self.message = Some(String::from("There is nowhere further forward to jump to"));
+        self.failed = true;
+    }
+    /// Moves the cursor to `jump`, opening its file again if it has been closed.
+    /// Returns false if the jump was in a buffer without a file that has been closed.
+    fn go_to_jump(&mut self, jump: &Jump) -> bool {
+        let current = self.backend.current_buffer().id();
+        let open = jump.buffer.and_then(|id| self.backend.buffer_index(id)).or_else(|| {
+            let filename = jump.filename.as_ref()?;
+            self.backend.buffers().iter().position(|buffer| {
+                buffer.filename.as_ref().is_some_and(|name| marks::file_key(name) == *filename)
+            })
+        });
+        match (open, jump.filename.as_ref()) {
+            (Some(index), _) => self.backend.switch_to_buffer(index),
+            (None, Some(filename)) => {
+                if let Err(e) = self.backend.new_buffer_from_filename(filename.clone()) {
+                    self.message = Some(format!("Failed to open {}: {}", filename, e));
+                    return false;
+                }
+            },
+            (None, None) => return false,
+        }
+        // A selection can't carry on into another buffer.
+        if self.backend.current_buffer().id() != current {
+            if let Some(vi) = self.vi.as_mut() {
+                vi.end_visual();
             }
-            // Render the new state of the editor to the screen.
-            self.draw();
+        }
+        let mut cursor = jump.cursor;
+        cursor.clamp(self.backend.current_lines());
+        *self.backend.cursor_mut() = cursor;
+        true
+    }
+    /// Asks for the name of a mark, which is a single letter or digit.
+    fn prompt_for_mark(&mut self, prompt: &str) -> Option<char> {
+        let answer = self.frontend.prompt_for_text(prompt)?;
+        let mut chars = answer.trim().chars();
+        match (chars.next(), chars.next()) {
+            (Some(name), None) if name.is_alphanumeric() => Some(name),
+            _ => {
+                self.message = Some(String::from("A mark's name is a single letter or digit"));
+                self.failed = true;
+                None
+            },
+        }
+    }
+    /// Sets the mark called `name` to where the cursor is, and saves it if the buffer has a file.
+    fn set_mark(&mut self, name: char) {
+        self.backend.set_mark(name);
+        if let Some(filename) = self.backend.filename().clone() {
+            self.marks.borrow_mut().set(&filename, self.backend.marks());
+        }
+        self.message = Some(format!("Set mark {}", name));
+    }
+    /// Jumps to the mark called `name`, or to the first non-blank character on its line if `exact` is false.
+    fn jump_to_mark(&mut self, name: char, exact: bool) {
+        let mark = match self.backend.mark(name) {
+            Some(mark) => mark,
+            None => {
+                self.message = Some(format!("Mark {} isn't set", name));
+                self.failed = true;
+                return;
+            },
+        };
+        self.jump(|editor| if exact {
+            *editor.backend.cursor_mut() = mark;
+        } else {
+            editor.backend.go_to_line(mark.line);
+        });
+    }
+    /// Returns the first and last lines of vi's selection or the block, if there is one.
+    fn selected_lines(&self) -> Option<(usize, usize)> {
+        let cursor = *self.backend.cursor();
+        let selection = self.vi.as_ref().and_then(|vi| vi.selection(cursor, self.backend.current_lines()));
+        match (selection, self.backend.block()) {
+            (Some((start, end, _)), _) => Some((start.line, end.line)),
+            (None, Some(block)) => Some((block.first, block.last)),
+            (None, None) => None,
+        }
+    }
+    /// Stops selecting, once a line command is done with the selection.
+    fn end_selection(&mut self) {
+        let cursor = *self.backend.cursor();
+        if let Some(vi) = self.vi.as_mut() {
+            if vi.selection(cursor, self.backend.current_lines()).is_some() {
+                vi.reset();
+            }
+        }
+        self.backend.end_block();
+    }
+    /// Sorts the lines from `first` to `last`, and says how many lines were
+    /// dropped if only unique lines are kept.
+    fn sort_lines(&mut self, first: usize, last: usize, sort: Sort) {
+        let dropped = self.backend.current_buffer_mut().sort_lines(first, last, sort);
+        self.backend.go_to_line(first);
+        if sort.unique {
+            self.message = Some(format!("Removed {} duplicate line{}", dropped, if dropped == 1 { "" } else { "s" }));
+        }
+    }
+    /// Says that a block command had no block to work on.
+    fn no_block(&mut self) {
+        self.message = Some(String::from("There is no block selected"));
+        self.failed = true;
+    }
+    /// Starts recording the keys that the user types into the macro called `name`.
+    fn record_macro(&mut self, name: &str) {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            self.message = Some(String::from("A macro's name can't be empty or have spaces in it"));
+            return;
+        }
+        self.frontend.start_recording();
+        self.recording_macro = Some(String::from(name));
+        self.message = Some(format!("Recording macro {}", name));
+    }
+    /// Stops recording a macro and saves it.
+    fn stop_recording(&mut self) {
+        let name = match self.recording_macro.take() {
+            Some(name) => name,
+            None => return,
+        };
+        let mut keys = self.frontend.stop_recording().unwrap_or_default();
+        // Leave out the keys that stopped the recording, and any keys
+        // that can't be written to the macros file, like unnamed ones.
+        keys.truncate(self.sequence_start);
+        keys.retain(keymap::has_name);
+        if keys.is_empty() {
+            self.message = Some(format!("Nothing was recorded, so macro {} wasn't changed", name));
+            return;
+        }
+        self.message = Some(format!("Recorded macro {} ({} key{})", name, keys.len(),
+                                    if keys.len() == 1 { "" } else { "s" }));
+        self.macros.set(&name, keys);
+    }
+    /// Plays the macro called `name`, `times` times or over and over until it fails.
+    fn play_macro(&mut self, name: &str, times: Option<usize>) {
+        if self.recording_macro.as_ref().is_some_and(|recording| recording == name) {
+            self.message = Some(format!("Macro {} is still being recorded", name));
+            self.failed = true;
+            return;
+        }
+        let keys = match self.macros.get(name) {
+            // Playing nothing over and over would never stop.
+            Some([]) => {
+                self.message = Some(format!("Macro {} is empty", name));
+                self.failed = true;
+                return;
+            },
+            Some(keys) => keys.to_vec(),
+            None => {
+                self.message = Some(format!("There is no macro called {}", name));
+                self.failed = true;
+                return;
+            },
+        };
+        self.macros.set_last(name);
+        match times {
+            Some(times) => match keys.len().checked_mul(times) {
+                Some(count) => {
+                    let all: Vec<Key> = keys.iter().cycle().take(count).cloned().collect();
+                    self.frontend.replay(&all);
+                },
+                None => {
+                    self.message = Some(format!("Can't play macro {} {} times", name, times));
+                    self.failed = true;
+                },
+            },
+            None => self.repeat_macro = Some(keys),
         }
     }
     /// Renders the current state of the editor to the screen.
@@ -87,10 +1166,413 @@ impl<'a> Editor<'a> {
     /// from the backend to the frontend.
     fn draw(&mut self) {
         self.frontend.clear_screen();
-        self.frontend.draw(self.backend.cursor(),
-                           self.backend.filename(),
-                           self.backend.current_lines());
-        self.frontend.move_cursor(self.backend.cursor());
+        let area = self.screen_area();
+        let areas = self.layout.areas(area);
+        let tabs: Vec<String> = self.backend.buffers().iter().map(|buffer| {
+            if buffer.dirty {
+                format!("{} [+]", buffer.short_name())
+            } else {
+                buffer.short_name()
+            }
+        }).collect();
+        self.frontend.draw_tab_bar(0, &tabs, self.backend.current_index());
+        let focused = self.layout.focused();
+        let limit = areas[focused].height + BRACKET_SEARCH_MARGIN;
+        let bracket = brackets::find(self.backend.current_lines(), *self.backend.cursor(), limit);
+        let selection = self.selection(bracket);
+        for (i, (pane, area)) in self.layout.panes_mut().into_iter().zip(&areas).enumerate() {
+            // A pane whose buffer was just closed shows the current buffer until the panes are synced.
+            let (buffer, shown) = match self.backend.buffer_index(pane.buffer) {
+                Some(index) => (&self.backend.buffers()[index], true),
+                None => (self.backend.current_buffer(), false),
+            };
+            // The focused pane's cursor is the buffer's cursor.
+            let cursor = if i == focused || !shown { *buffer.cursor() } else { pane.cursor };
+            let rows = area.height.saturating_sub(1);
+            let highlights = if i == focused { &selection[..] } else { &[] };
+            let width = area.width.saturating_sub(frontend::gutter_width(buffer.settings()) + 1);
+            let column = columns::visual_column(&buffer.lines()[cursor.line], cursor.column,
+                                                buffer.settings().tab_width);
+            pane.scroll_to(cursor.line, column, rows, width);
+            self.frontend.draw_pane(area, pane, i == focused, buffer, highlights);
+        }
+        let mut status: Vec<String> = self.vi.as_ref().and_then(|vi| vi.status()).into_iter().collect();
+        if self.backend.block().is_some() {
+            status.push(String::from("-- BLOCK --"));
+        }
+        if let Some(Bracket::Unmatched(at)) = bracket {
+            let bracket = self.backend.current_lines()[at.line].chars().nth(at.column).unwrap();
+            status.push(format!("Unmatched {}", bracket));
+        }
+        if let Some(ref name) = self.recording_macro {
+            status.push(format!("recording {}", name));
+        }
+        let status = if status.is_empty() { None } else { Some(status.join("  ")) };
+        if let Some(message) = self.message.as_ref().or(status.as_ref()) {
+            self.frontend.draw_message(message);
+        }
+        let pane = self.layout.focused_pane();
+        let buffer = self.backend.current_buffer();
+        let cursor = buffer.cursor();
+        let column = columns::visual_column(&buffer.lines()[cursor.line], cursor.column,
+                                            buffer.settings().tab_width);
+        self.frontend.move_cursor(&areas[focused], pane, cursor.line, column, buffer.settings());
         self.frontend.flush();
     }
+    /// Returns the highlights for vi's selection or the block, if there is one, for the other cursors,
+    /// and for the `bracket` at the cursor.
+    fn selection(&self, bracket: Option<Bracket>) -> Vec<Highlight> {
+        let buffer = self.backend.current_buffer();
+        let lines = buffer.lines();
+        let tab_width = buffer.settings().tab_width;
+        let character = |at: Cursor, kind| {
+            let column = columns::visual_column(&lines[at.line], at.column, tab_width);
+            Highlight { line: at.line, start: column, end: column + 1, kind: kind }
+        };
+        let mut highlights = match bracket {
+            Some(Bracket::Matched { at, partner }) => {
+                vec![character(at, HighlightKind::Bracket), character(partner, HighlightKind::Bracket)]
+            },
+            Some(Bracket::Unmatched(at)) => vec![character(at, HighlightKind::UnmatchedBracket)],
+            None => Vec::new(),
+        };
+        // The other cursors show as a highlighted character.
+        highlights.extend(buffer.cursors().iter().map(|&cursor| character(cursor, HighlightKind::Selection)));
+        if let Some(block) = self.backend.block() {
+            highlights.extend((block.first..block.last + 1).map(|line| {
+                Highlight { line: line, start: block.left, end: block.right, kind: HighlightKind::Selection }
+            }));
+        }
+        let (start, end, linewise) = match self.vi.as_ref().and_then(|vi| vi.selection(*buffer.cursor(), lines)) {
+            Some(selection) => selection,
+            None => return highlights,
+        };
+        highlights.extend((start.line..end.line + 1).map(|line| {
+            let text = &lines[line];
+            if linewise {
+                return Highlight { line: line, start: 0, end: usize::MAX, kind: HighlightKind::Selection };
+            }
+            let first = if line == start.line { columns::visual_column(text, start.column, tab_width) } else { 0 };
+            let last = if line == end.line {
+                columns::visual_column(text, end.column, tab_width)
+            } else {
+                // Show that the newline at the end of the line is selected too.
+                columns::visual_width(text, tab_width) + 1
+            };
+            Highlight { line: line, start: first, end: last, kind: HighlightKind::Selection }
+        }));
+        highlights
+    }
+    /// Reloads the config if any of its files have changed, and hands the new
+    /// config to the frontend and backend. Returns true if it was reloaded.
+    fn reload_config(&mut self) -> bool {
+        if !self.config.changed() {
+            return false;
+        }
+        let config = Config::load();
+        self.message = Some(if config.errors.is_empty() {
+            String::from("Reloaded the config")
+        } else {
+            config.errors.join("; ")
+        });
+        self.frontend.set_config(config.clone());
+        self.backend.set_config(config.clone());
+        self.config = config;
+        self.switch_keymap();
+        true
+    }
+    /// Returns the part of the screen that the panes are drawn in,
+    /// which is everything below the tab bar.
+    fn screen_area(&self) -> Rect {
+        let (width, height) = self.frontend.terminal_size();
+        Rect::new(0, 1, width, height - 1)
+    }
+    /// Brings the panes up to date with what happened in the backend.
+    /// The pane with focus follows the current buffer, and the other panes
+    /// move their cursors to keep up with `changes` made in the pane with focus.
+    fn sync_panes(&mut self, changes: Vec<(usize, Change)>) {
+        let current = self.backend.current_buffer().id();
+        // The jumps and vi's selection move along with the text too.
+        for &(id, ref change) in &changes {
+            self.jumps.adjust(id, change);
+            if id == current {
+                if let Some(vi) = self.vi.as_mut() {
+                    vi.adjust(change);
+                }
+            }
+        }
+        let focused = self.layout.focused();
+        for (i, pane) in self.layout.panes_mut().into_iter().enumerate() {
+            if i == focused {
+                pane.buffer = current;
+                pane.cursor = *self.backend.cursor();
+                continue;
+            }
+            for &(id, ref change) in &changes {
+                if id == pane.buffer {
+                    pane.cursor.adjust(change);
+                }
+            }
+            match self.backend.buffer_index(pane.buffer) {
+                Some(index) => pane.cursor.clamp(self.backend.buffers()[index].lines()),
+                None => {
+                    // The buffer was closed, so show the current one instead.
+                    pane.buffer = current;
+                    pane.cursor = *self.backend.cursor();
+                },
+            }
+        }
+    }
+    /// Runs `f` to move focus to another pane, and switches the backend to
+    /// the new pane's buffer and cursor. Returns whatever `f` returns.
+    fn change_focus<F: FnOnce(&mut Layout) -> bool>(&mut self, f: F) -> bool {
+        // The buffer's cursor belongs to the pane losing focus.
+        self.layout.focused_pane_mut().cursor = *self.backend.cursor();
+        let result = f(&mut self.layout);
+        let pane = self.layout.focused_pane().clone();
+        if let Some(index) = self.backend.buffer_index(pane.buffer) {
+            self.backend.switch_to_buffer(index);
+            *self.backend.cursor_mut() = pane.cursor;
+        }
+        result
+    }
+    /// Moves focus to the nearest pane in `direction`.
+    fn focus_direction(&mut self, direction: Direction) {
+        let area = self.screen_area();
+        self.change_focus(|layout| { layout.focus_direction(direction, area); true });
+    }
+    /// Shows the list of open buffers and lets the user switch to or close them.
+    fn pick_buffer(&mut self) {
+        let mut picker = Picker::new("Buffers", self.buffer_list()).numbered();
+        picker.select(self.backend.current_index());
+        loop {
+            match self.frontend.pick(&mut picker) {
+                Picked::Open(index) => self.backend.switch_to_buffer(index),
+                Picked::Remove(index) => {
+                    if !self.backend.buffers()[index].dirty ||
+                        self.frontend.prompt_for_bool(REMOVE_WHILE_DIRTY) {
+                        self.backend.remove_buffer(index);
+                    }
+                    // Show the list again without the buffer that was closed,
+                    // once the panes that showed it have moved on to another one.
+                    self.sync_panes(Vec::new());
+                    picker.set_items(self.buffer_list());
+                    self.draw();
+                    continue;
+                },
+                Picked::Cancel => {},
+            }
+            break;
+        }
+    }
+    /// Lets the user fuzzy search for a file under the working directory and opens it.
+    fn find_file(&mut self) {
+        let files = finder::list_files(Path::new("."));
+        let items = files.iter()
+            .map(|f| PickerItem::new(f.clone(), String::new()))
+            .collect();
+        let mut picker = Picker::new("Find file", items);
+        let picked = self.frontend.pick_with_preview(&mut picker, |index, lines| {
+            finder::preview(&files[index], lines)
+        });
+        if let Picked::Open(index) = picked {
+            self.open(files[index].clone());
+        }
+    }
+    /// Opens the file called `name` in a new buffer, or tells the user why it couldn't be.
+    fn open(&mut self, name: String) {
+        if let Err(e) = self.backend.new_buffer_from_filename(name.clone()) {
+            self.message = Some(format!("Failed to open {}: {}", name, e));
+        }
+    }
+    /// Describes each buffer for the buffer list.
+    fn buffer_list(&self) -> Vec<PickerItem> {
+        self.backend.buffers().iter().map(|buffer| {
+            let name = buffer.filename.clone()
+                .unwrap_or_else(|| String::from("**no filename**"));
+            let detail = format!("{}line {}",
+                                 if buffer.dirty { "[+] " } else { "" },
+                                 buffer.cursor().line + 1);
+            PickerItem::new(name, detail)
+        }).collect()
+    }
+    /// Saves the current buffer, under `name` if one is given, and fires the save hooks.
+    fn save(&mut self, name: Option<String>) {
+        let id = self.backend.current_buffer().id();
+        let hooks = !self.saving;
+        if hooks {
+            self.saving = true;
+            self.fire(Hook::BeforeSave, id);
+        }
+        let result = match name {
+            Some(name) => self.backend.save_as(name),
+            None => self.backend.save(),
+        };
+        let saved = result.is_ok();
+        self.report_save(result);
+        if hooks {
+            if saved {
+                self.fire(Hook::AfterSave, id);
+            }
+            self.saving = false;
+        }
+    }
+    /// Saves every dirty buffer, firing the save hooks for each one.
+    fn save_all(&mut self) {
+        let current = self.backend.current_buffer().id();
+        let dirty: Vec<usize> = self.backend.buffers().iter()
+            .filter(|buffer| buffer.dirty)
+            .map(|buffer| buffer.id())
+            .collect();
+        let mut results = Vec::new();
+        for id in dirty {
+            let index = match self.backend.buffer_index(id) {
+                Some(index) => index,
+                None => continue,
+            };
+            self.backend.switch_to_buffer(index);
+            self.fire(Hook::BeforeSave, id);
+            let name = self.backend.filename().clone()
+                .unwrap_or_else(|| String::from("**no filename**"));
+            let result = self.backend.save();
+            if result.is_ok() {
+                self.fire(Hook::AfterSave, id);
+            }
+            results.push((name, result));
+        }
+        if let Some(index) = self.backend.buffer_index(current) {
+            self.backend.switch_to_buffer(index);
+        }
+        self.report_save_all(results);
+    }
+    /// Takes a snapshot of the things that hooks watch for changes to.
+    fn state(&self) -> State {
+        State {
+            buffers: self.backend.buffers().iter()
+                .map(|buffer| (buffer.id(), buffer.filename.clone()))
+                .collect(),
+            current: self.backend.current_buffer().id(),
+            cursor: *self.backend.cursor(),
+            mode: self.vi.as_ref().map(|vi| vi.mode()),
+        }
+    }
+    /// Fires the hooks for whatever changed since `before`.
+    /// Returns the changes made to the text, including any made by the handlers.
+    fn fire_hooks(&mut self, before: State) -> Vec<(usize, Change)> {
+        let mut changes = self.backend.take_changes();
+        let after = self.state();
+        for &(id, _) in &after.buffers {
+            if !before.buffers.iter().any(|&(b, _)| b == id) {
+                self.fire(Hook::BufferOpened, id);
+            }
+        }
+        for (id, filename) in before.buffers {
+            if !after.buffers.iter().any(|&(b, _)| b == id) {
+                self.fire_event(hooks::Event {
+                    hook: Hook::BufferClosed,
+                    buffer: id,
+                    filename: filename,
+                    mode: after.mode.map(|mode| mode.name()),
+                });
+            }
+        }
+        let mut changed: Vec<usize> = changes.iter().map(|&(id, _)| id).collect();
+        changed.sort();
+        changed.dedup();
+        for id in changed {
+            if self.backend.buffer_index(id).is_some() {
+                self.fire(Hook::TextChanged, id);
+            }
+        }
+        if after.current != before.current || after.cursor != before.cursor {
+            self.fire(Hook::CursorMoved, after.current);
+        }
+        if after.mode != before.mode {
+            self.fire(Hook::ModeChanged, after.current);
+        }
+        // Changes made by the handlers don't fire text-changed again,
+        // otherwise a handler that edits the text would never stop.
+        changes.extend(self.backend.take_changes());
+        changes
+    }
+    /// Fires `hook` for the buffer with the id `buffer`.
+    fn fire(&mut self, hook: Hook, buffer: usize) {
+        let filename = self.backend.buffer_index(buffer)
+            .and_then(|index| self.backend.buffers()[index].filename.clone());
+        let mode = self.vi.as_ref().map(|vi| vi.mode().name());
+        self.fire_event(hooks::Event { hook: hook, buffer: buffer, filename: filename, mode: mode });
+    }
+    /// Runs the Rust and script handlers for `event`, with the buffer that
+    /// it is about as the current one.
+    fn fire_event(&mut self, event: hooks::Event) {
+        let current = self.backend.current_buffer().id();
+        if let Some(index) = self.backend.buffer_index(event.buffer) {
+            self.backend.switch_to_buffer(index);
+        }
+        self.hooks.fire(&event, self.backend);
+        let (errors, requests) = self.scripts.fire(&event, self.backend, self.frontend);
+        if let Some(index) = self.backend.buffer_index(current) {
+            self.backend.switch_to_buffer(index);
+        }
+        if !errors.is_empty() {
+            let mut messages: Vec<String> = self.message.take().into_iter().collect();
+            messages.extend(errors);
+            self.message = Some(messages.join("; "));
+        }
+        self.handle_script_requests(requests);
+    }
+    /// Tells the user whether saving the current buffer worked.
+    fn report_save(&mut self, result: io::Result<()>) {
+        let name = self.backend.filename().clone().unwrap_or_default();
+        self.message = Some(match result {
+            Ok(()) => format!("Saved {}", name),
+            Err(e) => format!("Failed to save {}: {}", name, e),
+        });
+    }
+    /// Tells the user which buffers were saved by save-all and which ones failed.
+    fn report_save_all(&mut self, results: Vec<(String, io::Result<()>)>) {
+        if results.is_empty() {
+            self.message = Some(String::from("No unsaved buffers"));
+            return;
+        }
+        let mut saved = Vec::new();
+        let mut failed = Vec::new();
+        for (name, result) in results {
+            match result {
+                Ok(()) => saved.push(name),
+                Err(e) => failed.push(format!("{} ({})", name, e)),
+            }
+        }
+        let mut message = String::new();
+        if !saved.is_empty() {
+            message.push_str(&format!("Saved {}", saved.join(", ")));
+        }
+        if !failed.is_empty() {
+            if !message.is_empty() {
+                message.push_str("; ");
+            }
+            message.push_str(&format!("Failed to save {}", failed.join(", ")));
+        }
+        self.message = Some(message);
+    }
+}
+
+/// Builds the keymap called `name` with the keys that scripts bound on top of it,
+/// and then the user's keymap file, and returns it along with any problems in the file.
+fn load_keymap(name: &str, scripts: &Scripts, script_bindings: &[(Vec<Key>, String)])
+               -> (Keymap, Vec<String>) {
+    let mut keymap = Keymap::preset(name).unwrap_or_else(Keymap::new);
+    for (keys, command) in script_bindings {
+        keymap.bind(keys.clone(), command.clone());
+    }
+    let errors = match paths::config_dir() {
+        Some(dir) => keymap.load(&dir.join("keys"), |name| is_command(scripts, name)),
+        None => Vec::new(),
+    };
+    (keymap, errors)
+}
+
+/// Is `name` a built in command or one that a script added?
+fn is_command(scripts: &Scripts, name: &str) -> bool {
+    keymap::is_builtin_command(name) || scripts.command(name).is_some()
 }
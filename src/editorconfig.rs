@@ -0,0 +1,359 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use config::{Settings, LineEnding, Charset};
+
+/// The name of the files that EditorConfig reads.
+const EDITORCONFIG: &'static str = ".editorconfig";
+
+/// The properties that apply to a file, as (name, value) pairs.
+/// Names are lowercase, and so are the values of the properties we know about.
+pub type Properties = Vec<(String, String)>;
+
+/// Finds the `.editorconfig` files for the file at `path` and returns the
+/// properties from all of the sections that match it.
+/// Files closer to `path` win over ones further up, and within a file later
+/// sections win over earlier ones. See https://editorconfig.org for the details.
+pub fn properties(path: &Path) -> Properties {
+    let path = match env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => path.to_path_buf(),
+    };
+    // Collect the files from the closest one up to the root one.
+    let mut files: Vec<(PathBuf, String)> = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let text = match fs::read_to_string(dir.join(EDITORCONFIG)) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let root = parse(&text).0;
+        files.push((dir.to_path_buf(), text));
+        if root {
+            break;
+        }
+    }
+    let mut properties = Properties::new();
+    for (dir, text) in files.iter().rev() {
+        let relative = match path.strip_prefix(dir) {
+            Ok(relative) => relative.to_string_lossy().into_owned(),
+            Err(_) => continue,
+        };
+        for (glob, section) in parse(text).1 {
+            if !matches_section(&glob, &relative) {
+                continue;
+            }
+            for (name, value) in section {
+                properties.retain(|(n, _)| *n != name);
+                properties.push((name, value));
+            }
+        }
+    }
+    properties
+}
+
+/// Changes `settings` to follow the EditorConfig `properties`.
+/// Properties that we don't know about, or that are `unset`, are ignored.
+pub fn apply(properties: &Properties, settings: &mut Settings) {
+    let get = |name: &str| {
+        properties.iter().find(|(n, _)| n == name).map(|(_, v)| &v[..])
+    };
+    let number = |name: &str| get(name).and_then(|v| v.parse::<usize>().ok()).filter(|&n| n > 0);
+    if let Some(width) = number("tab_width") {
+        settings.tab_width = width;
+    }
+    match get("indent_size") {
+        Some("tab") => settings.indent_size = settings.tab_width,
+        _ => if let Some(size) = number("indent_size") {
+            settings.indent_size = size;
+            // The tab width defaults to the indent size.
+            if get("tab_width").is_none() {
+                settings.tab_width = size;
+            }
+        },
+    }
+    match get("indent_style") {
+        Some("tab") => {
+            settings.expand_tabs = false;
+            if get("indent_size").is_none() {
+                settings.indent_size = settings.tab_width;
+            }
+        },
+        Some("space") => settings.expand_tabs = true,
+        _ => {},
+    }
+    match get("end_of_line") {
+        Some("lf") => settings.line_ending = LineEnding::Lf,
+        Some("crlf") => settings.line_ending = LineEnding::CrLf,
+        Some("cr") => settings.line_ending = LineEnding::Cr,
+        _ => {},
+    }
+    match get("charset") {
+        Some("utf-8") => settings.charset = Charset::Utf8,
+        Some("utf-8-bom") => settings.charset = Charset::Utf8Bom,
+        Some("latin1") => settings.charset = Charset::Latin1,
+        Some("utf-16be") => settings.charset = Charset::Utf16Be,
+        Some("utf-16le") => settings.charset = Charset::Utf16Le,
+        _ => {},
+    }
+    match get("trim_trailing_whitespace") {
+        Some("true") => settings.trim_trailing_whitespace = true,
+        Some("false") => settings.trim_trailing_whitespace = false,
+        _ => {},
+    }
+    match get("insert_final_newline") {
+        Some("true") => settings.insert_final_newline = true,
+        Some("false") => settings.insert_final_newline = false,
+        _ => {},
+    }
+}
+
+/// Parses the text of an `.editorconfig` file into whether it says `root = true`
+/// and the glob and properties of each of its sections.
+fn parse(text: &str) -> (bool, Vec<(String, Properties)>) {
+    let mut root = false;
+    let mut sections: Vec<(String, Properties)> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            sections.push((String::from(&line[1..line.len() - 1]), Properties::new()));
+            continue;
+        }
+        let (name, value) = match line.find('=') {
+            Some(i) => (line[..i].trim().to_lowercase(), line[i + 1..].trim()),
+            None => continue,
+        };
+        // Values of the properties that we know about aren't case sensitive.
+        let value = value.to_lowercase();
+        match sections.last_mut() {
+            Some((_, properties)) => properties.push((name, value)),
+            // Properties before the first section are about the file itself.
+            None => if name == "root" {
+                root = value == "true";
+            },
+        }
+    }
+    (root, sections)
+}
+
+/// Does the section `glob` match the file at `relative`, which is relative
+/// to the directory of the `.editorconfig` file?
+fn matches_section(glob: &str, relative: &str) -> bool {
+    let path: Vec<char> = relative.chars().collect();
+    if glob.contains('/') {
+        let glob = glob.strip_prefix('/').unwrap_or(glob);
+        matches(&glob.chars().collect::<Vec<char>>(), &path)
+    } else {
+        // Globs without a slash can match a file in any directory.
+        let name: Vec<char> = match relative.rfind('/') {
+            Some(i) => relative[i + 1..].chars().collect(),
+            None => path,
+        };
+        matches(&glob.chars().collect::<Vec<char>>(), &name)
+    }
+}
+
+/// Matches `text` against an EditorConfig glob.
+/// `*` matches anything but a `/`, `**` matches anything, `?` matches one character,
+/// `[abc]` and `[!abc]` match sets of characters, `{a,b}` matches either alternative
+/// and `{1..10}` matches a number in a range.
+fn matches(glob: &[char], text: &[char]) -> bool {
+    let (first, rest) = match glob.split_first() {
+        Some(split) => split,
+        None => return text.is_empty(),
+    };
+    match *first {
+        '*' if rest.first() == Some(&'*') => {
+            (0..=text.len()).any(|i| matches(&rest[1..], &text[i..]))
+        },
+        '*' => {
+            let end = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=end).any(|i| matches(rest, &text[i..]))
+        },
+        '?' => text.first().is_some_and(|&c| c != '/') && matches(rest, &text[1..]),
+        '[' => match rest.iter().position(|&c| c == ']') {
+            Some(end) if end > 0 => {
+                let (negated, set) = match rest[0] {
+                    '!' => (true, &rest[1..end]),
+                    _ => (false, &rest[..end]),
+                };
+                match text.first() {
+                    Some(&c) if c != '/' && in_set(set, c) != negated => matches(&rest[end + 1..], &text[1..]),
+                    _ => false,
+                }
+            },
+            _ => literal('[', rest, text),
+        },
+        '{' => match closing_brace(rest) {
+            Some(end) => {
+                let inside = &rest[..end];
+                let after = &rest[end + 1..];
+                if let Some((low, high)) = number_range(inside) {
+                    // Try every run of digits at the start of the text.
+                    let digits = text.iter().enumerate()
+                        .take_while(|&(i, &c)| c.is_ascii_digit() || (i == 0 && c == '-'))
+                        .count();
+                    return (1..=digits).any(|len| {
+                        let number: String = text[..len].iter().collect();
+                        number.parse::<i64>().is_ok_and(|n| n >= low && n <= high)
+                            && matches(after, &text[len..])
+                    });
+                }
+                let alternatives = split_alternatives(inside);
+                if alternatives.len() < 2 {
+                    return literal('{', rest, text);
+                }
+                alternatives.iter().any(|alternative| {
+                    let mut glob = alternative.to_vec();
+                    glob.extend_from_slice(after);
+                    matches(&glob, text)
+                })
+            },
+            None => literal('{', rest, text),
+        },
+        '\\' if !rest.is_empty() => literal(rest[0], &rest[1..], text),
+        c => literal(c, rest, text),
+    }
+}
+
+/// Matches the character `c` and then the rest of the glob.
+fn literal(c: char, rest: &[char], text: &[char]) -> bool {
+    text.first() == Some(&c) && matches(rest, &text[1..])
+}
+
+/// Is `c` in a character set like `a-z_`?
+fn in_set(set: &[char], c: char) -> bool {
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == '-' {
+            if set[i] <= c && c <= set[i + 2] {
+                return true;
+            }
+            i += 3;
+        } else {
+            if set[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+/// Finds the `}` that closes a `{`, allowing for nested braces.
+fn closing_brace(glob: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, &c) in glob.iter().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Splits the inside of braces at the commas that aren't in nested braces.
+fn split_alternatives(glob: &[char]) -> Vec<&[char]> {
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, &c) in glob.iter().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(&glob[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    alternatives.push(&glob[start..]);
+    alternatives
+}
+
+/// Parses the inside of braces like `{1..10}` as a range of numbers.
+fn number_range(glob: &[char]) -> Option<(i64, i64)> {
+    let text: String = glob.iter().collect();
+    let dots = text.find("..")?;
+    let low = text[..dots].parse().ok()?;
+    let high = text[dots + 2..].parse().ok()?;
+    Some((low, high))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_root_and_sections() {
+        let (root, sections) = parse("root = TRUE\n\n# comment\n[*.rs]\nindent_style = Space\n; x\n[Makefile]\nindent_style=tab\n");
+        assert!(root);
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].0, "*.rs");
+        assert_eq!(sections[0].1, vec![(String::from("indent_style"), String::from("space"))]);
+        assert_eq!(sections[1].0, "Makefile");
+        assert_eq!(sections[1].1, vec![(String::from("indent_style"), String::from("tab"))]);
+    }
+
+    #[test]
+    fn root_inside_a_section_is_a_property() {
+        let (root, sections) = parse("[*]\nroot = true\n");
+        assert!(!root);
+        assert_eq!(sections[0].1.len(), 1);
+    }
+
+    #[test]
+    fn stars_and_question_marks() {
+        assert!(matches_section("*", "main.rs"));
+        assert!(matches_section("*.rs", "src/main.rs"));
+        assert!(!matches_section("*.rs", "src/main.rsx"));
+        assert!(!matches_section("src/*.rs", "src/a/main.rs"));
+        assert!(matches_section("src/**.rs", "src/a/main.rs"));
+        assert!(matches_section("/src/*.rs", "src/main.rs"));
+        assert!(matches_section("?.c", "a.c"));
+        assert!(!matches_section("?.c", "ab.c"));
+    }
+
+    #[test]
+    fn character_sets() {
+        assert!(matches_section("[abc].txt", "b.txt"));
+        assert!(!matches_section("[!abc].txt", "b.txt"));
+        assert!(matches_section("[!abc].txt", "d.txt"));
+        assert!(matches_section("[]", "[]"));
+        assert!(in_set(&['a', '-', 'z', '_'], 'q'));
+        assert!(in_set(&['a', '-', 'z', '_'], '_'));
+        assert!(!in_set(&['a', '-', 'z', '_'], '-'));
+        assert!(in_set(&['a', '-'], '-'));
+    }
+
+    #[test]
+    fn braces() {
+        assert!(matches_section("*.{js,ts}", "a.ts"));
+        assert!(!matches_section("*.{js,ts}", "a.rs"));
+        assert!(matches_section("{a,{b,c}}.txt", "c.txt"));
+        assert!(matches_section("{single}.txt", "{single}.txt"));
+        assert!(matches_section("\\{a,b\\}", "{a,b}"));
+        assert!(matches_section("file{1..10}.txt", "file10.txt"));
+        assert!(!matches_section("file{1..10}.txt", "file11.txt"));
+        assert!(matches_section("{-5..5}", "-3"));
+    }
+
+    #[test]
+    fn number_ranges() {
+        assert_eq!(number_range(&"1..10".chars().collect::<Vec<char>>()), Some((1, 10)));
+        assert_eq!(number_range(&"-3..3".chars().collect::<Vec<char>>()), Some((-3, 3)));
+        assert_eq!(number_range(&"a..b".chars().collect::<Vec<char>>()), None);
+        assert_eq!(number_range(&"1,2".chars().collect::<Vec<char>>()), None);
+    }
+}
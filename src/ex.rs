@@ -0,0 +1,582 @@
+use regex::{Regex, RegexBuilder};
+use toml::value::{Table, Value};
+use backend::{Buffer, Sort};
+use commands::{Command, ALL_COMMANDS};
+use completion::{self, Completion};
+use config::Settings;
+use cursor::Cursor;
+use columns::line_len;
+
+/// The ex commands, each with the shortest abbreviation that it can be typed as.
+static EX_COMMANDS: &'static [(&'static str, &'static str)] = &[
+    ("write", "w"),
+    ("wall", "wa"),
+    ("wq", "wq"),
+    ("xit", "x"),
+    ("quit", "q"),
+    ("qall", "qa"),
+    ("edit", "e"),
+    ("substitute", "s"),
+    ("sort", "sor"),
+    ("set", "se"),
+    ("bnext", "bn"),
+    ("bprevious", "bp"),
+    ("bdelete", "bd"),
+    ("buffer", "b"),
+    ("ls", "ls"),
+];
+
+/// Other names that the settings can be given in `:set`, like the ones vi uses.
+static SETTING_NAMES: &'static [(&'static str, &'static str)] = &[
+    ("tabwidth", "tab_width"),
+    ("ts", "tab_width"),
+    ("tabstop", "tab_width"),
+    ("indentsize", "indent_size"),
+    ("sw", "indent_size"),
+    ("shiftwidth", "indent_size"),
+    ("expandtabs", "expand_tabs"),
+    ("expandtab", "expand_tabs"),
+    ("et", "expand_tabs"),
+    ("number", "line_numbers"),
+    ("nu", "line_numbers"),
+    ("linenumbers", "line_numbers"),
+    ("autopairs", "auto_pairs"),
+];
+
+/// A range of lines that a command works on, from `start` to `end`
+/// including both. Lines count from 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A search and replace, from `:s/pattern/replacement/flags`.
+#[derive(Debug)]
+pub struct Substitution {
+    pub regex: Regex,
+    /// The replacement, in the form that the regex crate expects.
+    pub replacement: String,
+    /// Replace every match on each line, not just the first.
+    pub global: bool,
+}
+
+/// A command typed at the `:` prompt.
+#[derive(Debug)]
+pub enum Ex {
+    /// Go to a line, like `:42`.
+    Goto(usize),
+    /// Save the current buffer, to a new file if one is given.
+    Write(Option<String>),
+    WriteAll,
+    /// Save the current buffer and quit. The bool is true for `:x`,
+    /// which only saves if there are changes.
+    WriteQuit(bool),
+    /// Quit. The bool is true if unsaved changes should be thrown away.
+    Quit(bool),
+    Edit(String),
+    Substitute(LineRange, Substitution),
+    /// Sort the lines, like `:sort nu`.
+    Sort(LineRange, Sort),
+    /// Change or show the settings, like `tabwidth=4`.
+    Set(Vec<String>),
+    NextBuffer,
+    PreviousBuffer,
+    /// Close the current buffer. The bool is true if unsaved changes should be thrown away.
+    DeleteBuffer(bool),
+    /// Switch to the buffer with this number, counting from 1.
+    Buffer(usize),
+    ListBuffers,
+    /// Run one of the commands that can be bound to keys, by its name.
+    /// The editor checks that there is such a command, since scripts can add them.
+    Command(String),
+}
+
+/// Parses a command line. `cursor_line` is the line that `.` means and
+/// `lines` is the number of lines in the buffer.
+pub fn parse(text: &str, cursor_line: usize, lines: usize) -> Result<Ex, String> {
+    let text = text.trim();
+    let (range, rest) = parse_range(text, cursor_line, lines)?;
+    let name_len = rest.find(|c: char| !(c.is_alphabetic() || c == '-')).unwrap_or(rest.len());
+    let (name, rest) = rest.split_at(name_len);
+    let (bang, argument) = match rest.strip_prefix('!') {
+        Some(rest) => (true, rest.trim()),
+        None => (false, rest.trim()),
+    };
+    if name.is_empty() {
+        // A line number on its own goes to that line.
+        return match range {
+            Some(range) if argument.is_empty() && !bang => Ok(Ex::Goto(range.end)),
+            _ => Err(format!("Not an editor command: {}", text)),
+        };
+    }
+    let full_name = match full_name(name) {
+        Some(full_name) => full_name,
+        None => {
+            return if range.is_none() && argument.is_empty() {
+                Ok(Ex::Command(String::from(name)))
+            } else if Command::from_name(name).is_some() {
+                Err(format!("{} doesn't take any arguments", name))
+            } else {
+                Err(format!("Not an editor command: {}", name))
+            };
+        },
+    };
+    if full_name == "substitute" {
+        let range = range.unwrap_or(LineRange { start: cursor_line, end: cursor_line });
+        return Ok(Ex::Substitute(range, parse_substitution(rest)?));
+    }
+    if full_name == "sort" {
+        // Like vi, sorting without a range sorts the whole buffer.
+        let range = range.unwrap_or(LineRange { start: 0, end: lines - 1 });
+        return Ok(Ex::Sort(range, parse_sort(argument)?));
+    }
+    if range.is_some() {
+        return Err(format!(":{} doesn't take a range", full_name));
+    }
+    let no_argument = |ex: Ex| {
+        if argument.is_empty() { Ok(ex) } else { Err(format!(":{} doesn't take an argument", full_name)) }
+    };
+    match full_name {
+        "write" if argument.is_empty() => Ok(Ex::Write(None)),
+        "write" => Ok(Ex::Write(Some(String::from(argument)))),
+        "wall" => no_argument(Ex::WriteAll),
+        "wq" => no_argument(Ex::WriteQuit(false)),
+        "xit" => no_argument(Ex::WriteQuit(true)),
+        "quit" | "qall" => no_argument(Ex::Quit(bang)),
+        "edit" if argument.is_empty() => Err(String::from(":edit needs a filename")),
+        "edit" => Ok(Ex::Edit(String::from(argument))),
+        "set" => Ok(Ex::Set(argument.split_whitespace().map(String::from).collect())),
+        "bnext" => no_argument(Ex::NextBuffer),
+        "bprevious" => no_argument(Ex::PreviousBuffer),
+        "bdelete" => no_argument(Ex::DeleteBuffer(bang)),
+        "buffer" => match argument.parse::<usize>() {
+            Ok(number) if number > 0 => Ok(Ex::Buffer(number)),
+            _ => Err(String::from(":buffer needs a buffer number")),
+        },
+        _ => no_argument(Ex::ListBuffers),
+    }
+}
+
+/// Finds the ex command that `name` is the full name or an abbreviation of.
+fn full_name(name: &str) -> Option<&'static str> {
+    EX_COMMANDS.iter()
+        .find(|&&(full, short)| full.starts_with(name) && name.len() >= short.len())
+        .map(|&(full, _)| full)
+}
+
+/// Parses the range at the start of a command, like `%`, `3,$` or `.,.+2`.
+/// Returns the range if there is one, and the rest of the text.
+fn parse_range(text: &str, cursor_line: usize, lines: usize) -> Result<(Option<LineRange>, &str), String> {
+    if let Some(rest) = text.strip_prefix('%') {
+        return Ok((Some(LineRange { start: 0, end: lines - 1 }), rest));
+    }
+    let (start, rest) = parse_address(text, cursor_line, lines)?;
+    let start = match start {
+        Some(start) => start,
+        None => return Ok((None, rest)),
+    };
+    let (end, rest) = match rest.strip_prefix(',') {
+        Some(rest) => match parse_address(rest, cursor_line, lines)? {
+            (Some(end), rest) => (end, rest),
+            (None, _) => return Err(String::from("Expected a line after the ,")),
+        },
+        None => (start, rest),
+    };
+    if start > end {
+        return Err(String::from("Backwards range"));
+    }
+    Ok((Some(LineRange { start: start, end: end }), rest))
+}
+
+/// Parses one line address, like `42`, `.`, `$` or `.+3`, and returns
+/// the line it means, if there was one, and the rest of the text.
+fn parse_address(text: &str, cursor_line: usize, lines: usize) -> Result<(Option<usize>, &str), String> {
+    let number = |text: &str| -> (Option<usize>, usize) {
+        let len = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+        (text[..len].parse().ok(), len)
+    };
+    // Lines are numbered from 1 here, and 0 is before the first line.
+    let (mut line, mut rest) = match text.chars().next() {
+        Some('.') => (Some(cursor_line as i64 + 1), &text[1..]),
+        Some('$') => (Some(lines as i64), &text[1..]),
+        Some(c) if c.is_ascii_digit() => {
+            let (n, len) = number(text);
+            (n.map(|n| n as i64), &text[len..])
+        },
+        _ => (None, text),
+    };
+    // Any number of offsets, which are from the cursor if there was no line.
+    while let Some(sign) = rest.chars().next().filter(|&c| c == '+' || c == '-') {
+        let (n, len) = number(&rest[1..]);
+        let offset = n.unwrap_or(1) as i64;
+        let base = line.unwrap_or(cursor_line as i64 + 1);
+        line = Some(if sign == '+' { base + offset } else { base - offset });
+        rest = &rest[1 + len..];
+    }
+    match line {
+        None => Ok((None, rest)),
+        Some(line) if line < 1 || line > lines as i64 => Err(format!("There is no line {}", line)),
+        Some(line) => Ok((Some(line as usize - 1), rest)),
+    }
+}
+
+/// Parses the flags of a sort, which are `n` to sort by number,
+/// `r` to reverse the order and `u` to drop lines that are the same.
+pub fn parse_sort(flags: &str) -> Result<Sort, String> {
+    let mut sort = Sort::default();
+    for c in flags.chars().filter(|c| !c.is_whitespace()) {
+        match c {
+            'n' => sort.numeric = true,
+            'r' => sort.reverse = true,
+            'u' => sort.unique = true,
+            _ => return Err(format!("Unknown sort flag: {}", c)),
+        }
+    }
+    Ok(sort)
+}
+
+/// Parses the `/pattern/replacement/flags` after `:s`.
+/// Any punctuation can be used instead of the `/`.
+fn parse_substitution(text: &str) -> Result<Substitution, String> {
+    let mut chars = text.chars();
+    let delimiter = match chars.next() {
+        Some(c) if !c.is_alphanumeric() && !c.is_whitespace() && c != '\\' => c,
+        _ => return Err(String::from("Expected :s/pattern/replacement/")),
+    };
+    let (pattern, rest) = split_at_delimiter(chars.as_str(), delimiter);
+    let (replacement, flags) = match rest {
+        Some(rest) => split_at_delimiter(rest, delimiter),
+        None => (String::new(), None),
+    };
+    if pattern.is_empty() {
+        return Err(String::from("Empty search pattern"));
+    }
+    let mut global = false;
+    let mut ignore_case = false;
+    for flag in flags.unwrap_or("").trim().chars() {
+        match flag {
+            'g' => global = true,
+            'i' => ignore_case = true,
+            'I' => ignore_case = false,
+            _ => return Err(format!("Unknown flag for :s: {}", flag)),
+        }
+    }
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| {
+            // The regex crate's errors are several lines long, with the problem last.
+            let error = e.to_string();
+            let problem = error.lines().last().unwrap_or("").trim_start_matches("error: ").to_string();
+            format!("Bad pattern: {}", problem)
+        })?;
+    Ok(Substitution {
+        regex: regex,
+        replacement: replacement_template(&replacement),
+        global: global,
+    })
+}
+
+/// Splits `text` at the first `delimiter` that isn't escaped with a `\`.
+/// Escaped delimiters lose their `\`, and other escapes are kept.
+fn split_at_delimiter(text: &str, delimiter: char) -> (String, Option<&str>) {
+    let mut part = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == delimiter {
+            return (part, Some(&text[i + c.len_utf8()..]));
+        }
+        if c == '\\' {
+            match chars.next() {
+                Some((_, next)) if next == delimiter => part.push(next),
+                Some((_, next)) => {
+                    part.push('\\');
+                    part.push(next);
+                },
+                None => part.push('\\'),
+            }
+            continue;
+        }
+        part.push(c);
+    }
+    (part, None)
+}
+
+/// Turns a vi style replacement, where `&` is the match and `\1` is a group,
+/// into the regex crate's `${0}` and `${1}`.
+fn replacement_template(replacement: &str) -> String {
+    let mut template = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => template.push_str("${0}"),
+            '$' => template.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(d) if d.is_ascii_digit() => template.push_str(&format!("${{{}}}", d)),
+                Some('n') => template.push('\n'),
+                Some('t') => template.push('\t'),
+                Some(other) => template.push(other),
+                None => template.push('\\'),
+            },
+            c => template.push(c),
+        }
+    }
+    template
+}
+
+/// Does the search and replace on the lines in `range` of `buffer`.
+/// Returns the number of replacements made and the last line changed.
+pub fn substitute(buffer: &mut Buffer, range: LineRange, substitution: &Substitution) -> (usize, Option<usize>) {
+    let mut count = 0;
+    let mut last = None;
+    // Go from the bottom up so that replacements with newlines in
+    // don't move the lines that are still to do.
+    for line in (range.start..range.end + 1).rev() {
+        let text = buffer.lines()[line].clone();
+        let matches = substitution.regex.find_iter(&text).count();
+        if matches == 0 {
+            continue;
+        }
+        let replaced = if substitution.global {
+            count += matches;
+            substitution.regex.replace_all(&text, &substitution.replacement[..])
+        } else {
+            count += 1;
+            substitution.regex.replace(&text, &substitution.replacement[..])
+        };
+        let start = Cursor::new(line, 0);
+        buffer.delete_between(start, Cursor::new(line, line_len(&text)));
+        let end = buffer.insert_text_at(start, &replaced);
+        if last.is_none() {
+            last = Some(end.line);
+        }
+    }
+    (count, last)
+}
+
+/// Applies the `:set` arguments to the settings of `buffer`. Each one is `name=value`,
+/// `name` to turn it on, `noname` to turn it off, or `name?` to show it.
+/// Returns what to show the user.
+pub fn set(buffer: &mut Buffer, arguments: &[String]) -> Result<Option<String>, String> {
+    let settings = buffer.settings();
+    if arguments.is_empty() {
+        let names = ["tab_width", "indent_size", "expand_tabs", "line_numbers", "margin",
+                     "trim_trailing_whitespace", "insert_final_newline", "auto_pairs",
+                     "auto_indent"];
+        let values: Vec<String> = names.iter().map(|name| describe(settings, name).unwrap()).collect();
+        return Ok(Some(values.join(" ")));
+    }
+    let mut shown = Vec::new();
+    let mut table = Table::new();
+    for argument in arguments {
+        if let Some(name) = argument.strip_prefix('?').or_else(|| argument.strip_suffix('?')) {
+            match describe(settings, setting_name(name)) {
+                Some(value) => shown.push(value),
+                None => return Err(format!("Unknown setting: {}", name)),
+            }
+            continue;
+        }
+        let (name, value) = match argument.find('=') {
+            Some(i) => {
+                let value = &argument[i + 1..];
+                let value = match value.parse::<i64>() {
+                    Ok(n) => Value::Integer(n),
+                    Err(_) => match value {
+                        "true" | "on" => Value::Boolean(true),
+                        "false" | "off" => Value::Boolean(false),
+                        _ => Value::String(String::from(value)),
+                    },
+                };
+                (setting_name(&argument[..i]), value)
+            },
+            None => match argument.strip_prefix("no") {
+                Some(name) if describe(settings, setting_name(name)).is_some() => {
+                    (setting_name(name), Value::Boolean(false))
+                },
+                _ => (setting_name(argument), Value::Boolean(true)),
+            },
+        };
+        table.insert(String::from(name), value);
+    }
+    // The settings stay set for this buffer even when the config is reloaded.
+    let errors = buffer.override_settings(table);
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+    Ok(if shown.is_empty() { None } else { Some(shown.join(" ")) })
+}
+
+/// Returns the setting's real name if `name` is another name for it.
+fn setting_name(name: &str) -> &str {
+    SETTING_NAMES.iter().find(|&&(alias, _)| alias == name).map_or(name, |&(_, real)| real)
+}
+
+/// Describes the value of a setting, like `tab_width=4`.
+fn describe(settings: &Settings, name: &str) -> Option<String> {
+    let value = match name {
+        "tab_width" => settings.tab_width.to_string(),
+        "indent_size" => settings.indent_size.to_string(),
+        "expand_tabs" => settings.expand_tabs.to_string(),
+        "line_numbers" => settings.line_numbers.to_string(),
+        "margin" => settings.margin.map_or(String::from("0"), |m| m.to_string()),
+        "trim_trailing_whitespace" => settings.trim_trailing_whitespace.to_string(),
+        "insert_final_newline" => settings.insert_final_newline.to_string(),
+        "auto_pairs" => settings.auto_pairs.to_string(),
+        "auto_indent" => settings.auto_indent.to_string(),
+        _ => return None,
+    };
+    Some(format!("{}={}", name, value))
+}
+
+/// Completes the command line typed so far: the name of the command,
+/// or the filename for commands that take one.
+pub fn complete(input: &str) -> Completion {
+    let name_start = input.find(|c: char| c.is_alphabetic()).unwrap_or(input.len());
+    let (range, rest) = input.split_at(name_start);
+    let name_len = rest.find(|c: char| !(c.is_alphabetic() || c == '-')).unwrap_or(rest.len());
+    let (name, argument) = rest.split_at(name_len);
+    let with_range = |mut completion: Completion, before: &str| {
+        completion.text = format!("{}{}", before, completion.text);
+        completion
+    };
+    if argument.is_empty() {
+        let mut names: Vec<&str> = EX_COMMANDS.iter().map(|&(name, _)| name).collect();
+        names.extend(ALL_COMMANDS.iter().map(|command| command.name()));
+        return with_range(completion::complete_name(name, &names), range);
+    }
+    match full_name(name) {
+        Some("edit") | Some("write") if argument.starts_with(char::is_whitespace) => {
+            let path = argument.trim_start();
+            let before = &input[..input.len() - path.len()];
+            with_range(completion::complete_path(path), before)
+        },
+        _ => Completion { text: String::from(input), candidates: Vec::new() },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(text: &str) -> Result<(Option<LineRange>, &str), String> {
+        // The cursor is on the 5th of 10 lines.
+        parse_range(text, 4, 10)
+    }
+
+    fn lines(start: usize, end: usize) -> Option<LineRange> {
+        Some(LineRange { start: start, end: end })
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(range("s/a/b/"), Ok((None, "s/a/b/")));
+        assert_eq!(range("%s"), Ok((lines(0, 9), "s")));
+        assert_eq!(range("3"), Ok((lines(2, 2), "")));
+        assert_eq!(range(".,$d"), Ok((lines(4, 9), "d")));
+        assert_eq!(range(".,.+2"), Ok((lines(4, 6), "")));
+        assert_eq!(range("+,++"), Ok((lines(5, 6), "")));
+        assert_eq!(range("$-1"), Ok((lines(8, 8), "")));
+        assert_eq!(range("-3,."), Ok((lines(1, 4), "")));
+    }
+
+    #[test]
+    fn rejects_bad_ranges() {
+        assert_eq!(range("5,2"), Err(String::from("Backwards range")));
+        assert_eq!(range("0"), Err(String::from("There is no line 0")));
+        assert_eq!(range("11"), Err(String::from("There is no line 11")));
+        assert_eq!(range("$+1"), Err(String::from("There is no line 11")));
+        assert_eq!(range("1,"), Err(String::from("Expected a line after the ,")));
+    }
+
+    #[test]
+    fn parses_commands() {
+        let parse = |text| parse(text, 4, 10);
+        assert!(parse("42").is_err());
+        assert!(matches!(parse(" 7 "), Ok(Ex::Goto(6))));
+        assert!(matches!(parse("$"), Ok(Ex::Goto(9))));
+        assert!(matches!(parse("w"), Ok(Ex::Write(None))));
+        assert!(matches!(parse("w new.txt"), Ok(Ex::Write(Some(ref name))) if name == "new.txt"));
+        assert!(matches!(parse("wa"), Ok(Ex::WriteAll)));
+        assert!(matches!(parse("x"), Ok(Ex::WriteQuit(true))));
+        assert!(matches!(parse("q"), Ok(Ex::Quit(false))));
+        assert!(matches!(parse("quit!"), Ok(Ex::Quit(true))));
+        assert!(matches!(parse("qa!"), Ok(Ex::Quit(true))));
+        assert!(matches!(parse("bd!"), Ok(Ex::DeleteBuffer(true))));
+        assert!(matches!(parse("b 2"), Ok(Ex::Buffer(2))));
+        assert!(matches!(parse("se ts=8 noet"), Ok(Ex::Set(ref arguments)) if arguments.len() == 2));
+        assert!(matches!(parse("s/a/b/g"), Ok(Ex::Substitute(LineRange { start: 4, end: 4 }, _))));
+        assert!(matches!(parse("save-as"), Ok(Ex::Command(ref name)) if name == "save-as"));
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        let parse = |text| parse(text, 4, 10).err().unwrap();
+        assert_eq!(parse("b 0"), ":buffer needs a buffer number");
+        assert_eq!(parse("e"), ":edit needs a filename");
+        assert_eq!(parse("q now"), ":quit doesn't take an argument");
+        assert_eq!(parse("1,2w"), ":write doesn't take a range");
+        assert_eq!(parse("save-as x"), "save-as doesn't take any arguments");
+        assert_eq!(parse("nonsense x"), "Not an editor command: nonsense");
+        assert_eq!(parse("3!"), "Not an editor command: 3!");
+    }
+
+    #[test]
+    fn abbreviations_must_be_long_enough() {
+        assert_eq!(full_name("w"), Some("write"));
+        assert_eq!(full_name("wri"), Some("write"));
+        assert_eq!(full_name("s"), Some("substitute"));
+        assert_eq!(full_name("bn"), Some("bnext"));
+        assert_eq!(full_name("b"), Some("buffer"));
+        assert_eq!(full_name("writes"), None);
+    }
+
+    #[test]
+    fn parses_sorts() {
+        assert_eq!(parse_sort(""), Ok(Sort::default()));
+        assert_eq!(parse_sort("n u"), Ok(Sort { numeric: true, reverse: false, unique: true }));
+        assert_eq!(parse_sort("rn"), Ok(Sort { numeric: true, reverse: true, unique: false }));
+        assert_eq!(parse_sort("x"), Err(String::from("Unknown sort flag: x")));
+        assert!(matches!(parse("sor", 4, 10), Ok(Ex::Sort(LineRange { start: 0, end: 9 }, _))));
+        assert!(matches!(parse("2,3sort u", 4, 10),
+                         Ok(Ex::Sort(LineRange { start: 1, end: 2 }, Sort { unique: true, .. }))));
+    }
+
+    #[test]
+    fn splits_at_delimiters() {
+        assert_eq!(split_at_delimiter("a/b/", '/'), (String::from("a"), Some("b/")));
+        assert_eq!(split_at_delimiter("a\\/b/c", '/'), (String::from("a/b"), Some("c")));
+        assert_eq!(split_at_delimiter("a\\d+#x", '#'), (String::from("a\\d+"), Some("x")));
+        assert_eq!(split_at_delimiter("abc", '/'), (String::from("abc"), None));
+        assert_eq!(split_at_delimiter("abc\\", '/'), (String::from("abc\\"), None));
+    }
+
+    #[test]
+    fn substitutes() {
+        let mut buffer = Buffer::new();
+        buffer.insert_text_at(Cursor::new(0, 0), "a a\nb\na");
+        let substitution = match parse("%s/a/[&]/g", 0, 3) {
+            Ok(Ex::Substitute(_, substitution)) => substitution,
+            _ => panic!("expected a substitution"),
+        };
+        assert_eq!(substitute(&mut buffer, LineRange { start: 0, end: 2 }, &substitution), (3, Some(2)));
+        assert_eq!(buffer.lines(), ["[a] [a]", "b", "[a]"]);
+        assert_eq!(replacement_template("\\1$\\n"), "${1}$$\n");
+        assert!(parse_substitution("/(/").is_err());
+        assert!(parse_substitution("a/b/").is_err());
+    }
+
+    #[test]
+    fn sets_and_shows_settings() {
+        let mut buffer = Buffer::new();
+        assert_eq!(set(&mut buffer, &[String::from("ts=8"), String::from("et")]), Ok(None));
+        assert_eq!(buffer.settings().tab_width, 8);
+        assert!(buffer.settings().expand_tabs);
+        assert_eq!(set(&mut buffer, &[String::from("noet"), String::from("ts?")]),
+                   Ok(Some(String::from("tab_width=8"))));
+        assert!(!buffer.settings().expand_tabs);
+        assert!(set(&mut buffer, &[String::from("ts=99")]).is_err());
+        assert_eq!(buffer.settings().tab_width, 8);
+        assert_eq!(set(&mut buffer, &[String::from("nope?")]), Err(String::from("Unknown setting: nope")));
+    }
+}
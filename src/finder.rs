@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use ignore::WalkBuilder;
+
+/// The most files that we will list, so that opening the finder in a huge
+/// directory (like your home directory) doesn't take forever.
+const MAX_FILES: usize = 20_000;
+
+/// Lists the files under `root`, skipping hidden files and anything
+/// ignored by a .gitignore file. The paths are relative to `root`.
+pub fn list_files(root: &Path) -> Vec<String> {
+    let walker = WalkBuilder::new(root)
+        // Respect .gitignore files even if we aren't in a git repo.
+        .require_git(false)
+        .build();
+    let mut files = Vec::new();
+    for entry in walker.filter_map(Result::ok) {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let path = entry.path().strip_prefix(root).unwrap_or_else(|_| entry.path());
+        files.push(path.to_string_lossy().into_owned());
+        if files.len() == MAX_FILES {
+            break;
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Returns the first `lines` lines of the file at `path` to show as a preview.
+pub fn preview(path: &str, lines: usize) -> Vec<String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return vec![format!("Can't preview {}: {}", path, e)],
+    };
+    let mut preview = Vec::with_capacity(lines);
+    for line in BufReader::new(file).split(b'\n').take(lines) {
+        match line {
+            Ok(bytes) => match String::from_utf8(bytes) {
+                Ok(line) => preview.push(line.trim_end_matches('\r').to_string()),
+                Err(_) => return vec![String::from("(binary file)")],
+            },
+            Err(e) => {
+                preview.push(format!("Can't preview {}: {}", path, e));
+                break;
+            },
+        }
+    }
+    preview
+}
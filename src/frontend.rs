@@ -1,71 +1,354 @@
-use std::io::{Read, Write, Stdin, stdin, Stdout, stdout};
+use std::io;
+use std::io::{Write, stdin, Stdout, stdout};
 use std::ops::Drop;
+use std::cmp;
+use std::mem;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
 use termion;
-use termion::{clear, color};
+use termion::{clear, color, style};
+use termion::event::{Event, Key};
+use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 use left_pad::leftpad;
-use cursor::Cursor;
+use window::{Pane, Rect};
+use picker::{Picker, PickerItem, Picked};
+use completion::{self, Completion};
+use line_editor::LineEditor;
+use history::History;
+use unicode_width::{UnicodeWidthStr, UnicodeWidthChar};
+use paths;
+use keymap;
+use columns;
+use backend::Buffer;
+use config::{Config, Settings};
+
+/// The number of columns taken up by the line numbers on the left of each pane.
+const GUTTER_WIDTH: usize = 4;
+
+/// Returns the number of columns on the left of a pane that come before the text.
+pub fn gutter_width(settings: &Settings) -> usize {
+    if settings.line_numbers { GUTTER_WIDTH } else { 0 }
+}
+
+/// A part of a line that is drawn highlighted, like a selection.
+/// `start` and `end` are visual columns, and `end` isn't included.
+#[derive(Clone, Copy, Debug)]
+pub struct Highlight {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+    pub kind: HighlightKind,
+}
+
+/// The ways that a highlight can be drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HighlightKind {
+    /// Selected text, and the other cursors, which are drawn inverted.
+    Selection,
+    /// The bracket at the cursor and the one that matches it.
+    Bracket,
+    /// A bracket at the cursor that nothing matches.
+    UnmatchedBracket,
+}
 
 // The Frontend is responsible for rendering the state of the editor
 // to the screen and interacting with the user.
 pub struct Frontend {
-    stdin: Stdin,
+    /// The events typed by the user.
+    /// They are read on another thread, so that we can stop waiting for them
+    /// every now and then. That thread keeps one iterator around for the whole
+    /// session because termion sometimes reads more bytes than it needs for one
+    /// event, and those bytes would be lost if the iterator was dropped.
+    events: Receiver<io::Result<Event>>,
     stdout: termion::raw::RawTerminal<Stdout>,
+    /// The line editor used for prompts.
+    line: LineEditor,
+    /// What the user has typed into prompts before.
+    history: History,
+    /// The user's configuration, for the colors.
+    config: Config,
+    /// The keys typed since a macro started recording.
+    recording: Option<Vec<Key>>,
+    /// Keys from a macro that is being played, which are read before anything the user types.
+    replay: VecDeque<Key>,
+    /// Set when the user typed something while a macro was playing, which stops it.
+    interrupted: bool,
+    /// What the user typed to stop a macro, which is read next.
+    typed_ahead: Option<io::Result<Event>>,
 }
 
 impl Frontend {
     /// Creates a new Frontend
-    pub fn new() -> Frontend {
-        let in_ = stdin();
+    pub fn new(config: Config) -> Frontend {
         let out = stdout().into_raw_mode().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for event in stdin().events() {
+                if sender.send(event.map(keymap::translate_event)).is_err() {
+                    break;
+                }
+            }
+        });
         Frontend {
-            stdin: in_,
+            events: receiver,
             stdout: out,
+            line: LineEditor::new(),
+            history: History::load(),
+            config: config,
+            recording: None,
+            replay: VecDeque::new(),
+            interrupted: false,
+            typed_ahead: None,
+        }
+    }
+    /// Replaces the configuration.
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+    }
+    /// Waits for the next event from the user.
+    /// Returns None if there are no more events.
+    pub fn next_event(&mut self) -> Option<io::Result<Event>> {
+        if let Some(key) = self.next_replayed() {
+            return Some(Ok(Event::Key(key)));
+        }
+        let event = match self.typed_ahead.take() {
+            Some(event) => Some(event),
+            None => self.events.recv().ok(),
+        };
+        if let Some(Ok(ref event)) = event {
+            self.record(event);
+        }
+        event
+    }
+    /// Waits for the next event from the user, but gives up after `timeout`.
+    pub fn next_event_timeout(&mut self, timeout: Duration)
+                              -> Result<io::Result<Event>, RecvTimeoutError> {
+        if let Some(key) = self.next_replayed() {
+            return Ok(Ok(Event::Key(key)));
+        }
+        let event = match self.typed_ahead.take() {
+            Some(event) => Ok(event),
+            None => self.events.recv_timeout(timeout),
+        };
+        if let Ok(Ok(ref event)) = event {
+            self.record(event);
+        }
+        event
+    }
+    /// Starts remembering the keys that the user types, for a macro.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+    /// Stops recording, and returns the keys typed since it started.
+    pub fn stop_recording(&mut self) -> Option<Vec<Key>> {
+        self.recording.take()
+    }
+    /// Returns how many keys have been recorded, if we are recording.
+    pub fn recorded_len(&self) -> Option<usize> {
+        self.recording.as_ref().map(|keys| keys.len())
+    }
+    /// Plays `keys` back, as if the user typed them before anything else.
+    pub fn replay(&mut self, keys: &[Key]) {
+        for &key in keys.iter().rev() {
+            self.replay.push_front(key);
+        }
+    }
+    /// Throws away the keys that haven't been played back yet.
+    pub fn stop_replay(&mut self) {
+        self.replay.clear();
+    }
+    /// Are there keys waiting to be played back?
+    pub fn is_replaying(&self) -> bool {
+        !self.replay.is_empty()
+    }
+    /// Returns true, once, if a macro was stopped because the user typed something.
+    pub fn take_interrupted(&mut self) -> bool {
+        mem::replace(&mut self.interrupted, false)
+    }
+    /// Returns the next key to play back, unless the user has typed something,
+    /// which stops the macro so that what they typed is read instead.
+    fn next_replayed(&mut self) -> Option<Key> {
+        if self.replay.is_empty() {
+            return None;
+        }
+        if let Ok(event) = self.events.try_recv() {
+            self.replay.clear();
+            self.interrupted = true;
+            self.typed_ahead = Some(event);
+            return None;
+        }
+        self.replay.pop_front()
+    }
+    /// Remembers `event` if a macro is being recorded.
+    fn record(&mut self, event: &Event) {
+        if let (Some(ref mut keys), &Event::Key(key)) = (&mut self.recording, event) {
+            keys.push(key);
+        }
+    }
+    /// Waits for the next key press, ignoring other events.
+    /// Returns None if there are no more keys or reading them failed.
+    fn next_key(&mut self) -> Option<Key> {
+        loop {
+            match self.next_event() {
+                Some(Ok(Event::Key(key))) => return Some(key),
+                Some(Ok(_)) => {},
+                Some(Err(_)) | None => return None,
+            }
         }
     }
     /// Clears the screen
     pub fn clear_screen(&mut self) {
         write!(self.stdout, "{}", clear::All).unwrap();
     }
-    /// Draws the state of the editor to the screen.
-    pub fn draw(&mut self, cursor: &Cursor, filename: &Option<String>, lines: &[String]) {
-        let (width, height) = self.terminal_size();
+    /// Draws a tab for each of the `tabs` across row `y` of the screen,
+    /// highlighting the `current` one. If there are too many tabs to fit
+    /// then the strip scrolls so that the current tab is visible.
+    pub fn draw_tab_bar(&mut self, y: usize, tabs: &[String], current: usize) {
+        let (width, _) = self.terminal_size();
+        let labels: Vec<String> = tabs.iter().map(|t| format!(" {} ", t)).collect();
+        let widths: Vec<usize> = labels.iter().map(|l| l.width() + 1).collect();
+        // Leave room for the arrows that show there are more tabs.
+        let room = width.saturating_sub(2);
+        // Find the first tab to show, so that the current one fits.
+        let mut first = 0;
+        while first < current && widths[first..current + 1].iter().sum::<usize>() > room {
+            first += 1;
+        }
+        self.goto_term(0, y as u16);
+        let mut used = 0;
+        let colors = self.config.colors.clone();
+        write!(&mut self.stdout, "{}{}{}",
+               color::Bg(colors.tab_bar),
+               color::Fg(colors.title_text),
+               if first > 0 { "<" } else { " " }).unwrap();
+        let mut last = first;
+        for (i, label) in labels.iter().enumerate().skip(first) {
+            if used + widths[i] > room {
+                break;
+            }
+            if i == current {
+                write!(&mut self.stdout, "{}{}{}",
+                       color::Bg(colors.tab_current), label, color::Bg(colors.tab_bar)).unwrap();
+            } else {
+                write!(&mut self.stdout, "{}", label).unwrap();
+            }
+            write!(&mut self.stdout, "\u{2502}").unwrap();
+            used += widths[i];
+            last = i + 1;
+        }
+        write!(&mut self.stdout, "{}{}{}{}",
+               leftpad("", room - used),
+               if last < labels.len() { ">" } else { " " },
+               color::Fg(color::Reset),
+               color::Bg(color::Reset)).unwrap();
+    }
+    /// Draws a pane showing `buffer` in the `area` of the screen.
+    /// `pane` says which part of the text is visible, and `focused` says
+    /// whether the pane should be drawn as the one with focus.
+    /// `highlights` are the parts of the text to draw highlighted.
+    pub fn draw_pane(&mut self, area: &Rect, pane: &Pane, focused: bool, buffer: &Buffer,
+                     highlights: &[Highlight]) {
+        let (filename, lines, settings) = (&buffer.filename, buffer.lines(), buffer.settings());
+        let colors = self.config.colors.clone();
+        let (screen_width, _) = self.terminal_size();
         let num_lines = lines.len();
-        // The index of the first line of text that is rendered.
-        let start = if cursor.line > height { cursor.line - height } else { 0 };
+        // Panes that aren't on the right edge of the screen use their last
+        // column to separate themselves from the pane to their right.
+        let separator = area.x + area.width < screen_width;
+        let width = if separator { area.width - 1 } else { area.width };
         // The filename of the current buffer or a no filename message.
         let name = filename.clone().unwrap_or_else(|| String::from("**no filename**"));
-        let padding = (width - name.len()) / 2;
-        let need_extra = padding*2+name.len() != width;
-        self.goto_term(0, 0);
-        // Draw the title bar.
-        write!(&mut self.stdout, "{}{}{}{}{}{}{}{}",
-               color::Bg(color::White),
-               color::Fg(color::Black),
-               leftpad(" ", padding),
-               name,
-               leftpad(" ", padding),
-               if need_extra { " " } else { "" },
+        let padding = width.saturating_sub(name.chars().count()) / 2;
+        self.goto_term(area.x as u16, area.y as u16);
+        // Draw the title bar. The pane with focus gets a brighter one.
+        if focused {
+            write!(&mut self.stdout, "{}", color::Bg(colors.title)).unwrap();
+        } else {
+            write!(&mut self.stdout, "{}", color::Bg(colors.title_inactive)).unwrap();
+        }
+        write!(&mut self.stdout, "{}{}{}{}",
+               color::Fg(colors.title_text),
+               fit(&format!("{}{}", leftpad("", padding), name), width),
                color::Fg(color::Reset),
                color::Bg(color::Reset),
         ).unwrap();
         // Draw the lines of text.
-        for (y, line_number) in (start..start + height - 1).enumerate() {
-            self.goto_term(0, (y + 1) as u16);
-            if line_number < num_lines {
-                // Draw the line of text
-                write!(self.stdout, "{}{}{} {}",
-                       color::Fg(color::Cyan),
-                       leftpad(format!("{}", line_number + 1), 3),
-                       color::Fg(color::Reset),
-                       lines[line_number],
-                ).unwrap();
+        let gutter = gutter_width(settings);
+        let text_width = width.saturating_sub(gutter);
+        // The margin's column on the screen, if it is in view.
+        let margin = settings.margin
+            .and_then(|column| column.checked_sub(pane.left))
+            .filter(|&column| column < text_width);
+        for y in 1..area.height {
+            let line_number = pane.top + y - 1;
+            self.goto_term(area.x as u16, (area.y + y) as u16);
+            let text = if line_number < num_lines {
+                columns::render(&lines[line_number], pane.left, text_width, settings.tab_width)
             } else {
-                // Draw a ~ to show that there is no line.
-                write!(self.stdout, "{}  ~{}",
-                       color::Fg(color::Cyan),
-                       color::Fg(color::Reset),
-                ).unwrap();
+                String::new()
+            };
+            if gutter > 0 {
+                // Draw the line number, or a ~ to show that there is no line.
+                let number = if line_number < num_lines {
+                    format!("{}", line_number + 1)
+                } else {
+                    String::from("~")
+                };
+                write!(self.stdout, "{}{}{} ",
+                       color::Fg(colors.line_numbers),
+                       leftpad(number, gutter - 1),
+                       color::Fg(color::Reset)).unwrap();
+            }
+            write!(self.stdout, "{}", fit(&text, text_width)).unwrap();
+            if let Some(column) = margin {
+                // Draw the margin over the top of the text,
+                // unless a wide character is sitting on it.
+                let mut at = 0;
+                let mut under = Some(' ');
+                for c in text.chars() {
+                    let width = c.width().unwrap_or(0);
+                    if at + width > column {
+                        under = if at == column && width == 1 { Some(c) } else { None };
+                        break;
+                    }
+                    at += width;
+                }
+                if let Some(c) = under {
+                    self.goto_term((area.x + gutter + column) as u16, (area.y + y) as u16);
+                    write!(self.stdout, "{}{}{}",
+                           color::Bg(colors.margin), c, color::Bg(color::Reset)).unwrap();
+                }
+            }
+        }
+        for highlight in highlights {
+            if highlight.line < pane.top || highlight.line + 1 >= pane.top + area.height {
+                continue;
+            }
+            let start = cmp::max(highlight.start, pane.left);
+            let end = cmp::min(highlight.end, pane.left + text_width);
+            if start >= end {
+                continue;
+            }
+            let text = columns::render(&lines[highlight.line], start, end - start, settings.tab_width);
+            self.goto_term((area.x + gutter + start - pane.left) as u16,
+                           (area.y + 1 + highlight.line - pane.top) as u16);
+            let text = fit(&text, end - start);
+            match highlight.kind {
+                HighlightKind::Selection => write!(self.stdout, "{}{}{}", style::Invert, text, style::Reset),
+                HighlightKind::Bracket => write!(self.stdout, "{}{}{}",
+                                                 color::Bg(colors.bracket), text, color::Bg(color::Reset)),
+                HighlightKind::UnmatchedBracket => write!(self.stdout, "{}{}{}",
+                                                          color::Bg(colors.bracket_unmatched), text,
+                                                          color::Bg(color::Reset)),
+            }.unwrap();
+        }
+        if separator {
+            for y in 0..area.height {
+                self.goto_term((area.x + width) as u16, (area.y + y) as u16);
+                write!(self.stdout, "\u{2502}").unwrap();
             }
         }
     }
@@ -85,41 +368,61 @@ impl Frontend {
     pub fn goto_term(&mut self, x: u16, y: u16) {
         write!(self.stdout, "{}", termion::cursor::Goto(x+1, y+1)).unwrap();
     }
-    /// Moves the cursor to the position specified by the Cursor
-    pub fn move_cursor(&mut self, cursor: &Cursor) {
-        let (_, height) = self.terminal_size();
-        let x = (cursor.column + 4) as u16;
-        let y = if cursor.line > height {
-            cursor.line as u16
-        } else {
-            (cursor.line + 1) as u16
-        };
-        self.goto_term(x, y)
+    /// Moves the cursor to `line` and visual column `column`
+    /// in a pane drawn in `area` with `settings`.
+    pub fn move_cursor(&mut self, area: &Rect, pane: &Pane, line: usize, column: usize,
+                       settings: &Settings) {
+        let x = area.x + gutter_width(settings) + column.saturating_sub(pane.left);
+        let y = area.y + 1 + line.saturating_sub(pane.top);
+        self.goto_term(x as u16, y as u16)
     }
     /// Returns the size of the terminal as (width, height)
     pub fn terminal_size(&self) -> (usize, usize) {
         let (w, h) = termion::terminal_size().unwrap();
         (w as usize, h as usize)
     }
+    /// Draws a message on the bottom line of the screen
+    pub fn draw_message(&mut self, message: &str) {
+        self.draw_bottom_bar();
+        let (width, _) = self.terminal_size();
+        write!(&mut self.stdout, "{}{}{}",
+               fit(message, width),
+               color::Fg(color::Reset),
+               color::Bg(color::Reset)).unwrap();
+    }
     /// Prompts for a line of text
     pub fn prompt_for_text(&mut self, prompt: &str) -> Option<String> {
-        let (width, height) = termion::terminal_size().unwrap();
-        self.goto_term(0, height - 1);
-        // Draw the background.
-        write!(&mut self.stdout, "{}{}{}{}",
-               termion::clear::CurrentLine,
-               termion::color::Bg(color::White),
-               termion::color::Fg(color::Black),
-               leftpad("", width as usize)).unwrap();
-        self.goto_term(0, height - 1);
-        // Draw the prompt.
-        write!(&mut self.stdout, "{}: ", prompt).unwrap();
-        // Show it.
-        self.flush();
+        self.prompt_for_text_with_default(prompt, "")
+    }
+    /// Prompts for a line of text, starting with `default` already typed in
+    pub fn prompt_for_text_with_default(&mut self, prompt: &str, default: &str) -> Option<String> {
+        self.prompt(prompt, default, None, None)
+    }
+    /// Prompts for a line of text and remembers the answer in the history for
+    /// `history`, so that it can be brought back with the up arrow next time.
+    pub fn prompt_with_history(&mut self, prompt: &str, history: &str) -> Option<String> {
+        self.prompt(prompt, "", None, Some(history))
+    }
+    /// Prompts for a path to a file.
+    /// Tab completes the path and a leading `~` is expanded to the home directory.
+    pub fn prompt_for_path(&mut self, prompt: &str, default: &str, history: &str) -> Option<String> {
+        self.prompt(prompt, default, Some(completion::complete_path), Some(history))
+            .map(|path| paths::expand_tilde(&path))
+    }
+    /// Prompts for a line of text that Tab completes with `complete`.
+    pub fn prompt_with_completion(&mut self, prompt: &str, history: &str,
+                                  complete: fn(&str) -> Completion) -> Option<String> {
+        self.prompt(prompt, "", Some(complete), Some(history))
+    }
+    fn prompt(&mut self, prompt: &str, default: &str, complete: Option<fn(&str) -> Completion>,
+              history: Option<&str>) -> Option<String> {
         // Get the input from the user,
-        let input = self.read_line();
+        let input = self.read_line(prompt, default, complete, history);
         // Reset the forground and background.
         write!(self.stdout, "{}{}", color::Fg(color::Reset), color::Bg(color::Reset)).unwrap();
+        if let (Some(kind), Some(ref input)) = (history, &input) {
+            self.history.add(kind, input);
+        }
         input
     }
     /// Prompts for a yes/no response from the user
@@ -131,43 +434,265 @@ impl Frontend {
             false
         }
     }
+    /// Clears the bottom line of the screen and leaves the terminal cursor
+    /// at the start of it with the prompt colors set.
+    fn draw_bottom_bar(&mut self) {
+        let (width, height) = termion::terminal_size().unwrap();
+        self.goto_term(0, height - 1);
+        // Draw the background.
+        write!(&mut self.stdout, "{}{}{}{}",
+               termion::clear::CurrentLine,
+               termion::color::Bg(self.config.colors.status),
+               termion::color::Fg(self.config.colors.status_text),
+               leftpad("", width as usize)).unwrap();
+        self.goto_term(0, height - 1);
+    }
     /// Reads a line of text from the user.
-    /// TODO: Fix for Unicode. I think that the actual user input is handled
-    /// correctly, but echoing the typed characters may not be.
-    fn read_line(&mut self) -> Option<String> {
-        // Start with a buffer of size 40 so that small inputs don't require
-        // reallocating the buffer.
-        let mut buf = Vec::with_capacity(30);
+    /// `initial` is treated as if the user had already typed it.
+    /// If there is a `complete` function then Tab completes the text with it.
+    /// The up and down arrows go through the entries of `history`.
+    fn read_line(&mut self, prompt: &str, initial: &str, complete: Option<fn(&str) -> Completion>,
+                 history: Option<&str>) -> Option<String> {
+        self.line.set_text(initial);
+        let entries: Vec<String> = history.map_or(Vec::new(), |h| self.history.entries(h).to_vec());
+        // The entry of the history being shown. entries.len() means the line the
+        // user was typing, which we save in `typed` when they start going through
+        // the history.
+        let mut entry = entries.len();
+        let mut typed = String::new();
+        // The number of rows above the prompt used to show completions.
+        let mut candidate_rows = 0;
         loop {
-            // Get one byte of input
-            let mut b = [0; 1];
-            self.stdin.read_exact(&mut b[..]).unwrap();
-            match b[0] {
-                0 | 3 | 4 => return None,
-                // 0x7f is backspace
-                0x7f if !buf.is_empty() => {
-                    // Delete the last character typed
-                    buf.pop();
-                    // Clear the last character from the screen
-                    write!(&mut self.stdout, "{}{}",
-                           termion::cursor::Left(1),
-                           termion::clear::UntilNewline).unwrap();
-                    self.flush();
-                },
-                0x7f => {},
+            self.draw_prompt_line(prompt);
+            self.flush();
+            let key = self.next_key()?;
+            if candidate_rows > 0 {
+                // The completions are out of date now.
+                self.clear_rows_above_prompt(candidate_rows);
+                candidate_rows = 0;
+            }
+            match key {
+                Key::Null | Key::Ctrl('c') | Key::Ctrl('d') | Key::Ctrl('g') | Key::Esc => return None,
                 // Newline or CR ends the input
-                b'\n' | b'\r' => break,
-                c => {
-                    // Add the typed character to the input
-                    buf.push(c);
-                    // Draw it to the screen
-                    write!(&mut self.stdout, "{}", char::from(c)).unwrap();
-                    self.flush();
+                Key::Char('\n') => break,
+                Key::Char('\t') if complete.is_some() => {
+                    // Complete everything up to the cursor, and keep what's after it.
+                    let completion = complete.unwrap()(&self.line.text_before_cursor());
+                    self.line.replace_text_before_cursor(&completion.text);
+                    candidate_rows = self.draw_candidates(&completion.candidates);
+                },
+                Key::Up | Key::Ctrl('p') if entry > 0 => {
+                    if entry == entries.len() {
+                        typed = self.line.text();
+                    }
+                    entry -= 1;
+                    self.line.set_text(&entries[entry]);
+                },
+                Key::Down | Key::Ctrl('n') if entry < entries.len() => {
+                    entry += 1;
+                    let text = if entry == entries.len() { &typed } else { &entries[entry] };
+                    self.line.set_text(text);
+                },
+                key => {
+                    self.line.handle_key(key);
+                },
+            };
+        }
+        Some(self.line.text())
+    }
+    /// Draws the prompt and the line being edited on the bottom line,
+    /// with the terminal cursor where the line editor's cursor is.
+    fn draw_prompt_line(&mut self, prompt: &str) {
+        self.draw_bottom_bar();
+        let (width, height) = self.terminal_size();
+        // Prompts like vi's `:` don't need another colon.
+        let prompt = if prompt.ends_with(':') { String::from(prompt) } else { format!("{}: ", prompt) };
+        let prompt_width = prompt.width();
+        // If the text doesn't fit, scroll it so that the cursor is still on the screen.
+        let room = width.saturating_sub(prompt_width + 1);
+        let mut skip = self.line.cursor_column().saturating_sub(room);
+        let mut text = String::new();
+        let mut text_width = 0;
+        for c in self.line.text().chars() {
+            let w = c.width().unwrap_or(0);
+            if skip > 0 {
+                skip = skip.saturating_sub(w);
+                continue;
+            }
+            if text_width + w > room {
+                break;
+            }
+            text.push(c);
+            text_width += w;
+        }
+        let skipped = self.line.cursor_column().saturating_sub(room);
+        write!(&mut self.stdout, "{}{}", prompt, text).unwrap();
+        let x = prompt_width + self.line.cursor_column() - skipped;
+        self.goto_term(x as u16, (height - 1) as u16);
+    }
+    /// Lists completion candidates in the rows above the prompt.
+    /// Returns the number of rows used.
+    fn draw_candidates(&mut self, candidates: &[String]) -> usize {
+        if candidates.is_empty() {
+            return 0;
+        }
+        let (width, height) = self.terminal_size();
+        // Fit as many candidates on each row as we can.
+        let mut rows = vec![String::new()];
+        for candidate in candidates {
+            let len = rows.last().unwrap().chars().count();
+            if len > 0 && len + candidate.chars().count() + 2 > width {
+                rows.push(String::new());
+            }
+            let row = rows.last_mut().unwrap();
+            row.push_str(candidate);
+            row.push_str("  ");
+        }
+        // Don't cover up more than half of the screen.
+        rows.truncate(height / 2);
+        for (i, row) in rows.iter().enumerate() {
+            let y = height - 1 - rows.len() + i;
+            self.goto_term(0, y as u16);
+            write!(&mut self.stdout, "{}{}{}{}{}",
+                   color::Bg(color::Black),
+                   color::Fg(color::White),
+                   fit(row, width),
+                   color::Fg(color::Reset),
+                   color::Bg(color::Reset)).unwrap();
+        }
+        rows.len()
+    }
+    /// Blanks out `rows` rows above the prompt.
+    fn clear_rows_above_prompt(&mut self, rows: usize) {
+        let (_, height) = self.terminal_size();
+        for y in (height - 1 - rows)..(height - 1) {
+            self.goto_term(0, y as u16);
+            write!(&mut self.stdout, "{}{}{}",
+                   color::Bg(color::Reset), clear::CurrentLine, color::Fg(color::Reset)).unwrap();
+        }
+    }
+    /// Lets the user choose an item from the picker.
+    /// The picker is drawn on top of whatever is on the screen.
+    pub fn pick(&mut self, picker: &mut Picker) -> Picked {
+        self.run_picker(picker, None)
+    }
+    /// Like `pick`, but also shows a preview of the selected item next to the list.
+    /// `preview` is given the index of the selected item and the number of lines
+    /// that fit in the preview and returns the lines to show.
+    pub fn pick_with_preview<F>(&mut self, picker: &mut Picker, mut preview: F) -> Picked
+        where F: FnMut(usize, usize) -> Vec<String>
+    {
+        self.run_picker(picker, Some(&mut preview))
+    }
+    /// Shows the command palette, which lists `commands` as (name, keys, description)
+    /// and lets the user fuzzy search them by name.
+    /// Returns the index of the command that was picked.
+    pub fn pick_command(&mut self, commands: &[(&str, String, &str)]) -> Option<usize> {
+        let name_width = commands.iter().map(|&(name, _, _)| name.width()).max().unwrap_or(0);
+        let keys_width = commands.iter().map(|(_, keys, _)| keys.width()).max().unwrap_or(0);
+        let items = commands.iter().map(|&(name, ref keys, description)| {
+            // Line the keys and descriptions up in columns.
+            let detail = format!("{}{}{}  {}", " ".repeat(name_width - name.width()), keys,
+                                 " ".repeat(keys_width - keys.width()), description);
+            PickerItem::new(String::from(name), detail)
+        }).collect();
+        let mut picker = Picker::new("Command", items);
+        match self.pick(&mut picker) {
+            Picked::Open(index) => Some(index),
+            _ => None,
+        }
+    }
+    fn run_picker(&mut self, picker: &mut Picker,
+                  mut preview: Option<&mut dyn FnMut(usize, usize) -> Vec<String>>) -> Picked {
+        loop {
+            self.draw_picker(picker, &mut preview);
+            self.flush();
+            let key = match self.next_key() {
+                Some(key) => key,
+                None => return Picked::Cancel,
+            };
+            if let Some(picked) = picker.handle_key(key) {
+                return picked;
+            }
+        }
+    }
+    /// Draws a picker as a box in the middle of the screen.
+    fn draw_picker(&mut self, picker: &Picker,
+                   preview: &mut Option<&mut dyn FnMut(usize, usize) -> Vec<String>>) {
+        let (width, height) = self.terminal_size();
+        let (box_width, rows) = if preview.is_some() {
+            // Previews get as much room as we can give them.
+            (cmp::min(width.saturating_sub(4), 160), height.saturating_sub(4))
+        } else {
+            // One row for the title and query, and one for each item. The box doesn't
+            // shrink as the query filters the items so that it covers what it drew before.
+            (cmp::min(width.saturating_sub(4), 72),
+             cmp::min(height.saturating_sub(4), picker.len() + 1))
+        };
+        let rows = cmp::max(rows, 2);
+        let left = (width - box_width) / 2;
+        // The box can be taller than a tiny terminal.
+        let top = height.saturating_sub(rows) / 2;
+        // Only show the part of the list with the selection in it.
+        let visible = rows - 1;
+        let first = (picker.selected_position() + 1).saturating_sub(visible);
+        // Draw the title and query.
+        self.goto_term(left as u16, top as u16);
+        let title = format!(" {}: {}", picker.title, picker.query());
+        write!(&mut self.stdout, "{}{}{}{}{}",
+               color::Bg(color::White),
+               color::Fg(color::Black),
+               fit(&title, box_width),
+               color::Fg(color::Reset),
+               color::Bg(color::Reset)).unwrap();
+        // The list takes up the left part of the box when there is a preview.
+        let list_width = if preview.is_some() { box_width * 2 / 5 } else { box_width };
+        if let Some(ref mut preview) = *preview {
+            let lines = match picker.selected() {
+                Some(index) => preview(index, visible),
+                None => Vec::new(),
+            };
+            let preview_left = left + list_width;
+            let preview_width = box_width - list_width;
+            for row in 0..visible {
+                self.goto_term(preview_left as u16, (top + row + 1) as u16);
+                let tab = " ".repeat(self.config.settings.tab_width);
+                let line = lines.get(row).map_or(String::new(), |l| l.replace('\t', &tab));
+                write!(&mut self.stdout, "{}{}{}{}{}",
+                       color::Bg(color::Black),
+                       color::Fg(color::White),
+                       fit(&format!(" \u{2502} {}", line), preview_width),
+                       color::Fg(color::Reset),
+                       color::Bg(color::Reset)).unwrap();
+            }
+        }
+        for row in 0..visible {
+            self.goto_term(left as u16, (top + row + 1) as u16);
+            let text = match picker.matches().get(first + row) {
+                Some(&index) => {
+                    let item = picker.item(index);
+                    if picker.is_numbered() {
+                        format!(" {:>3} {}  {}", index + 1, item.label, item.detail)
+                    } else {
+                        format!(" {}  {}", item.label, item.detail)
+                    }
                 },
+                None => String::new(),
             };
+            if first + row == picker.selected_position() {
+                write!(&mut self.stdout, "{}{}{}",
+                       style::Invert, fit(&text, list_width), style::Reset).unwrap();
+            } else {
+                write!(&mut self.stdout, "{}{}{}{}",
+                       color::Bg(color::Black),
+                       fit(&text, list_width),
+                       color::Bg(color::Reset),
+                       style::Reset).unwrap();
+            }
         }
-        // Convert the buffer to a String.
-        Some(String::from_utf8(buf).unwrap())
+        // Leave the terminal cursor at the end of the query.
+        let query_end = cmp::min(left + title.chars().count(), (left + box_width).saturating_sub(1));
+        self.goto_term(query_end as u16, top as u16);
     }
 
 }
@@ -182,3 +707,19 @@ impl Drop for Frontend {
         self.flush();
     }
 }
+
+/// Pads or truncates `text` so that it is exactly `width` cells wide.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > width {
+            break;
+        }
+        fitted.push(c);
+        used += w;
+    }
+    fitted.push_str(&leftpad("", width - used));
+    fitted
+}
@@ -0,0 +1,67 @@
+use std::cmp::Reverse;
+
+/// Scores how well `pattern` fuzzy matches `candidate`.
+/// Every character of the pattern has to appear in the candidate in order,
+/// but there can be other characters between them. Matching is case insensitive.
+/// Returns None if the pattern doesn't match at all, and a higher score
+/// for better matches otherwise.
+pub fn score(pattern: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    // The index in the candidate where we start looking for the next
+    // character of the pattern.
+    let mut next = 0;
+    // The index of the last character that matched, so that we can
+    // reward matches that come right after each other.
+    let mut last_match: Option<usize> = None;
+    for p in pattern.chars().flat_map(char::to_lowercase) {
+        let found = candidate[next..].iter()
+            .position(|c| c.to_lowercase().eq(Some(p)))
+            .map(|i| i + next);
+        let i = found?;
+        score += 1;
+        if last_match.is_some_and(|last| last + 1 == i) {
+            // Consecutive characters are a good sign.
+            score += 5;
+        } else if let Some(last) = last_match {
+            // Big gaps between characters are a bad sign.
+            score -= (i - last) as i64 / 4;
+        }
+        if is_word_start(&candidate, i) {
+            // So are characters that start a word or path component.
+            score += 8;
+        }
+        last_match = Some(i);
+        next = i + 1;
+    }
+    // Prefer shorter candidates when everything else is the same.
+    Some(score * 100 - candidate.len() as i64)
+}
+
+/// Returns the indices of the `candidates` that match `pattern`,
+/// best match first. Candidates with equal scores keep their order.
+pub fn filter<S: AsRef<str>>(pattern: &str, candidates: &[S]) -> Vec<usize> {
+    if pattern.is_empty() {
+        // Everything matches an empty pattern equally well.
+        return (0..candidates.len()).collect();
+    }
+    let mut matches: Vec<(usize, i64)> = candidates.iter()
+        .enumerate()
+        .filter_map(|(i, c)| score(pattern, c.as_ref()).map(|s| (i, s)))
+        .collect();
+    // sort_by_key is stable, so ties stay in their original order.
+    matches.sort_by_key(|&(_, score)| Reverse(score));
+    matches.into_iter().map(|(i, _)| i).collect()
+}
+
+/// Is the character at `i` the start of a word?
+fn is_word_start(chars: &[char], i: usize) -> bool {
+    if i == 0 {
+        return true;
+    }
+    let (prev, c) = (chars[i - 1], chars[i]);
+    match prev {
+        '/' | '\\' | '_' | '-' | '.' | ' ' => true,
+        _ => prev.is_lowercase() && c.is_uppercase(),
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use paths;
+
+/// The most entries that we remember for each kind of prompt.
+const MAX_ENTRIES: usize = 100;
+
+/// History remembers what the user typed into prompts so that they
+/// can get it back later. Each kind of prompt (opening files, saving files,
+/// commands, ...) has its own list of entries, and all of them are saved
+/// to a file so that they are still around the next time trusty is run.
+#[derive(Debug)]
+pub struct History {
+    /// The entries for each kind of prompt, oldest first.
+    entries: HashMap<String, Vec<String>>,
+    /// The file the history is saved to, if we have anywhere to save it.
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// Loads the history from the history file in the data directory.
+    /// If there isn't a history file yet we start with an empty history.
+    pub fn load() -> History {
+        let path = paths::data_dir().map(|dir| dir.join("history"));
+        let mut history = History {
+            entries: HashMap::new(),
+            path: path,
+        };
+        if let Some(file) = history.path.as_ref().and_then(|p| File::open(p).ok()) {
+            // Each line is the kind of prompt, a tab, and then the entry.
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                if let Some(tab) = line.find('\t') {
+                    let (kind, entry) = line.split_at(tab);
+                    history.entries.entry(String::from(kind))
+                        .or_default()
+                        .push(String::from(&entry[1..]));
+                }
+            }
+        }
+        history
+    }
+    /// Returns the entries for the `kind` of prompt, oldest first.
+    pub fn entries(&self, kind: &str) -> &[String] {
+        self.entries.get(kind).map_or(&[], |e| &e[..])
+    }
+    /// Adds an entry to the history for the `kind` of prompt and saves the history.
+    pub fn add(&mut self, kind: &str, entry: &str) {
+        if entry.is_empty() {
+            return;
+        }
+        {
+            let entries = self.entries.entry(String::from(kind)).or_default();
+            // Only keep the most recent copy of each entry.
+            entries.retain(|e| e != entry);
+            entries.push(String::from(entry));
+            if entries.len() > MAX_ENTRIES {
+                let extra = entries.len() - MAX_ENTRIES;
+                entries.drain(..extra);
+            }
+        }
+        // Losing the history isn't worth bothering the user about.
+        let _ = self.save();
+    }
+    /// Writes the history to the history file.
+    fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = File::create(path)?;
+        for (kind, entries) in &self.entries {
+            for entry in entries {
+                writeln!(&mut file, "{}\t{}", kind, entry)?;
+            }
+        }
+        Ok(())
+    }
+}
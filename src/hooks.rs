@@ -0,0 +1,90 @@
+use backend::Backend;
+
+/// The things that happen in the editor which handlers can be hooked onto.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hook {
+    /// A buffer was opened, either from a file or empty.
+    BufferOpened,
+    /// A buffer is about to be saved. Handlers can still change its text.
+    BeforeSave,
+    /// A buffer was saved.
+    AfterSave,
+    /// A buffer was closed. It is gone by the time the handlers run.
+    BufferClosed,
+    /// The text of a buffer changed.
+    TextChanged,
+    /// The cursor moved, or another buffer became the current one.
+    CursorMoved,
+    /// vi switched modes, or was turned on or off.
+    ModeChanged,
+}
+
+/// Every hook.
+pub static ALL_HOOKS: &[Hook] = &[
+    Hook::BufferOpened,
+    Hook::BeforeSave,
+    Hook::AfterSave,
+    Hook::BufferClosed,
+    Hook::TextChanged,
+    Hook::CursorMoved,
+    Hook::ModeChanged,
+];
+
+impl Hook {
+    /// Returns the name that scripts use for the hook.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Hook::BufferOpened => "buffer-opened",
+            Hook::BeforeSave => "before-save",
+            Hook::AfterSave => "after-save",
+            Hook::BufferClosed => "buffer-closed",
+            Hook::TextChanged => "text-changed",
+            Hook::CursorMoved => "cursor-moved",
+            Hook::ModeChanged => "mode-changed",
+        }
+    }
+    /// Finds the hook called `name`.
+    pub fn from_name(name: &str) -> Option<Hook> {
+        ALL_HOOKS.iter().find(|h| h.name() == name).cloned()
+    }
+}
+
+/// What the handlers are told about something that happened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub hook: Hook,
+    /// The id of the buffer that it happened to.
+    /// Unless the buffer was closed, it is the current buffer while the handlers run.
+    pub buffer: usize,
+    /// The buffer's filename.
+    pub filename: Option<String>,
+    /// The vi mode, like "normal" or "insert", when vi is on.
+    pub mode: Option<&'static str>,
+}
+
+/// A handler written in Rust.
+pub type Handler = Box<dyn FnMut(&Event, &mut Backend)>;
+
+/// The Rust handlers for each hook.
+pub struct Hooks {
+    handlers: Vec<(Hook, Handler)>,
+}
+
+impl Hooks {
+    /// Creates a Hooks with no handlers.
+    pub fn new() -> Hooks {
+        Hooks { handlers: Vec::new() }
+    }
+    /// Adds a handler that runs every time `hook` fires.
+    pub fn on(&mut self, hook: Hook, handler: Handler) {
+        self.handlers.push((hook, handler));
+    }
+    /// Runs the handlers for `event.hook`, in the order they were added.
+    pub fn fire(&mut self, event: &Event, backend: &mut Backend) {
+        for &mut (hook, ref mut handler) in &mut self.handlers {
+            if hook == event.hook {
+                handler(event, backend);
+            }
+        }
+    }
+}
@@ -0,0 +1,467 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use termion::event::{Event, Key};
+use commands::Command;
+
+/// The bindings that trusty starts with before reading the user's keymap file.
+static DEFAULT_BINDINGS: &'static [(&'static str, &'static str)] = &[
+    ("esc", "quit"),
+    ("up", "move-up"),
+    ("down", "move-down"),
+    ("left", "move-left"),
+    ("right", "move-right"),
+    ("enter", "insert-newline"),
+    ("backspace", "delete-backward"),
+    ("tab", "insert-tab"),
+    ("ctrl-s", "save"),
+    ("alt-s", "save-as"),
+    ("alt-c", "save-copy"),
+    ("alt-a", "save-all"),
+    ("ctrl-n", "new-buffer"),
+    ("ctrl-o", "open"),
+    ("ctrl-p", "find-file"),
+    ("ctrl-x", "close-buffer"),
+    ("ctrl-l", "next-buffer"),
+    ("ctrl-k", "previous-buffer"),
+    ("ctrl-b", "buffer-list"),
+    ("alt-<", "move-buffer-left"),
+    ("alt->", "move-buffer-right"),
+    ("alt-2", "split-horizontal"),
+    ("alt-3", "split-vertical"),
+    ("alt-0", "close-pane"),
+    ("alt-1", "close-other-panes"),
+    ("alt-o", "next-pane"),
+    ("alt-h", "focus-left"),
+    ("alt-j", "focus-down"),
+    ("alt-k", "focus-up"),
+    ("alt-l", "focus-right"),
+    ("alt-=", "grow-pane"),
+    ("alt--", "shrink-pane"),
+    ("alt-;", "command-line"),
+    ("alt-x", "command-palette"),
+    ("f3", "record-macro"),
+    ("f4", "play-macro"),
+    ("alt-p", "add-cursor-above"),
+    ("alt-n", "add-cursor-below"),
+    ("alt-d", "add-cursor-at-next-match"),
+    ("alt-i", "split-selection-into-cursors"),
+    ("alt-q", "clear-cursors"),
+    ("home", "move-smart-line-start"),
+    ("end", "move-line-end"),
+    ("ctrl-left", "move-word-backward"),
+    ("ctrl-right", "move-word-forward"),
+    ("ctrl-up", "move-paragraph-backward"),
+    ("ctrl-down", "move-paragraph-forward"),
+    ("pageup", "move-page-up"),
+    ("pagedown", "move-page-down"),
+    ("ctrl-home", "move-buffer-start"),
+    ("ctrl-end", "move-buffer-end"),
+    ("ctrl-g", "go-to-line"),
+    ("alt-m", "set-mark"),
+    ("alt-'", "jump-to-mark"),
+    ("alt-,", "jump-back"),
+    ("alt-.", "jump-forward"),
+    ("alt-]", "jump-to-matching-bracket"),
+    ("ctrl-y", "delete-line"),
+    ("ctrl-d", "duplicate-lines"),
+    ("alt-up", "move-lines-up"),
+    ("alt-down", "move-lines-down"),
+    ("alt-J", "join-lines"),
+    ("f9", "sort-lines"),
+    ("alt-}", "indent-lines"),
+    ("alt-{", "outdent-lines"),
+    ("ctrl-z", "undo"),
+    ("ctrl-r", "redo"),
+    ("ctrl-v", "block-select"),
+    ("alt-t", "block-insert"),
+    ("alt-e", "block-delete"),
+    ("alt-y", "block-copy"),
+    ("alt-v", "block-paste"),
+];
+
+/// The bindings of the `emacs` keymap, for people who have Emacs in their fingers.
+static EMACS_BINDINGS: &'static [(&'static str, &'static str)] = &[
+    ("up", "move-up"),
+    ("down", "move-down"),
+    ("left", "move-left"),
+    ("right", "move-right"),
+    ("enter", "insert-newline"),
+    ("backspace", "delete-backward"),
+    ("delete", "delete-forward"),
+    ("tab", "insert-tab"),
+    ("ctrl-p", "move-up"),
+    ("ctrl-n", "move-down"),
+    ("ctrl-b", "move-left"),
+    ("ctrl-f", "move-right"),
+    ("ctrl-a", "move-line-start"),
+    ("ctrl-e", "move-line-end"),
+    ("alt-f", "move-word-forward"),
+    ("alt-b", "move-word-backward"),
+    ("alt-m", "move-smart-line-start"),
+    ("ctrl-v", "move-page-down"),
+    ("alt-v", "move-page-up"),
+    ("alt-{", "move-paragraph-backward"),
+    ("alt-}", "move-paragraph-forward"),
+    ("alt-<", "move-buffer-start"),
+    ("alt->", "move-buffer-end"),
+    ("alt-g g", "go-to-line"),
+    ("alt-g alt-g", "go-to-line"),
+    ("ctrl-x r space", "set-mark"),
+    ("ctrl-x r j", "jump-to-mark"),
+    ("alt-,", "jump-back"),
+    ("alt-.", "jump-forward"),
+    ("alt-]", "jump-to-matching-bracket"),
+    ("home", "move-smart-line-start"),
+    ("end", "move-line-end"),
+    ("ctrl-left", "move-word-backward"),
+    ("ctrl-right", "move-word-forward"),
+    ("ctrl-up", "move-paragraph-backward"),
+    ("ctrl-down", "move-paragraph-forward"),
+    ("pageup", "move-page-up"),
+    ("pagedown", "move-page-down"),
+    ("ctrl-home", "move-buffer-start"),
+    ("ctrl-end", "move-buffer-end"),
+    ("ctrl-d", "delete-forward"),
+    ("ctrl-k", "kill-line"),
+    ("ctrl-y", "yank"),
+    ("alt-y", "yank-pop"),
+    ("ctrl-g", "cancel"),
+    ("ctrl-x ctrl-s", "save"),
+    ("ctrl-x ctrl-w", "save-as"),
+    ("ctrl-x s", "save-all"),
+    ("ctrl-x ctrl-f", "open"),
+    ("ctrl-x ctrl-c", "quit"),
+    ("ctrl-x b", "buffer-list"),
+    ("ctrl-x k", "close-buffer"),
+    ("ctrl-x right", "next-buffer"),
+    ("ctrl-x left", "previous-buffer"),
+    ("ctrl-x 2", "split-horizontal"),
+    ("ctrl-x 3", "split-vertical"),
+    ("ctrl-x 0", "close-pane"),
+    ("ctrl-x 1", "close-other-panes"),
+    ("ctrl-x o", "next-pane"),
+    ("ctrl-x ^", "grow-pane"),
+    ("ctrl-x -", "shrink-pane"),
+    ("ctrl-x (", "record-macro"),
+    ("ctrl-x )", "record-macro"),
+    ("ctrl-x e", "play-macro"),
+    ("alt-p", "add-cursor-above"),
+    ("alt-n", "add-cursor-below"),
+    ("ctrl-x space", "block-select"),
+    ("ctrl-x r t", "block-insert"),
+    ("ctrl-x r k", "block-delete"),
+    ("ctrl-x r alt-w", "block-copy"),
+    ("ctrl-x r y", "block-paste"),
+    ("ctrl-c k", "delete-line"),
+    ("ctrl-c d", "duplicate-lines"),
+    ("alt-up", "move-lines-up"),
+    ("alt-down", "move-lines-down"),
+    ("ctrl-c j", "join-lines"),
+    ("ctrl-c s", "sort-lines"),
+    ("ctrl-c r", "reverse-lines"),
+    ("ctrl-c >", "indent-lines"),
+    ("ctrl-c <", "outdent-lines"),
+    ("ctrl-x u", "undo"),
+    ("ctrl-x U", "redo"),
+    ("alt-;", "command-line"),
+    ("alt-x", "command-palette"),
+    ("f3", "record-macro"),
+    ("f4", "play-macro"),
+];
+
+/// termion doesn't know about ctrl with the arrow keys, home or end, so we give
+/// each of them a `Key::Ctrl` with a character that can't be typed with ctrl.
+/// Each one has its name and the escape sequence that xterm-like terminals send for it.
+static CTRL_KEYS: &'static [(&'static str, char, &'static [u8])] = &[
+    ("left", '←', b"1;5D"),
+    ("right", '→', b"1;5C"),
+    ("up", '↑', b"1;5A"),
+    ("down", '↓', b"1;5B"),
+    ("home", '⇱', b"1;5H"),
+    ("end", '⇲', b"1;5F"),
+];
+
+/// The same for alt with the arrow keys, which give a `Key::Alt` with the arrow.
+static ALT_KEYS: &'static [(&'static str, char, &'static [u8])] = &[
+    ("left", '←', b"1;3D"),
+    ("right", '→', b"1;3C"),
+    ("up", '↑', b"1;3A"),
+    ("down", '↓', b"1;3B"),
+];
+
+/// Turns the escape sequences for the keys in `CTRL_KEYS` and `ALT_KEYS` into their keys.
+/// Any other event is returned as it is.
+pub fn translate_event(event: Event) -> Event {
+    if let Event::UnknownCsi(ref csi) = event {
+        if let Some(&(_, c, _)) = CTRL_KEYS.iter().find(|&&(_, _, sequence)| sequence == &csi[..]) {
+            return Event::Key(Key::Ctrl(c));
+        }
+        if let Some(&(_, c, _)) = ALT_KEYS.iter().find(|&&(_, _, sequence)| sequence == &csi[..]) {
+            return Event::Key(Key::Alt(c));
+        }
+    }
+    event
+}
+
+/// What a sequence of keys is bound to.
+#[derive(Debug, PartialEq)]
+pub enum Binding<'a> {
+    /// The keys run the named command.
+    Command(&'a str),
+    /// The keys are the start of at least one longer binding.
+    Prefix,
+    /// The keys aren't bound to anything.
+    Unbound,
+}
+
+/// A Keymap maps sequences of keys to the names of commands.
+/// Most bindings are a single key, but longer sequences like
+/// `ctrl-x ctrl-s` work too.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: Vec<(Vec<Key>, String)>,
+}
+
+impl Keymap {
+    /// Creates a keymap with no bindings.
+    pub fn empty() -> Keymap {
+        Keymap {
+            bindings: Vec::new(),
+        }
+    }
+    /// Creates the keymap that trusty uses when there is no keymap file.
+    pub fn new() -> Keymap {
+        Keymap::from_bindings(DEFAULT_BINDINGS)
+    }
+    /// Creates the keymap with Emacs style bindings.
+    pub fn emacs() -> Keymap {
+        Keymap::from_bindings(EMACS_BINDINGS)
+    }
+    /// Creates the keymap that the `keymap` setting calls `name`,
+    /// or returns None if there isn't one with that name.
+    /// The vi keys sit on top of the default keymap.
+    pub fn preset(name: &str) -> Option<Keymap> {
+        match name {
+            "default" | "vi" => Some(Keymap::new()),
+            "emacs" => Some(Keymap::emacs()),
+            _ => None,
+        }
+    }
+    fn from_bindings(bindings: &[(&str, &str)]) -> Keymap {
+        let mut keymap = Keymap::empty();
+        for &(keys, command) in bindings {
+            keymap.bind(parse_keys(keys).unwrap(), String::from(command));
+        }
+        keymap
+    }
+    /// Binds `keys` to the command named `command`.
+    /// Any bindings that would get in the way of the new one are removed,
+    /// and returned so that the caller can complain about them.
+    pub fn bind(&mut self, keys: Vec<Key>, command: String) -> Vec<(Vec<Key>, String)> {
+        let (removed, kept) = self.bindings.drain(..).partition(|(k, _)| {
+            k.starts_with(&keys) || keys.starts_with(k)
+        });
+        self.bindings = kept;
+        self.bindings.push((keys, command));
+        removed
+    }
+    /// Removes the binding for `keys`, if there is one.
+    pub fn unbind(&mut self, keys: &[Key]) {
+        self.bindings.retain(|(k, _)| k[..] != *keys);
+    }
+    /// Looks up what `keys` are bound to.
+    pub fn lookup(&self, keys: &[Key]) -> Binding<'_> {
+        let mut prefix = false;
+        for (k, command) in &self.bindings {
+            if k[..] == *keys {
+                return Binding::Command(command);
+            }
+            if k.starts_with(keys) {
+                prefix = true;
+            }
+        }
+        if prefix { Binding::Prefix } else { Binding::Unbound }
+    }
+    /// Returns the names of the key sequences bound to `command`.
+    pub fn keys_for(&self, command: &str) -> Vec<String> {
+        self.bindings.iter()
+            .filter(|&(_, c)| c == command)
+            .map(|(keys, _)| keys_name(keys))
+            .collect()
+    }
+    /// Reads bindings from the keymap file at `path` on top of this keymap.
+    /// Each line of the file looks like `ctrl-x ctrl-s = save`, and binding
+    /// keys to `none` removes their binding. Lines starting with # are comments.
+    /// Returns a description of each problem found in the file.
+    /// `is_command` is used to check that the commands exist.
+    pub fn load<F>(&mut self, path: &Path, is_command: F) -> Vec<String>
+        where F: Fn(&str) -> bool
+    {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            // Not having a keymap file is fine.
+            Err(_) => return Vec::new(),
+        };
+        let name = path.display();
+        let mut errors = Vec::new();
+        // The keys bound by the file so far, so we can spot conflicts in it.
+        let mut bound_here: Vec<Vec<Key>> = Vec::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    errors.push(format!("{}: {}", name, e));
+                    break;
+                },
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let at = format!("{}:{}", name, number + 1);
+            let (keys, command) = match line.rfind('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => {
+                    errors.push(format!("{}: expected `keys = command`", at));
+                    continue;
+                },
+            };
+            let keys = match parse_keys(keys) {
+                Ok(keys) => keys,
+                Err(e) => {
+                    errors.push(format!("{}: {}", at, e));
+                    continue;
+                },
+            };
+            if command == "none" {
+                self.unbind(&keys);
+                continue;
+            }
+            if !is_command(command) {
+                errors.push(format!("{}: unknown command `{}`", at, command));
+                continue;
+            }
+            for (removed, _) in self.bind(keys.clone(), String::from(command)) {
+                if bound_here.contains(&removed) {
+                    errors.push(format!("{}: `{}` conflicts with `{}` bound earlier",
+                                        at, keys_name(&keys), keys_name(&removed)));
+                }
+            }
+            bound_here.push(keys);
+        }
+        errors
+    }
+}
+
+/// Is `name` the name of a built in command?
+pub fn is_builtin_command(name: &str) -> bool {
+    Command::from_name(name).is_some()
+}
+
+/// Parses a space separated sequence of keys, like `ctrl-x ctrl-s`.
+pub fn parse_keys(text: &str) -> Result<Vec<Key>, String> {
+    let keys = text.split_whitespace().map(parse_key).collect::<Result<Vec<Key>, String>>()?;
+    if keys.is_empty() {
+        return Err(String::from("no keys given"));
+    }
+    Ok(keys)
+}
+
+/// Parses the name of a single key, like `ctrl-s`, `alt-x`, `pageup` or `a`.
+pub fn parse_key(text: &str) -> Result<Key, String> {
+    let single = |rest: &str| {
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    let key = match text {
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "backspace" => Key::Backspace,
+        "enter" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "space" => Key::Char(' '),
+        "esc" => Key::Esc,
+        _ => {
+            if let Some(rest) = text.strip_prefix("ctrl-") {
+                match single(rest).or_else(|| CTRL_KEYS.iter().find(|k| k.0 == rest).map(|k| k.1)) {
+                    Some(c) => Key::Ctrl(c.to_ascii_lowercase()),
+                    None => return Err(format!("unknown key `{}`", text)),
+                }
+            } else if let Some(rest) = text.strip_prefix("alt-") {
+                match single(rest).or_else(|| ALT_KEYS.iter().find(|k| k.0 == rest).map(|k| k.1)) {
+                    Some(c) => Key::Alt(c),
+                    None if rest == "space" => Key::Alt(' '),
+                    None => return Err(format!("unknown key `{}`", text)),
+                }
+            } else if let Some(n) = text.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                if !(1..=12).contains(&n) {
+                    return Err(format!("unknown key `{}`", text));
+                }
+                Key::F(n)
+            } else {
+                match single(text) {
+                    Some(c) => Key::Char(c),
+                    None => return Err(format!("unknown key `{}`", text)),
+                }
+            }
+        },
+    };
+    Ok(key)
+}
+
+/// Returns the name of a key, in the same format that `parse_key` reads.
+pub fn key_name(key: &Key) -> String {
+    match *key {
+        Key::Up => String::from("up"),
+        Key::Down => String::from("down"),
+        Key::Left => String::from("left"),
+        Key::Right => String::from("right"),
+        Key::Home => String::from("home"),
+        Key::End => String::from("end"),
+        Key::PageUp => String::from("pageup"),
+        Key::PageDown => String::from("pagedown"),
+        Key::Delete => String::from("delete"),
+        Key::Insert => String::from("insert"),
+        Key::Backspace => String::from("backspace"),
+        Key::Char('\n') => String::from("enter"),
+        Key::Char('\t') => String::from("tab"),
+        Key::Char(' ') => String::from("space"),
+        Key::Alt(' ') => String::from("alt-space"),
+        Key::Esc => String::from("esc"),
+        Key::F(n) => format!("f{}", n),
+        Key::Char(c) => c.to_string(),
+        Key::Alt(c) => match ALT_KEYS.iter().find(|k| k.1 == c) {
+            Some(&(name, _, _)) => format!("alt-{}", name),
+            None => format!("alt-{}", c),
+        },
+        Key::Ctrl(c) => match CTRL_KEYS.iter().find(|k| k.1 == c) {
+            Some(&(name, _, _)) => format!("ctrl-{}", name),
+            None => format!("ctrl-{}", c),
+        },
+        _ => String::from("?"),
+    }
+}
+
+/// Does `key` have a name that `parse_key` reads back as the same key?
+/// Keys that termion doesn't know the name of, or with whitespace in their names, don't.
+pub fn has_name(key: &Key) -> bool {
+    let name = key_name(key);
+    !name.contains(char::is_whitespace) && parse_key(&name).as_ref() == Ok(key)
+}
+
+/// Returns the name of a sequence of keys, like `ctrl-x ctrl-s`.
+pub fn keys_name(keys: &[Key]) -> String {
+    keys.iter().map(key_name).collect::<Vec<String>>().join(" ")
+}
@@ -0,0 +1,52 @@
+/// The most kills that the kill ring remembers.
+const MAX_KILLS: usize = 60;
+
+/// The KillRing holds the text that has been killed, so that it can be yanked
+/// back. Yanking gives the newest kill, and yanking again straight after that
+/// goes round the older ones.
+#[derive(Debug)]
+pub struct KillRing {
+    /// The kills, oldest first.
+    kills: Vec<String>,
+    /// How far back from the newest kill the last yank was.
+    yanked: usize,
+}
+
+impl KillRing {
+    /// Creates an empty kill ring.
+    pub fn new() -> KillRing {
+        KillRing {
+            kills: Vec::new(),
+            yanked: 0,
+        }
+    }
+    /// Adds a new kill, forgetting the oldest one if there are too many.
+    pub fn push(&mut self, text: String) {
+        if self.kills.len() == MAX_KILLS {
+            self.kills.remove(0);
+        }
+        self.kills.push(text);
+    }
+    /// Adds `text` to the end of the newest kill, so that killing
+    /// several times in a row yanks back as one piece.
+    pub fn append(&mut self, text: &str) {
+        match self.kills.last_mut() {
+            Some(kill) => kill.push_str(text),
+            None => self.kills.push(String::from(text)),
+        }
+    }
+    /// Returns the newest kill.
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yanked = 0;
+        self.kills.last().map(|kill| &kill[..])
+    }
+    /// Returns the kill before the one that was yanked last, going back round
+    /// to the newest one after the oldest.
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.kills.is_empty() {
+            return None;
+        }
+        self.yanked = (self.yanked + 1) % self.kills.len();
+        Some(&self.kills[self.kills.len() - 1 - self.yanked])
+    }
+}
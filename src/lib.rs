@@ -1,11 +1,40 @@
-#![cfg_attr(feature = "cargo-clippy", allow(new_without_default))]
+#![allow(clippy::new_without_default, clippy::redundant_field_names, clippy::redundant_static_lifetimes)]
 extern crate termion;
 extern crate left_pad;
+extern crate ignore;
+extern crate unicode_width;
+extern crate unicode_segmentation;
+extern crate toml;
+extern crate regex;
+extern crate rhai;
 
 pub mod backend;
 pub mod frontend;
 pub mod editor;
 pub mod cursor;
+pub mod columns;
+pub mod fuzzy;
+pub mod finder;
+pub mod picker;
+pub mod paths;
+pub mod completion;
+pub mod line_editor;
+pub mod history;
+pub mod window;
+pub mod commands;
+pub mod keymap;
+pub mod kill_ring;
+pub mod config;
+pub mod editorconfig;
+pub mod vi;
+pub mod ex;
+pub mod scripting;
+pub mod hooks;
+pub mod macros;
+pub mod marks;
+pub mod brackets;
+pub mod undo;
 pub use backend::Backend;
 pub use frontend::Frontend;
 pub use editor::Editor;
+pub use config::Config;
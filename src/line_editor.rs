@@ -0,0 +1,153 @@
+use termion::event::Key;
+use unicode_width::UnicodeWidthChar;
+
+/// A LineEditor holds a single line of text that the user is typing,
+/// like the answer to a prompt, and applies editing keys to it.
+/// It supports the usual readline style keys for moving around,
+/// killing and yanking text.
+#[derive(Debug)]
+pub struct LineEditor {
+    /// The text being edited.
+    text: Vec<char>,
+    /// The position of the cursor in `text`.
+    pos: usize,
+    /// The last text that was killed. It stays around between lines
+    /// so that you can kill text in one prompt and yank it into another.
+    killed: String,
+}
+
+impl LineEditor {
+    /// Creates a new, empty LineEditor.
+    pub fn new() -> LineEditor {
+        LineEditor {
+            text: Vec::new(),
+            pos: 0,
+            killed: String::new(),
+        }
+    }
+    /// Replaces the text and puts the cursor at the end of it.
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.chars().collect();
+        self.pos = self.text.len();
+    }
+    /// Returns the text as a String.
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+    /// Returns the text before the cursor.
+    pub fn text_before_cursor(&self) -> String {
+        self.text[..self.pos].iter().collect()
+    }
+    /// Replaces the text before the cursor, keeping the text after it.
+    /// The cursor ends up at the end of the new text.
+    pub fn replace_text_before_cursor(&mut self, text: &str) {
+        let mut new_text: Vec<char> = text.chars().collect();
+        let pos = new_text.len();
+        new_text.extend_from_slice(&self.text[self.pos..]);
+        self.text = new_text;
+        self.pos = pos;
+    }
+    /// Returns the number of terminal columns the text before the cursor takes up.
+    pub fn cursor_column(&self) -> usize {
+        self.text[..self.pos].iter().map(|c| c.width().unwrap_or(0)).sum()
+    }
+    /// Applies an editing key to the line.
+    /// Returns false if the key isn't an editing key.
+    pub fn handle_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(c) if !c.is_control() => {
+                self.text.insert(self.pos, c);
+                self.pos += 1;
+            },
+            Key::Backspace => {
+                if self.pos > 0 {
+                    self.pos -= 1;
+                    self.text.remove(self.pos);
+                }
+            },
+            Key::Delete => {
+                if self.pos < self.text.len() {
+                    self.text.remove(self.pos);
+                }
+            },
+            Key::Left | Key::Ctrl('b') => {
+                if self.pos > 0 {
+                    self.pos -= 1;
+                }
+            },
+            Key::Right | Key::Ctrl('f') => {
+                if self.pos < self.text.len() {
+                    self.pos += 1;
+                }
+            },
+            Key::Home | Key::Ctrl('a') => self.pos = 0,
+            Key::End | Key::Ctrl('e') => self.pos = self.text.len(),
+            Key::Alt('b') => self.pos = self.previous_word_start(),
+            Key::Alt('f') => self.pos = self.next_word_end(),
+            // Ctrl-k kills to the end of the line, and Ctrl-u kills to the start.
+            Key::Ctrl('k') => {
+                let end = self.text.len();
+                self.kill(self.pos, end);
+            },
+            Key::Ctrl('u') => {
+                let pos = self.pos;
+                self.kill(0, pos);
+            },
+            // Ctrl-w and Alt-Backspace kill the word before the cursor,
+            // Alt-d kills the word after it.
+            Key::Ctrl('w') | Key::Alt('\x7f') => {
+                let start = self.previous_word_start();
+                let pos = self.pos;
+                self.kill(start, pos);
+            },
+            Key::Alt('d') => {
+                let end = self.next_word_end();
+                let pos = self.pos;
+                self.kill(pos, end);
+            },
+            // Ctrl-y yanks the last killed text back in.
+            Key::Ctrl('y') => {
+                let killed: Vec<char> = self.killed.chars().collect();
+                let n = killed.len();
+                self.text.splice(self.pos..self.pos, killed);
+                self.pos += n;
+            },
+            _ => return false,
+        }
+        true
+    }
+    /// Removes the text from `start` to `end` and remembers it so that it can be yanked.
+    fn kill(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.killed = self.text.drain(start..end).collect();
+            self.pos = start;
+        }
+    }
+    /// Finds the start of the word before the cursor.
+    /// Slashes count as the end of a word so that you can work on one part of a path.
+    fn previous_word_start(&self) -> usize {
+        let mut start = self.pos;
+        while start > 0 && !is_word_char(self.text[start - 1]) {
+            start -= 1;
+        }
+        while start > 0 && is_word_char(self.text[start - 1]) {
+            start -= 1;
+        }
+        start
+    }
+    /// Finds the end of the word after the cursor.
+    fn next_word_end(&self) -> usize {
+        let mut end = self.pos;
+        while end < self.text.len() && !is_word_char(self.text[end]) {
+            end += 1;
+        }
+        while end < self.text.len() && is_word_char(self.text[end]) {
+            end += 1;
+        }
+        end
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && c != '/'
+}
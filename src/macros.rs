@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use termion::event::Key;
+use keymap;
+use paths;
+
+/// Keyboard macros are keys that were recorded so that they can be played back.
+/// Each one is kept in a register with a name, and they are all saved to a file
+/// in the data directory so that they are still around the next time trusty is run.
+#[derive(Debug)]
+pub struct Macros {
+    macros: HashMap<String, Vec<Key>>,
+    /// The name of the macro that was recorded or played last.
+    last: Option<String>,
+    /// The file the macros are saved to, if we have anywhere to save them.
+    path: Option<PathBuf>,
+}
+
+impl Macros {
+    /// Loads the macros from the macros file in the data directory.
+    /// If there isn't a macros file yet we start without any macros.
+    pub fn load() -> Macros {
+        let path = paths::data_dir().map(|dir| dir.join("macros"));
+        let mut macros = Macros {
+            macros: HashMap::new(),
+            last: None,
+            path: path,
+        };
+        if let Some(file) = macros.path.as_ref().and_then(|p| File::open(p).ok()) {
+            // Each line is the name of the macro, a tab, and then its keys.
+            // Only keys that `keymap::has_name` are stored, so that they all read back.
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                if let Some(tab) = line.find('\t') {
+                    let (name, keys) = line.split_at(tab);
+                    if let Ok(keys) = keymap::parse_keys(&keys[1..]) {
+                        macros.macros.insert(String::from(name), keys);
+                    }
+                }
+            }
+        }
+        macros
+    }
+    /// Returns the keys of the macro called `name`.
+    pub fn get(&self, name: &str) -> Option<&[Key]> {
+        self.macros.get(name).map(|keys| &keys[..])
+    }
+    /// Puts `keys` in the register called `name` and saves the macros.
+    pub fn set(&mut self, name: &str, keys: Vec<Key>) {
+        self.macros.insert(String::from(name), keys);
+        self.last = Some(String::from(name));
+        // Losing the macros isn't worth bothering the user about.
+        let _ = self.save();
+    }
+    /// Returns the name of the macro that was recorded or played last.
+    pub fn last(&self) -> Option<&str> {
+        self.last.as_ref().map(|name| &name[..])
+    }
+    /// Remembers that the macro called `name` was played.
+    pub fn set_last(&mut self, name: &str) {
+        self.last = Some(String::from(name));
+    }
+    /// Writes the macros to the macros file.
+    fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = File::create(path)?;
+        let mut names: Vec<&String> = self.macros.keys().collect();
+        names.sort();
+        for name in names {
+            writeln!(&mut file, "{}\t{}", name, keymap::keys_name(&self.macros[name]))?;
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,46 @@
 extern crate trusty;
 use std::env;
+use std::process;
+
+static USAGE: &str = "usage: trusty [--keymap default|vi|emacs] [file...]";
 
 fn main() {
-    let files = env::args().skip(1).collect();
-    let mut frontend = trusty::Frontend::new();
-    let mut backend = trusty::Backend::new(files);
-    let mut editor = trusty::Editor::new(&mut frontend, &mut backend);
+    let mut files = Vec::new();
+    let mut keymap = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--keymap" {
+            keymap = args.next();
+            if keymap.is_none() {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        } else if let Some(name) = arg.strip_prefix("--keymap=") {
+            keymap = Some(String::from(name));
+        } else {
+            files.push(arg);
+        }
+    }
+    if let Some(ref name) = keymap {
+        if trusty::keymap::Keymap::preset(name).is_none() {
+            eprintln!("trusty: unknown keymap `{}`\n{}", name, USAGE);
+            process::exit(2);
+        }
+    }
+    let config = trusty::Config::load();
+    // The files are opened before the frontend takes over the terminal,
+    // so that the error can be printed.
+    let mut backend = match trusty::Backend::new(files, config.clone()) {
+        Ok(backend) => backend,
+        Err(e) => {
+            eprintln!("trusty: {}", e);
+            process::exit(1);
+        },
+    };
+    let mut frontend = trusty::Frontend::new(config.clone());
+    let mut editor = trusty::Editor::new(&mut frontend, &mut backend, config);
+    if let Some(name) = keymap {
+        editor.set_keymap(&name);
+    }
     editor.start();
 }
@@ -0,0 +1,218 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use backend::Change;
+use cursor::Cursor;
+use paths;
+
+/// The most jumps that the jump list remembers.
+const MAX_JUMPS: usize = 100;
+
+/// Returns the name that a file is remembered by, which is its full path
+/// so that it is the same whichever directory trusty was started in.
+pub fn file_key(filename: &str) -> String {
+    match fs::canonicalize(filename) {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(_) => String::from(filename),
+    }
+}
+
+/// The marks of every file that has had marks set in it, saved to a file in
+/// the data directory so that they are still around the next time trusty is run.
+/// While a file is open its marks live in its buffer, where they move along with edits.
+#[derive(Debug)]
+pub struct Marks {
+    /// The marks of each file, by the file's full path.
+    files: HashMap<String, BTreeMap<char, Cursor>>,
+    /// The file the marks are saved to, if we have anywhere to save them.
+    path: Option<PathBuf>,
+}
+
+impl Marks {
+    /// Loads the marks from the marks file in the data directory.
+    pub fn load() -> Marks {
+        let path = paths::data_dir().map(|dir| dir.join("marks"));
+        let mut marks = Marks {
+            files: HashMap::new(),
+            path: path,
+        };
+        if let Some(file) = marks.path.as_ref().and_then(|p| File::open(p).ok()) {
+            // Each line is the mark, its line and column, and then the file, separated by tabs.
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                let parts: Vec<&str> = line.splitn(4, '\t').collect();
+                if let [name, line, column, filename] = parts[..] {
+                    let mut name = name.chars();
+                    if let (Some(name), None, Ok(line), Ok(column)) =
+                        (name.next(), name.next(), line.parse(), column.parse()) {
+                        marks.files.entry(String::from(filename)).or_default()
+                            .insert(name, Cursor::new(line, column));
+                    }
+                }
+            }
+        }
+        marks
+    }
+    /// Returns the marks that were saved for `filename`.
+    pub fn get(&self, filename: &str) -> BTreeMap<char, Cursor> {
+        self.files.get(&file_key(filename)).cloned().unwrap_or_default()
+    }
+    /// Remembers `marks` as the marks of `filename` and saves them all.
+    pub fn set(&mut self, filename: &str, marks: &BTreeMap<char, Cursor>) {
+        let key = file_key(filename);
+        if marks.is_empty() {
+            if self.files.remove(&key).is_none() {
+                return;
+            }
+        } else {
+            self.files.insert(key, marks.clone());
+        }
+        // Losing the marks isn't worth bothering the user about.
+        let _ = self.save();
+    }
+    /// Writes the marks to the marks file.
+    fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = File::create(path)?;
+        for (filename, marks) in &self.files {
+            for (name, at) in marks {
+                writeln!(&mut file, "{}\t{}\t{}\t{}", name, at.line, at.column, filename)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A place that the cursor jumped away from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Jump {
+    /// The id of the buffer, unless it is from an earlier session.
+    pub buffer: Option<usize>,
+    /// The full path of the buffer's file, if it has one.
+    pub filename: Option<String>,
+    pub cursor: Cursor,
+}
+
+impl Jump {
+    /// Is this jump on the same line of the same buffer as `other`?
+    fn same_line(&self, other: &Jump) -> bool {
+        let same_buffer = match (self.buffer, other.buffer) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.filename.is_some() && self.filename == other.filename,
+        };
+        same_buffer && self.cursor.line == other.cursor.line
+    }
+}
+
+/// The jump list remembers where the cursor was before each big jump, like going
+/// to a line or to another buffer, so that you can go back there and forward again.
+/// It is shared by all the buffers, and the jumps in files are saved to a file
+/// in the data directory so that they are still around the next time trusty is run.
+#[derive(Debug)]
+pub struct JumpList {
+    /// The jumps, oldest first.
+    jumps: Vec<Jump>,
+    /// The jump that we have gone back to, or the length of `jumps` if we haven't gone back.
+    position: usize,
+    /// The file the jumps are saved to, if we have anywhere to save them.
+    path: Option<PathBuf>,
+}
+
+impl JumpList {
+    /// Loads the jumps from the jumps file in the data directory.
+    pub fn load() -> JumpList {
+        let path = paths::data_dir().map(|dir| dir.join("jumps"));
+        let mut jumps = Vec::new();
+        if let Some(file) = path.as_ref().and_then(|p| File::open(p).ok()) {
+            // Each line is the line and column, and then the file, separated by tabs.
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                let parts: Vec<&str> = line.splitn(3, '\t').collect();
+                if let [line, column, filename] = parts[..] {
+                    if let (Ok(line), Ok(column)) = (line.parse(), column.parse()) {
+                        jumps.push(Jump {
+                            buffer: None,
+                            filename: Some(String::from(filename)),
+                            cursor: Cursor::new(line, column),
+                        });
+                    }
+                }
+            }
+        }
+        JumpList {
+            position: jumps.len(),
+            jumps: jumps,
+            path: path,
+        }
+    }
+    /// Remembers `from` as the place that the cursor just jumped away from.
+    /// Anything that we had gone back past is forgotten.
+    pub fn push(&mut self, from: Jump) {
+        self.jumps.truncate(self.position);
+        self.add(from);
+    }
+    /// Returns the jump before the one we are at. `from` is where the cursor is now,
+    /// which is remembered so that going forward again can come back to it.
+    pub fn back(&mut self, from: Jump) -> Option<Jump> {
+        if self.position == self.jumps.len() {
+            self.add(from);
+            self.position = self.jumps.len() - 1;
+        }
+        if self.position == 0 {
+            return None;
+        }
+        self.position -= 1;
+        Some(self.jumps[self.position].clone())
+    }
+    /// Returns the jump after the one we went back to.
+    pub fn forward(&mut self) -> Option<Jump> {
+        if self.position + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.position += 1;
+        Some(self.jumps[self.position].clone())
+    }
+    /// Moves the jumps in the buffer with the id `buffer` so that they stay on the same text after `change`.
+    pub fn adjust(&mut self, buffer: usize, change: &Change) {
+        for jump in &mut self.jumps {
+            if jump.buffer == Some(buffer) {
+                jump.cursor.adjust(change);
+            }
+        }
+    }
+    /// Adds `jump` to the end, in place of any jump on the same line, and saves the jumps.
+    fn add(&mut self, jump: Jump) {
+        self.jumps.retain(|j| !j.same_line(&jump));
+        self.jumps.push(jump);
+        if self.jumps.len() > MAX_JUMPS {
+            let extra = self.jumps.len() - MAX_JUMPS;
+            self.jumps.drain(..extra);
+        }
+        self.position = self.jumps.len();
+        // Losing the jumps isn't worth bothering the user about.
+        let _ = self.save();
+    }
+    /// Writes the jumps in files to the jumps file.
+    fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = File::create(path)?;
+        for jump in &self.jumps {
+            if let Some(ref filename) = jump.filename {
+                writeln!(&mut file, "{}\t{}\t{}", jump.cursor.line, jump.cursor.column, filename)?;
+            }
+        }
+        Ok(())
+    }
+}
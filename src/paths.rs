@@ -0,0 +1,39 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Returns the user's home directory, if we know where it is.
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME").filter(|h| !h.is_empty()).map(PathBuf::from)
+}
+
+/// Replaces a `~` at the start of `path` with the user's home directory.
+/// Paths like `~user/...` are left alone.
+pub fn expand_tilde(path: &str) -> String {
+    if path == "~" || path.starts_with("~/") {
+        if let Some(home) = home_dir() {
+            return format!("{}{}", home.to_string_lossy(), &path[1..]);
+        }
+    }
+    String::from(path)
+}
+
+/// Returns the directory that trusty keeps its data (like prompt history) in.
+/// This follows the XDG base directory spec, so it is usually `~/.local/share/trusty`.
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// Returns the directory that trusty's configuration files are kept in.
+/// This follows the XDG base directory spec, so it is usually `~/.config/trusty`.
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Returns `$<var>/trusty`, or `~/<fallback>/trusty` if the variable isn't set.
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    let base = match env::var_os(var).filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => home_dir()?.join(fallback),
+    };
+    Some(base.join("trusty"))
+}
@@ -0,0 +1,150 @@
+use termion::event::Key;
+use fuzzy;
+
+/// An entry in a Picker.
+#[derive(Clone, Debug)]
+pub struct PickerItem {
+    /// The text that the query is matched against.
+    pub label: String,
+    /// Extra information that is shown after the label but not searched.
+    pub detail: String,
+}
+
+impl PickerItem {
+    pub fn new(label: String, detail: String) -> PickerItem {
+        PickerItem {
+            label: label,
+            detail: detail,
+        }
+    }
+}
+
+/// What the user decided to do with a Picker.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Picked {
+    /// The item at the index was chosen.
+    Open(usize),
+    /// The user asked to remove the item at the index.
+    Remove(usize),
+    /// The user closed the picker without choosing anything.
+    Cancel,
+}
+
+/// A Picker lets the user choose one item from a list by moving
+/// a selection with the arrow keys or typing to fuzzy filter the list.
+/// It only keeps track of the state, the Frontend is what draws it.
+#[derive(Debug)]
+pub struct Picker {
+    /// The title shown above the list.
+    pub title: String,
+    items: Vec<PickerItem>,
+    /// What the user has typed so far.
+    query: String,
+    /// Indices into `items` of the items that match the query, best first.
+    matches: Vec<usize>,
+    /// The index into `matches` of the selected item.
+    selected: usize,
+    /// Are the items numbered? Numbered pickers let you jump to
+    /// an item by typing its number.
+    numbered: bool,
+}
+
+impl Picker {
+    /// Creates a new Picker with the given title and items.
+    pub fn new(title: &str, items: Vec<PickerItem>) -> Picker {
+        let mut picker = Picker {
+            title: String::from(title),
+            items: items,
+            query: String::new(),
+            matches: Vec::new(),
+            selected: 0,
+            numbered: false,
+        };
+        picker.update_matches();
+        picker
+    }
+    /// Numbers the items, starting from 1.
+    pub fn numbered(mut self) -> Picker {
+        self.numbered = true;
+        self
+    }
+    /// Are the items numbered?
+    pub fn is_numbered(&self) -> bool {
+        self.numbered
+    }
+    /// Replaces the items, keeping the query.
+    pub fn set_items(&mut self, items: Vec<PickerItem>) {
+        self.items = items;
+        self.update_matches();
+    }
+    /// Selects the item at `index` in the full item list, if it matches the query.
+    pub fn select(&mut self, index: usize) {
+        if let Some(i) = self.matches.iter().position(|&m| m == index) {
+            self.selected = i;
+        }
+    }
+    /// Returns the query typed so far.
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+    /// Returns the number of items, whether or not they match the query.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    /// Are there no items at all?
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    /// Returns the item at `index` in the full item list.
+    pub fn item(&self, index: usize) -> &PickerItem {
+        &self.items[index]
+    }
+    /// Returns the indices of the items that match the query, best first.
+    pub fn matches(&self) -> &[usize] {
+        &self.matches
+    }
+    /// Returns the index of the selected item in the full item list.
+    pub fn selected(&self) -> Option<usize> {
+        self.matches.get(self.selected).cloned()
+    }
+    /// Returns the position of the selection in the list of matches.
+    pub fn selected_position(&self) -> usize {
+        self.selected
+    }
+    /// Updates the picker in response to a key press.
+    /// Returns Some once the user has made up their mind.
+    pub fn handle_key(&mut self, key: Key) -> Option<Picked> {
+        match key {
+            Key::Esc | Key::Ctrl('c') | Key::Ctrl('g') => return Some(Picked::Cancel),
+            Key::Char('\n') => return self.selected().map(Picked::Open),
+            Key::Ctrl('x') | Key::Delete => return self.selected().map(Picked::Remove),
+            Key::Up | Key::Ctrl('p') if self.selected > 0 => self.selected -= 1,
+            Key::Down | Key::Ctrl('n') if self.selected + 1 < self.matches.len() => self.selected += 1,
+            Key::Backspace => {
+                self.query.pop();
+                self.update_matches();
+            },
+            Key::Char(c) if !c.is_control() => {
+                self.query.push(c);
+                self.update_matches();
+            },
+            _ => {},
+        }
+        None
+    }
+    /// Refilters the items after the query changed.
+    fn update_matches(&mut self) {
+        let labels: Vec<&str> = self.items.iter().map(|i| i.label.as_str()).collect();
+        self.matches = fuzzy::filter(&self.query, &labels);
+        if self.numbered {
+            // Typing a number jumps to the item with that number.
+            if let Ok(n) = self.query.parse::<usize>() {
+                if n >= 1 && n <= self.items.len() {
+                    self.matches.retain(|&i| i != n - 1);
+                    self.matches.insert(0, n - 1);
+                }
+            }
+        }
+        self.selected = 0;
+    }
+}
@@ -0,0 +1,465 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::ptr;
+use std::rc::Rc;
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+use backend::Backend;
+use frontend::Frontend;
+use cursor::Cursor;
+use columns::line_len;
+use hooks::{Event, Hook};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// The most operations that a script can do each time it runs. trusty can't be used
+/// while a script runs, so a script stuck in a loop is stopped instead of hanging it.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// A command that a script added with `command(name, description, function)`.
+#[derive(Clone, Debug)]
+pub struct ScriptCommand {
+    pub name: String,
+    pub description: String,
+    /// The index of the script that defines the function.
+    script: usize,
+    /// The name of the function in the script that runs the command.
+    function: String,
+}
+
+/// Something that a script asked the editor to do.
+/// These happen once the script has finished running.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    /// Show a message to the user.
+    Message(String),
+    /// Run a command, by name.
+    RunCommand(String),
+    /// Bind keys, like `ctrl-x u`, to a command.
+    Bind(String, String),
+    /// Save the buffer with this id, under a new filename if one is given.
+    Save(usize, Option<String>),
+}
+
+/// What the functions that scripts call work on.
+/// `backend` and `frontend` are only set while a script is running, which is
+/// when the editor has lent them to `Scripts::run`. Otherwise they are null.
+struct Host {
+    backend: *mut Backend,
+    frontend: *mut Frontend,
+    /// The script that is being loaded, so that `command` knows where its function is.
+    loading: usize,
+    commands: Vec<ScriptCommand>,
+    /// The functions added with `on(hook, function)`, with the index of their script.
+    hooks: Vec<(Hook, usize, String)>,
+    requests: Vec<Request>,
+}
+
+type Shared = Rc<RefCell<Host>>;
+
+/// The user's scripts, which are written in Rhai (https://rhai.rs) and live in
+/// the `scripts` directory in trusty's config directory. Each `.rhai` file there
+/// is run when trusty starts, and can add commands and bind keys to them:
+///
+/// ```rhai
+/// fn upcase_line() {
+///     let n = cursor_line();
+///     set_line(n, line(n).to_upper());
+/// }
+/// command("upcase-line", "Make the current line upper case", "upcase_line");
+/// bind("ctrl-x u", "upcase-line");
+/// ```
+///
+/// Lines and columns count from 0. The functions that scripts can call are:
+///
+/// - `line_count()`, `line(n)`, `lines()`, `set_line(n, text)`, `insert_line(n, text)`,
+///   `delete_line(n)` and `insert(text)` read and change the current buffer.
+/// - `cursor_line()`, `cursor_column()` and `move_cursor(line, column)` for the cursor.
+/// - `filename()`, `open(path)`, `save()`, `save_as(path)`, `new_buffer()`,
+///   `next_buffer()` and `previous_buffer()` work with buffers. Saving happens
+///   once the script has finished, so that the save hooks fire like for any save.
+/// - `message(text)`, `prompt(text)` and `confirm(text)` talk to the user.
+/// - `command(name, description, function)`, `bind(keys, command)` and
+///   `run_command(name)` work with commands.
+/// - `on(hook, function)` calls the function every time the hook fires, like
+///   `on("before-save", "format")`. The function is given a map with the `hook`,
+///   the buffer's `filename` and the vi `mode`, and the buffer that the hook
+///   is about is the current one while it runs.
+pub struct Scripts {
+    engine: Engine,
+    /// The scripts, with their filenames for error messages.
+    scripts: Vec<(String, AST)>,
+    host: Shared,
+}
+
+impl Scripts {
+    /// Creates a Scripts with no scripts loaded.
+    pub fn new() -> Scripts {
+        let host = Rc::new(RefCell::new(Host {
+            backend: ptr::null_mut(),
+            frontend: ptr::null_mut(),
+            loading: 0,
+            commands: Vec::new(),
+            hooks: Vec::new(),
+            requests: Vec::new(),
+        }));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        register_functions(&mut engine, &host);
+        Scripts {
+            engine: engine,
+            scripts: Vec::new(),
+            host: host,
+        }
+    }
+    /// Loads and runs every `.rhai` file in `dir`, in order of their names.
+    /// Returns a description of each problem, along with what the scripts asked for.
+    pub fn load(&mut self, dir: &Path, backend: &mut Backend, frontend: &mut Frontend)
+                -> (Vec<String>, Vec<Request>) {
+        let mut paths: Vec<_> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|e| e == "rhai"))
+                .collect(),
+            // Not having any scripts is fine.
+            Err(_) => return (Vec::new(), Vec::new()),
+        };
+        paths.sort();
+        let mut errors = Vec::new();
+        for path in paths {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let ast = match self.engine.compile_file(path) {
+                Ok(ast) => ast,
+                Err(e) => {
+                    errors.push(format!("{}: {}", name, e));
+                    continue;
+                },
+            };
+            self.host.borrow_mut().loading = self.scripts.len();
+            let engine = &self.engine;
+            // The script can only use the backend and frontend until `run` returns.
+            let result = self.run(backend, frontend, || engine.run_ast_with_scope(&mut Scope::new(), &ast));
+            if let Err(e) = result {
+                errors.push(format!("{}: {}", name, e));
+            }
+            self.scripts.push((name, ast));
+        }
+        (errors, self.take_requests())
+    }
+    /// Returns the commands that the scripts have added.
+    pub fn commands(&self) -> Vec<ScriptCommand> {
+        self.host.borrow().commands.clone()
+    }
+    /// Finds the command that a script added called `name`.
+    pub fn command(&self, name: &str) -> Option<ScriptCommand> {
+        self.host.borrow().commands.iter().find(|c| c.name == name).cloned()
+    }
+    /// Runs a command that a script added.
+    /// Returns what the script asked for, or a description of what went wrong.
+    pub fn run_command(&mut self, command: &ScriptCommand, backend: &mut Backend,
+                       frontend: &mut Frontend) -> Result<Vec<Request>, String> {
+        let (ref script, ref ast) = self.scripts[command.script];
+        let engine = &self.engine;
+        // The script can only use the backend and frontend until `run` returns.
+        let result = self.run(backend, frontend, || {
+            let options = CallFnOptions::new().eval_ast(false);
+            engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, &command.function, ())
+                .map(|_| ())
+        });
+        let requests = self.take_requests();
+        match result {
+            Ok(()) => Ok(requests),
+            Err(e) => Err(format!("{} ({}): {}", command.name, script, e)),
+        }
+    }
+    /// Calls the functions hooked onto `event.hook`.
+    /// Returns a description of each one that failed, along with what they asked for.
+    pub fn fire(&mut self, event: &Event, backend: &mut Backend, frontend: &mut Frontend)
+                -> (Vec<String>, Vec<Request>) {
+        let handlers: Vec<(usize, String)> = self.host.borrow().hooks.iter()
+            .filter(|&&(hook, _, _)| hook == event.hook)
+            .map(|&(_, script, ref function)| (script, function.clone()))
+            .collect();
+        if handlers.is_empty() {
+            return (Vec::new(), Vec::new());
+        }
+        let mut map = Map::new();
+        map.insert("hook".into(), Dynamic::from(String::from(event.hook.name())));
+        map.insert("filename".into(), optional(event.filename.clone()));
+        map.insert("mode".into(), optional(event.mode.map(String::from)));
+        let mut errors = Vec::new();
+        for (script, function) in handlers {
+            let (ref name, ref ast) = self.scripts[script];
+            let engine = &self.engine;
+            // The script can only use the backend and frontend until `run` returns.
+            let result = self.run(backend, frontend, || {
+                let options = CallFnOptions::new().eval_ast(false);
+                engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, &function, (map.clone(),))
+                    .map(|_| ())
+            });
+            if let Err(e) = result {
+                errors.push(format!("{} ({}): {}", event.hook.name(), name, e));
+            }
+        }
+        (errors, self.take_requests())
+    }
+    /// Lends `backend` and `frontend` to the scripts while `f` runs them.
+    ///
+    /// This is what makes the `unsafe` in `with_backend` and `with_frontend` sound:
+    /// the pointers in the host are only set while this has `backend` and `frontend`
+    /// borrowed mutably, so nothing else can touch them, and they are cleared before
+    /// it returns. The functions that scripts call only use one of them at a time and
+    /// never call back into a script while they have it, so there is never more than
+    /// one `&mut` to either of them.
+    fn run<F>(&self, backend: &mut Backend, frontend: &mut Frontend, f: F) -> ScriptResult<()>
+        where F: FnOnce() -> ScriptResult<()>
+    {
+        {
+            let mut host = self.host.borrow_mut();
+            host.backend = backend;
+            host.frontend = frontend;
+        }
+        let result = f();
+        let mut host = self.host.borrow_mut();
+        host.backend = ptr::null_mut();
+        host.frontend = ptr::null_mut();
+        result
+    }
+    fn take_requests(&self) -> Vec<Request> {
+        self.host.borrow_mut().requests.drain(..).collect()
+    }
+}
+
+/// Gives `f` the backend that the editor lent to the running script.
+fn with_backend<T, F>(host: &Shared, f: F) -> ScriptResult<T>
+    where F: FnOnce(&mut Backend) -> ScriptResult<T>
+{
+    let backend = host.borrow().backend;
+    if backend.is_null() {
+        return Err("the buffers can only be used while a command is running".into());
+    }
+    // This is safe because the pointer is only set while `Scripts::run` has the
+    // backend borrowed, and nothing else uses the backend until that returns.
+    // `f` mustn't run any script code, which could get here again.
+    f(unsafe { &mut *backend })
+}
+
+/// Gives `f` the frontend that the editor lent to the running script.
+fn with_frontend<T, F>(host: &Shared, f: F) -> ScriptResult<T>
+    where F: FnOnce(&mut Frontend) -> T
+{
+    let frontend = host.borrow().frontend;
+    if frontend.is_null() {
+        return Err("the screen can only be used while a command is running".into());
+    }
+    // See with_backend for why this is safe.
+    Ok(f(unsafe { &mut *frontend }))
+}
+
+/// Checks that `n` is the number of a line in the current buffer.
+fn line_number(backend: &Backend, n: INT) -> ScriptResult<usize> {
+    if n < 0 || n as usize >= backend.number_of_lines() {
+        return Err(format!("there is no line {}", n).into());
+    }
+    Ok(n as usize)
+}
+
+/// Turns an optional string into the string, or () for None.
+fn optional(text: Option<String>) -> Dynamic {
+    text.map_or(Dynamic::UNIT, Dynamic::from)
+}
+
+/// Gives the scripts their functions.
+fn register_functions(engine: &mut Engine, host: &Shared) {
+    // Printing would mess up the screen, so it shows a message instead.
+    let h = host.clone();
+    engine.on_print(move |text| h.borrow_mut().requests.push(Request::Message(String::from(text))));
+    let h = host.clone();
+    engine.on_debug(move |text, _, _| h.borrow_mut().requests.push(Request::Message(String::from(text))));
+
+    // The current buffer.
+    let h = host.clone();
+    engine.register_fn("line_count", move || {
+        with_backend(&h, |backend| Ok(backend.number_of_lines() as INT))
+    });
+    let h = host.clone();
+    engine.register_fn("line", move |n: INT| {
+        with_backend(&h, |backend| {
+            let n = line_number(backend, n)?;
+            Ok(backend.current_lines()[n].clone())
+        })
+    });
+    let h = host.clone();
+    engine.register_fn("lines", move || {
+        with_backend(&h, |backend| {
+            Ok(backend.current_lines().iter().cloned().map(Dynamic::from).collect::<Array>())
+        })
+    });
+    let h = host.clone();
+    engine.register_fn("set_line", move |n: INT, text: &str| {
+        with_backend(&h, |backend| {
+            let n = line_number(backend, n)?;
+            let buffer = backend.current_buffer_mut();
+            let len = line_len(&buffer.lines()[n]);
+            buffer.delete_between(Cursor::new(n, 0), Cursor::new(n, len));
+            buffer.insert_text_at(Cursor::new(n, 0), text);
+            clamp_cursor(backend);
+            Ok(())
+        })
+    });
+    let h = host.clone();
+    engine.register_fn("insert_line", move |n: INT, text: &str| {
+        with_backend(&h, |backend| {
+            let count = backend.number_of_lines();
+            let buffer = backend.current_buffer_mut();
+            if n >= 0 && (n as usize) < count {
+                buffer.insert_text_at(Cursor::new(n as usize, 0), &format!("{}\n", text));
+            } else if n as usize == count {
+                // Adding a line after the last one.
+                let end = Cursor::new(count - 1, line_len(&buffer.lines()[count - 1]));
+                buffer.insert_text_at(end, &format!("\n{}", text));
+            } else {
+                return Err(format!("can't insert a line at {}", n).into());
+            }
+            Ok(())
+        })
+    });
+    let h = host.clone();
+    engine.register_fn("delete_line", move |n: INT| {
+        with_backend(&h, |backend| {
+            let n = line_number(backend, n)?;
+            backend.current_buffer_mut().delete_lines(n, n);
+            clamp_cursor(backend);
+            Ok(())
+        })
+    });
+    let h = host.clone();
+    engine.register_fn("insert", move |text: &str| {
+        with_backend(&h, |backend| {
+            backend.insert_text(text);
+            Ok(())
+        })
+    });
+
+    // The cursor.
+    let h = host.clone();
+    engine.register_fn("cursor_line", move || {
+        with_backend(&h, |backend| Ok(backend.cursor().line as INT))
+    });
+    let h = host.clone();
+    engine.register_fn("cursor_column", move || {
+        with_backend(&h, |backend| Ok(backend.cursor().column as INT))
+    });
+    let h = host.clone();
+    engine.register_fn("move_cursor", move |line: INT, column: INT| {
+        with_backend(&h, |backend| {
+            *backend.cursor_mut() = Cursor::new(line.max(0) as usize, column.max(0) as usize);
+            clamp_cursor(backend);
+            Ok(())
+        })
+    });
+
+    // Buffers.
+    let h = host.clone();
+    engine.register_fn("filename", move || {
+        with_backend(&h, |backend| Ok(optional(backend.filename().clone())))
+    });
+    let h = host.clone();
+    engine.register_fn("open", move |path: &str| {
+        with_backend(&h, |backend| {
+            backend.new_buffer_from_filename(String::from(path))
+                .map_err(|e| format!("can't open {}: {}", path, e).into())
+        })
+    });
+    let h = host.clone();
+    engine.register_fn("save", move || -> ScriptResult<()> {
+        let buffer = with_backend(&h, |backend| {
+            if backend.filename().is_none() {
+                return Err("the buffer doesn't have a filename, use save_as".into());
+            }
+            Ok(backend.current_buffer().id())
+        })?;
+        h.borrow_mut().requests.push(Request::Save(buffer, None));
+        Ok(())
+    });
+    let h = host.clone();
+    engine.register_fn("save_as", move |path: &str| -> ScriptResult<()> {
+        let buffer = with_backend(&h, |backend| Ok(backend.current_buffer().id()))?;
+        h.borrow_mut().requests.push(Request::Save(buffer, Some(String::from(path))));
+        Ok(())
+    });
+    let h = host.clone();
+    engine.register_fn("new_buffer", move || {
+        with_backend(&h, |backend| {
+            backend.new_empty_buffer();
+            Ok(())
+        })
+    });
+    let h = host.clone();
+    engine.register_fn("next_buffer", move || {
+        with_backend(&h, |backend| {
+            backend.switch_to_next_buffer();
+            Ok(())
+        })
+    });
+    let h = host.clone();
+    engine.register_fn("previous_buffer", move || {
+        with_backend(&h, |backend| {
+            backend.switch_to_previous_buffer();
+            Ok(())
+        })
+    });
+
+    // Talking to the user.
+    let h = host.clone();
+    engine.register_fn("message", move |text: &str| {
+        h.borrow_mut().requests.push(Request::Message(String::from(text)));
+    });
+    let h = host.clone();
+    engine.register_fn("prompt", move |text: &str| {
+        with_frontend(&h, |frontend| optional(frontend.prompt_for_text(text)))
+    });
+    let h = host.clone();
+    engine.register_fn("confirm", move |text: &str| {
+        with_frontend(&h, |frontend| frontend.prompt_for_bool(text))
+    });
+
+    // Commands.
+    let h = host.clone();
+    engine.register_fn("command", move |name: &str, description: &str, function: &str| {
+        let mut host = h.borrow_mut();
+        let script = host.loading;
+        host.commands.retain(|c| c.name != name);
+        host.commands.push(ScriptCommand {
+            name: String::from(name),
+            description: String::from(description),
+            script: script,
+            function: String::from(function),
+        });
+    });
+    let h = host.clone();
+    engine.register_fn("bind", move |keys: &str, command: &str| {
+        h.borrow_mut().requests.push(Request::Bind(String::from(keys), String::from(command)));
+    });
+    let h = host.clone();
+    engine.register_fn("on", move |hook: &str, function: &str| -> ScriptResult<()> {
+        let hook = match Hook::from_name(hook) {
+            Some(hook) => hook,
+            None => return Err(format!("there is no hook called {}", hook).into()),
+        };
+        let mut host = h.borrow_mut();
+        let script = host.loading;
+        host.hooks.push((hook, script, String::from(function)));
+        Ok(())
+    });
+    let h = host.clone();
+    engine.register_fn("run_command", move |name: &str| {
+        h.borrow_mut().requests.push(Request::RunCommand(String::from(name)));
+    });
+}
+
+/// Keeps the cursor inside the text after a script changed it.
+fn clamp_cursor(backend: &mut Backend) {
+    let lines = backend.current_lines().clone();
+    backend.cursor_mut().clamp(&lines);
+}
@@ -0,0 +1,199 @@
+use cursor::Cursor;
+
+/// The most groups of edits that can be undone.
+const MAX_UNDOS: usize = 1000;
+
+/// One of the small edits that every change to the text is made of,
+/// with what it takes to undo it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+    /// `c` was inserted at `line`, `column`.
+    InsertChar { line: usize, column: usize, c: char },
+    /// `c` was deleted from `line`, `column`.
+    DeleteChar { line: usize, column: usize, c: char },
+    /// `line` was split in two at `column`.
+    SplitLine { line: usize, column: usize },
+    /// `line` was moved onto the end of the line before it, which was `column` long.
+    JoinLine { line: usize, column: usize },
+    /// A line of `text` was inserted before `line`.
+    InsertLine { line: usize, text: String },
+    /// `line`, which had `text` in it, was deleted.
+    DeleteLine { line: usize, text: String },
+    /// The text of `line` went from `old` to `new`.
+    ReplaceLine { line: usize, old: String, new: String },
+}
+
+impl Edit {
+    /// Returns the edit that puts things back the way they were before this one.
+    pub fn inverse(self) -> Edit {
+        match self {
+            Edit::InsertChar { line, column, c } => Edit::DeleteChar { line: line, column: column, c: c },
+            Edit::DeleteChar { line, column, c } => Edit::InsertChar { line: line, column: column, c: c },
+            Edit::SplitLine { line, column } => Edit::JoinLine { line: line + 1, column: column },
+            Edit::JoinLine { line, column } => Edit::SplitLine { line: line - 1, column: column },
+            Edit::InsertLine { line, text } => Edit::DeleteLine { line: line, text: text },
+            Edit::DeleteLine { line, text } => Edit::InsertLine { line: line, text: text },
+            Edit::ReplaceLine { line, old, new } => Edit::ReplaceLine { line: line, old: new, new: old },
+        }
+    }
+}
+
+/// Edits that are undone and redone together, and where the cursor was before them.
+#[derive(Clone, Debug)]
+pub struct Group {
+    pub edits: Vec<Edit>,
+    pub cursor: Cursor,
+}
+
+/// The UndoHistory of a buffer keeps the edits made to it in groups, so that
+/// a whole command can be undone at once, and keeps what was undone so that
+/// it can be redone until something else is edited.
+#[derive(Debug)]
+pub struct UndoHistory {
+    /// The groups that can be undone, oldest first.
+    undo: Vec<Group>,
+    /// The groups that were undone and can be redone, the next one to redo last.
+    redo: Vec<Group>,
+    /// The edits made since the last group was ended.
+    current: Option<Group>,
+    /// How many groups could be undone when the buffer was saved,
+    /// or None if undoing and redoing can't get back to that.
+    saved: Option<usize>,
+}
+
+impl UndoHistory {
+    /// Creates a history with nothing to undo, for text that is as it was saved.
+    pub fn new() -> UndoHistory {
+        UndoHistory {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            current: None,
+            saved: Some(0),
+        }
+    }
+    /// Adds `edit` to the current group, starting a new group if there isn't one.
+    /// `cursor` is where the cursor was, in case the group starts here.
+    pub fn record(&mut self, edit: Edit, cursor: Cursor) {
+        self.current.get_or_insert(Group { edits: Vec::new(), cursor: cursor }).edits.push(edit);
+    }
+    /// Ends the current group, so that the next edit starts a new one.
+    /// New edits mean that what was undone can't be redone any more.
+    pub fn end_group(&mut self) {
+        let group = match self.current.take() {
+            Some(group) => group,
+            None => return,
+        };
+        if self.saved.is_some_and(|saved| saved > self.undo.len()) {
+            self.saved = None;
+        }
+        self.redo.clear();
+        self.undo.push(group);
+        if self.undo.len() > MAX_UNDOS {
+            self.undo.remove(0);
+            self.saved = self.saved.and_then(|saved| saved.checked_sub(1));
+        }
+    }
+    /// Takes the newest group to undo and keeps it to be redone.
+    pub fn undo(&mut self) -> Option<Group> {
+        self.end_group();
+        let group = self.undo.pop()?;
+        self.redo.push(group.clone());
+        Some(group)
+    }
+    /// Takes the group that was undone last to do it again.
+    pub fn redo(&mut self) -> Option<Group> {
+        self.end_group();
+        let group = self.redo.pop()?;
+        self.undo.push(group.clone());
+        Some(group)
+    }
+    /// Forgets the edits made since the last group was ended. Undoing and redoing
+    /// record their edits like any others, and this throws them away.
+    pub fn discard(&mut self) {
+        self.current = None;
+    }
+    /// Remembers that the text was just saved.
+    pub fn mark_saved(&mut self) {
+        self.end_group();
+        self.saved = Some(self.undo.len());
+    }
+    /// Is the text the same as when it was last saved?
+    pub fn is_saved(&self) -> bool {
+        self.current.is_none() && self.saved == Some(self.undo.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(column: usize) -> Edit {
+        Edit::InsertChar { line: 0, column: column, c: 'x' }
+    }
+
+    #[test]
+    fn inverses_undo_each_other() {
+        let edits = vec![
+            edit(3),
+            Edit::SplitLine { line: 2, column: 5 },
+            Edit::JoinLine { line: 2, column: 5 },
+            Edit::InsertLine { line: 1, text: String::from("a") },
+            Edit::ReplaceLine { line: 1, old: String::from("a"), new: String::from("b") },
+        ];
+        for e in edits {
+            assert_ne!(e.clone().inverse(), e);
+            assert_eq!(e.clone().inverse().inverse(), e);
+        }
+        assert_eq!(Edit::SplitLine { line: 2, column: 5 }.inverse(), Edit::JoinLine { line: 3, column: 5 });
+    }
+
+    #[test]
+    fn groups_undo_and_redo_together() {
+        let mut history = UndoHistory::new();
+        history.record(edit(0), Cursor::new(0, 0));
+        history.record(edit(1), Cursor::new(0, 1));
+        history.end_group();
+        history.record(edit(2), Cursor::new(0, 2));
+        let group = history.undo().unwrap();
+        assert_eq!(group.edits, [edit(2)]);
+        let group = history.undo().unwrap();
+        assert_eq!(group.edits, [edit(0), edit(1)]);
+        assert_eq!(group.cursor, Cursor::new(0, 0));
+        assert!(history.undo().is_none());
+        assert_eq!(history.redo().unwrap().edits, [edit(0), edit(1)]);
+        assert_eq!(history.redo().unwrap().edits, [edit(2)]);
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn new_edits_forget_the_redos() {
+        let mut history = UndoHistory::new();
+        history.record(edit(0), Cursor::new(0, 0));
+        history.undo();
+        history.discard();
+        history.record(edit(5), Cursor::new(0, 0));
+        history.end_group();
+        assert!(history.redo().is_none());
+        assert_eq!(history.undo().unwrap().edits, [edit(5)]);
+    }
+
+    #[test]
+    fn knows_when_the_text_is_as_saved() {
+        let mut history = UndoHistory::new();
+        assert!(history.is_saved());
+        history.record(edit(0), Cursor::new(0, 0));
+        assert!(!history.is_saved());
+        history.mark_saved();
+        assert!(history.is_saved());
+        history.undo();
+        assert!(!history.is_saved());
+        history.redo();
+        assert!(history.is_saved());
+        // Once the saved text is only in the redos, editing means it can't come back.
+        history.undo();
+        history.record(edit(1), Cursor::new(0, 0));
+        history.end_group();
+        history.undo();
+        assert!(!history.is_saved());
+    }
+}
@@ -0,0 +1,1478 @@
+use std::cmp;
+use std::mem;
+use termion::event::Key;
+use backend::{Backend, Buffer, Change};
+use cursor::Cursor;
+use columns::line_len;
+use keymap;
+use brackets;
+
+/// The modes that vi can be in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Keys are commands that move around and change the text.
+    Normal,
+    /// Keys type text.
+    Insert,
+    /// Keys move the end of a selection, and operators work on the selection.
+    Visual,
+    /// Like Visual, but the selection is always whole lines.
+    VisualLine,
+}
+
+impl Mode {
+    /// Returns the name of the mode, like "normal".
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Mode::Normal => "normal",
+            Mode::Insert => "insert",
+            Mode::Visual => "visual",
+            Mode::VisualLine => "visual-line",
+        }
+    }
+}
+
+/// What the editor should do after vi has looked at a key.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// vi did everything that needed doing.
+    Handled,
+    /// vi doesn't use the key, so it should go through the normal keymap.
+    Pass(Key),
+    /// The user typed `:`, so the editor should read a command line.
+    CommandLine,
+    /// Something to tell the user.
+    Message(String),
+    /// The key couldn't be done, like `j` on the last line. This stops macros.
+    Failed,
+    /// `q` and a register: start recording a macro into the register.
+    RecordMacro(char),
+    /// `@` and a register: play the macro in the register this many times.
+    /// The register is `@` for the last macro played.
+    PlayMacro(char, usize),
+    /// The cursor jumped a long way, like `G`, from where it was. That goes in the jump list.
+    Jumped(Cursor),
+    /// `m` and a name: set the mark with that name.
+    SetMark(char),
+    /// `` ` `` and a name: jump to the mark with that name.
+    /// It is `'` when the bool is false, which jumps to the first non-blank on the mark's line.
+    JumpToMark(char, bool),
+    /// `ctrl-o`: go back to where the cursor was before the last jump.
+    JumpBack,
+    /// `tab`, which is `ctrl-i` to a terminal: go forward again after `ctrl-o`.
+    JumpForward,
+}
+
+/// The operators, which change the text that a motion moves over.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Dedent,
+}
+
+/// The ways that the cursor can move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// Down to the first non-blank character of the next line, like enter.
+    NextLine,
+    /// `w`, or `W` when the bool is true.
+    WordForward(bool),
+    /// `b`, or `B` when the bool is true.
+    WordBackward(bool),
+    /// `e`, or `E` when the bool is true.
+    WordEnd(bool),
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    /// `gg` when the bool is false and `G` when it is true. A count picks the line.
+    GotoLine(bool),
+    /// `f` and `t` (when the bool is true) find a character later in the line.
+    FindForward(char, bool),
+    /// `F` and `T` (when the bool is true) find a character earlier in the line.
+    FindBackward(char, bool),
+    /// `%` goes to the bracket that matches the next bracket on the line.
+    MatchingBracket,
+}
+
+impl Motion {
+    /// Do operators with this motion work on whole lines?
+    fn linewise(&self) -> bool {
+        matches!(*self, Motion::Up | Motion::Down | Motion::NextLine | Motion::GotoLine(_))
+    }
+    /// Does the motion go somewhere relative to the cursor, so that
+    /// staying put means it failed, like `l` at the end of the line?
+    fn relative(&self) -> bool {
+        !matches!(*self, Motion::LineStart | Motion::FirstNonBlank | Motion::LineEnd | Motion::GotoLine(_))
+    }
+    /// Do operators with this motion include the character that the motion ends on?
+    fn inclusive(&self) -> bool {
+        matches!(*self, Motion::WordEnd(_) | Motion::LineEnd | Motion::FindForward(..) | Motion::MatchingBracket)
+    }
+}
+
+/// The text objects, which are picked with `i` (the inner object) or `a` (all of it).
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Object {
+    /// A word, or a WORD when the bool is true.
+    Word(bool),
+    /// Text between a pair of quotes.
+    Quote(char),
+    /// Text between a pair of brackets.
+    Bracket(char, char),
+    /// Lines up to the next blank line.
+    Paragraph,
+}
+
+/// What an operator works on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    Motion(Motion),
+    /// A text object. The bool is true for the inner object.
+    Object(Object, bool),
+    /// The current line, like `dd` or `>>`.
+    Line,
+    /// The selection in visual mode.
+    Selection,
+}
+
+/// The ways of starting insert mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum InsertAt {
+    Before,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+/// A command read from the keys typed in normal or visual mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+    Move(Motion),
+    Operate(Operator, Target),
+    Insert(InsertAt),
+    /// `P` when the bool is true, `p` otherwise.
+    Paste(bool),
+    Replace(char),
+    Join,
+    /// Start (or switch to) visual mode. The bool is true for `V`.
+    Visual(bool),
+    /// Select a text object in visual mode.
+    Select(Object, bool),
+    /// Swap the ends of the selection.
+    SwapEnds,
+    CommandLine,
+    Repeat,
+    Undo,
+    Redo,
+    Escape,
+    RecordMacro(char),
+    PlayMacro(char),
+    SetMark(char),
+    /// `` ` `` when the bool is true, `'` otherwise.
+    JumpToMark(char, bool),
+    JumpBack,
+    JumpForward,
+}
+
+impl Action {
+    /// Does the action change the text, so that `.` should repeat it?
+    fn is_change(&self) -> bool {
+        match *self {
+            Action::Operate(Operator::Yank, _) => false,
+            Action::Operate(_, Target::Selection) => false,
+            Action::Operate(..) | Action::Insert(_) | Action::Paste(_) |
+            Action::Replace(_) | Action::Join => true,
+            _ => false,
+        }
+    }
+}
+
+/// The result of reading the keys typed so far.
+#[derive(Debug, PartialEq)]
+enum Parse {
+    /// The keys are the start of a command.
+    Incomplete,
+    /// The keys aren't a command.
+    Invalid,
+    /// The keys are a whole command, with the count given for it if there was one.
+    Done(Option<usize>, Action),
+}
+
+/// Text that has been deleted or yanked, ready to be put back with `p`.
+#[derive(Clone, Debug, Default)]
+struct Register {
+    text: String,
+    /// Was the text whole lines?
+    linewise: bool,
+}
+
+/// A range of text that an operator works on.
+/// `end` isn't included, unless the range is linewise in which case
+/// it is all of the lines from `start.line` to `end.line`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Range {
+    start: Cursor,
+    end: Cursor,
+    linewise: bool,
+}
+
+/// Vi is a modal way of editing, for the people who have vi in their fingers.
+/// It sits in front of the keymap: it handles the keys that it knows about,
+/// and passes the rest on to the editor.
+#[derive(Debug)]
+pub struct Vi {
+    mode: Mode,
+    /// The keys typed so far of a command that isn't finished.
+    pending: Vec<Key>,
+    /// The end of the selection that stays put in visual mode.
+    anchor: Cursor,
+    register: Register,
+    /// The keys of a change that is still going, like the text typed after `cw`.
+    recording: Option<Vec<Key>>,
+    /// The keys of the last change, which `.` repeats.
+    last_change: Vec<Key>,
+}
+
+impl Vi {
+    /// Creates a Vi that starts in normal mode.
+    pub fn new() -> Vi {
+        Vi {
+            mode: Mode::Normal,
+            pending: Vec::new(),
+            anchor: Cursor::new(0, 0),
+            register: Register::default(),
+            recording: None,
+            last_change: Vec::new(),
+        }
+    }
+    /// Returns the mode that vi is in.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+    /// Returns what should be shown to the user about the state of vi:
+    /// the mode, or the keys of a command that is being typed.
+    pub fn status(&self) -> Option<String> {
+        match self.mode {
+            Mode::Insert => Some(String::from("-- INSERT --")),
+            Mode::Visual => Some(String::from("-- VISUAL --")),
+            Mode::VisualLine => Some(String::from("-- VISUAL LINE --")),
+            Mode::Normal if !self.pending.is_empty() => Some(keymap::keys_name(&self.pending)),
+            Mode::Normal => None,
+        }
+    }
+    /// Is a command half typed, like `d` waiting for its motion?
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+    /// Returns the selection in visual mode as the start, the end (which isn't
+    /// included) and whether it is whole lines.
+    pub fn selection(&self, cursor: Cursor, lines: &[String]) -> Option<(Cursor, Cursor, bool)> {
+        match self.mode {
+            Mode::Visual | Mode::VisualLine => {
+                let range = self.selected_range(cursor, lines);
+                Some((range.start, range.end, range.linewise))
+            },
+            _ => None,
+        }
+    }
+    /// Moves the end of the selection that stays put so that it stays on the same text after `change`.
+    pub fn adjust(&mut self, change: &Change) {
+        self.anchor.adjust(change);
+    }
+    /// Goes back to normal mode if a selection is being made.
+    pub fn end_visual(&mut self) {
+        if let Mode::Visual | Mode::VisualLine = self.mode {
+            self.mode = Mode::Normal;
+        }
+    }
+    /// Goes back to normal mode, dropping anything half typed.
+    pub fn reset(&mut self) {
+        self.mode = Mode::Normal;
+        self.pending.clear();
+        self.recording = None;
+    }
+    /// Handles a key typed by the user.
+    pub fn handle_key(&mut self, key: Key, backend: &mut Backend) -> Outcome {
+        if self.mode == Mode::Insert {
+            return self.handle_insert_key(key, backend);
+        }
+        self.pending.push(key);
+        let visual = self.mode != Mode::Normal;
+        let (count, action) = match parse(&self.pending, visual) {
+            Parse::Incomplete => return Outcome::Handled,
+            Parse::Invalid => {
+                let keys = mem::take(&mut self.pending);
+                // Keys that vi never uses, like ctrl-s, are for the keymap.
+                return match keys[..] {
+                    [Key::Char(_)] | [Key::Esc] => Outcome::Handled,
+                    // Visual mode stays on, so that commands like
+                    // split-selection-into-cursors can use the selection.
+                    [key] => Outcome::Pass(key),
+                    _ => Outcome::Handled,
+                };
+            },
+            Parse::Done(count, action) => (count, action),
+        };
+        let keys = mem::take(&mut self.pending);
+        if action == Action::Repeat {
+            return self.repeat(count, backend);
+        }
+        let before = *backend.cursor();
+        let outcome = self.run(action, count, backend);
+        if action.is_change() && !visual {
+            if self.mode == Mode::Insert {
+                self.recording = Some(keys);
+            } else {
+                self.last_change = keys;
+            }
+        }
+        if self.mode == Mode::Normal {
+            keep_on_text(backend);
+        }
+        match action {
+            Action::Move(motion) if motion.relative() && *backend.cursor() == before => Outcome::Failed,
+            _ => outcome,
+        }
+    }
+    /// Handles a key in insert mode.
+    fn handle_insert_key(&mut self, key: Key, backend: &mut Backend) -> Outcome {
+        match key {
+            Key::Esc => {
+                self.mode = Mode::Normal;
+                if let Some(mut keys) = self.recording.take() {
+                    keys.push(key);
+                    self.last_change = keys;
+                }
+                // Like vi, the cursor goes back onto the last character typed.
+                let column = backend.cursor().column;
+                backend.cursor_mut().set_column(column.saturating_sub(1));
+                return Outcome::Handled;
+            },
+            Key::Char('\n') => backend.insert_newline(),
+            Key::Char('\t') => backend.insert_tab(),
+            Key::Char(c) => backend.insert_char(c),
+            Key::Backspace => backend.insert_backspace(),
+            // Anything else goes to the keymap, and isn't repeated by `.`.
+            _ => return Outcome::Pass(key),
+        }
+        if let Some(ref mut keys) = self.recording {
+            keys.push(key);
+        }
+        Outcome::Handled
+    }
+    /// Repeats the last change, with a new count if one is given.
+    fn repeat(&mut self, count: Option<usize>, backend: &mut Backend) -> Outcome {
+        let mut keys = self.last_change.clone();
+        if let Some(count) = count {
+            // Replace the counts that the change was typed with.
+            keys = count.to_string().chars().map(Key::Char).chain(without_counts(&keys)).collect();
+        }
+        for key in keys {
+            self.handle_key(key, backend);
+        }
+        Outcome::Handled
+    }
+    /// Runs an action. `count` is the count typed before it, if any.
+    fn run(&mut self, action: Action, count: Option<usize>, backend: &mut Backend) -> Outcome {
+        let cursor = *backend.cursor();
+        match action {
+            Action::Move(motion) => {
+                let buffer = backend.current_buffer();
+                let target = match motion {
+                    // Going up and down aims for the column that the cursor was in
+                    // before it went through any shorter lines.
+                    Motion::Up | Motion::Down => {
+                        let mut at = cursor;
+                        at.move_lines(buffer.lines(), count.unwrap_or(1), motion == Motion::Down,
+                                      buffer.settings().tab_width);
+                        Some(at)
+                    },
+                    _ => motion_target(buffer.lines(), cursor, motion, count).map(|mut at| {
+                        at.desired = None;
+                        at
+                    }),
+                };
+                match target {
+                    Some(target) => *backend.cursor_mut() = target,
+                    None => return Outcome::Failed,
+                }
+                if let Motion::GotoLine(_) | Motion::MatchingBracket = motion {
+                    return Outcome::Jumped(cursor);
+                }
+            },
+            Action::Operate(operator, target) => {
+                let range = match target {
+                    Target::Selection => Some(self.selected_range(cursor, backend.current_buffer().lines())),
+                    _ => operator_range(backend.current_buffer().lines(), cursor, operator, target, count),
+                };
+                if self.mode != Mode::Insert {
+                    self.mode = Mode::Normal;
+                }
+                match range {
+                    Some(range) => self.operate(operator, range, backend),
+                    None => return Outcome::Failed,
+                }
+            },
+            Action::Insert(at) => {
+                self.start_insert(at, backend);
+            },
+            Action::Paste(before) => {
+                for _ in 0..count.unwrap_or(1) {
+                    self.paste(before, backend);
+                }
+            },
+            Action::Replace(c) => {
+                let count = count.unwrap_or(1);
+                let buffer = backend.current_buffer_mut();
+                if count <= line_len(&buffer.lines()[cursor.line]) - cursor.column {
+                    let end = Cursor::new(cursor.line, cursor.column + count);
+                    buffer.delete_between(cursor, end);
+                    let text: String = ::std::iter::repeat_n(c, count).collect();
+                    buffer.insert_text_at(cursor, &text);
+                    backend.cursor_mut().column = cursor.column + count - 1;
+                }
+            },
+            Action::Join => {
+                // Like vi, `3J` joins three lines, which is two joins.
+                let joins = cmp::max(count.unwrap_or(2), 2) - 1;
+                if let Some(column) = backend.current_buffer_mut().join_lines(cursor.line, joins) {
+                    *backend.cursor_mut() = Cursor::new(cursor.line, column);
+                }
+            },
+            Action::Visual(linewise) => {
+                let mode = if linewise { Mode::VisualLine } else { Mode::Visual };
+                if self.mode == mode {
+                    self.mode = Mode::Normal;
+                } else {
+                    if self.mode == Mode::Normal {
+                        self.anchor = cursor;
+                    }
+                    self.mode = mode;
+                }
+            },
+            Action::Select(object, inner) => {
+                if let Some(range) = object_range(backend.current_buffer().lines(), cursor, object, inner) {
+                    self.anchor = range.start;
+                    if range.linewise {
+                        self.mode = Mode::VisualLine;
+                        *backend.cursor_mut() = Cursor::new(range.end.line, 0);
+                    } else {
+                        let lines = backend.current_buffer().lines();
+                        *backend.cursor_mut() = step_back(lines, range.end).unwrap_or(range.end);
+                    }
+                }
+            },
+            Action::SwapEnds => {
+                *backend.cursor_mut() = self.anchor;
+                self.anchor = cursor;
+            },
+            Action::CommandLine => {
+                self.mode = Mode::Normal;
+                return Outcome::CommandLine;
+            },
+            Action::Undo | Action::Redo => {
+                let buffer = backend.current_buffer_mut();
+                let mut done = 0;
+                while done < count.unwrap_or(1) {
+                    let undone = if action == Action::Undo { buffer.undo() } else { buffer.redo() };
+                    if !undone {
+                        break;
+                    }
+                    done += 1;
+                }
+                if done == 0 {
+                    let end = if action == Action::Undo { "oldest" } else { "newest" };
+                    return Outcome::Message(format!("Already at {} change", end));
+                }
+            },
+            Action::Escape => {
+                // Escape in normal mode gets rid of any extra cursors.
+                if self.mode == Mode::Normal {
+                    backend.clear_cursors();
+                }
+                self.mode = Mode::Normal;
+            },
+            Action::RecordMacro(register) => return Outcome::RecordMacro(register),
+            Action::PlayMacro(register) => return Outcome::PlayMacro(register, count.unwrap_or(1)),
+            Action::SetMark(name) => return Outcome::SetMark(name),
+            Action::JumpToMark(name, exact) => return Outcome::JumpToMark(name, exact),
+            Action::JumpBack => return Outcome::JumpBack,
+            Action::JumpForward => return Outcome::JumpForward,
+            // Repeating is handled before we get here.
+            Action::Repeat => {},
+        }
+        Outcome::Handled
+    }
+    /// Returns the selected text in visual mode.
+    fn selected_range(&self, cursor: Cursor, lines: &[String]) -> Range {
+        // The anchor could be past the end of the text if it was edited from somewhere else.
+        let mut anchor = self.anchor;
+        anchor.clamp(lines);
+        let (start, end) = ordered(anchor, cursor);
+        if self.mode == Mode::VisualLine {
+            return Range { start: start, end: end, linewise: true };
+        }
+        // The character under the end of the selection is included.
+        let end = Cursor::new(end.line, cmp::min(end.column + 1, line_len(&lines[end.line])));
+        Range { start: start, end: end, linewise: false }
+    }
+    /// Applies `operator` to the text in `range`.
+    fn operate(&mut self, operator: Operator, range: Range, backend: &mut Backend) {
+        let buffer = backend.current_buffer_mut();
+        match operator {
+            Operator::Yank => {
+                self.register = yank(buffer, range);
+                *backend.cursor_mut() = if range.linewise {
+                    Cursor::new(range.start.line, backend.cursor().column)
+                } else {
+                    range.start
+                };
+            },
+            Operator::Delete => {
+                self.register = yank(buffer, range);
+                if range.linewise {
+                    let line = buffer.delete_lines(range.start.line, range.end.line);
+                    let column = first_non_blank(&buffer.lines()[line]);
+                    *backend.cursor_mut() = Cursor::new(line, column);
+                } else {
+                    buffer.delete_between(range.start, range.end);
+                    *backend.cursor_mut() = range.start;
+                }
+            },
+            Operator::Change => {
+                self.register = yank(buffer, range);
+                if range.linewise {
+                    // Keep the lines' indentation, and type over the rest.
+                    let line = range.start.line;
+                    let start = Cursor::new(line, first_non_blank(&buffer.lines()[line]));
+                    let end = Cursor::new(range.end.line, line_len(&buffer.lines()[range.end.line]));
+                    buffer.delete_between(start, end);
+                    *backend.cursor_mut() = start;
+                } else {
+                    buffer.delete_between(range.start, range.end);
+                    *backend.cursor_mut() = range.start;
+                }
+                self.mode = Mode::Insert;
+            },
+            Operator::Indent | Operator::Dedent => {
+                if operator == Operator::Indent {
+                    buffer.indent_lines(range.start.line, range.end.line);
+                } else {
+                    buffer.outdent_lines(range.start.line, range.end.line);
+                }
+                let line = range.start.line;
+                let column = first_non_blank(&buffer.lines()[line]);
+                *backend.cursor_mut() = Cursor::new(line, column);
+            },
+        }
+    }
+    /// Moves the cursor to where `at` says and starts insert mode.
+    fn start_insert(&mut self, at: InsertAt, backend: &mut Backend) {
+        let cursor = *backend.cursor();
+        let line = backend.current_buffer().lines()[cursor.line].clone();
+        let len = line_len(&line);
+        match at {
+            InsertAt::Before => {},
+            InsertAt::After => backend.cursor_mut().set_column(cmp::min(cursor.column + 1, len)),
+            InsertAt::LineStart => backend.cursor_mut().set_column(first_non_blank(&line)),
+            InsertAt::LineEnd => backend.cursor_mut().set_column(len),
+            InsertAt::LineBelow => {
+                backend.cursor_mut().column = len;
+                backend.insert_newline();
+            },
+            InsertAt::LineAbove => {
+                let indent: String = line.chars().take(first_non_blank(&line)).collect();
+                let buffer = backend.current_buffer_mut();
+                buffer.insert_text_at(Cursor::new(cursor.line, 0), &format!("{}\n", indent));
+                *backend.cursor_mut() = Cursor::new(cursor.line, line_len(&indent));
+            },
+        }
+        self.mode = Mode::Insert;
+    }
+    /// Puts the register back into the text, after the cursor or before it.
+    fn paste(&mut self, before: bool, backend: &mut Backend) {
+        let cursor = *backend.cursor();
+        let buffer = backend.current_buffer_mut();
+        let text = &self.register.text;
+        if self.register.linewise {
+            let line = if before {
+                buffer.insert_text_at(Cursor::new(cursor.line, 0), &format!("{}\n", text));
+                cursor.line
+            } else {
+                let len = line_len(&buffer.lines()[cursor.line]);
+                buffer.insert_text_at(Cursor::new(cursor.line, len), &format!("\n{}", text));
+                cursor.line + 1
+            };
+            let column = first_non_blank(&buffer.lines()[line]);
+            *backend.cursor_mut() = Cursor::new(line, column);
+        } else {
+            let len = line_len(&buffer.lines()[cursor.line]);
+            let at = if before { cursor } else { Cursor::new(cursor.line, cmp::min(cursor.column + 1, len)) };
+            let end = buffer.insert_text_at(at, text);
+            // Leave the cursor on the last character that was put in.
+            *backend.cursor_mut() = step_back(buffer.lines(), end).unwrap_or(end);
+        }
+    }
+}
+
+/// Reads a command from the keys typed in normal mode, or visual mode if `visual` is true.
+fn parse(keys: &[Key], visual: bool) -> Parse {
+    let mut i = 0;
+    let count = read_count(keys, &mut i);
+    let key = match keys.get(i) {
+        Some(&key) => key,
+        None => return Parse::Incomplete,
+    };
+    i += 1;
+    let operator = match key {
+        Key::Char('d') => Some(Operator::Delete),
+        Key::Char('c') => Some(Operator::Change),
+        Key::Char('y') => Some(Operator::Yank),
+        Key::Char('>') => Some(Operator::Indent),
+        Key::Char('<') => Some(Operator::Dedent),
+        _ => None,
+    };
+    if let Some(operator) = operator {
+        if visual {
+            return Parse::Done(count, Action::Operate(operator, Target::Selection));
+        }
+        // Operators take a count of their own, which multiplies the first one.
+        let second = read_count(keys, &mut i);
+        let count = match (count, second) {
+            (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+            (a, b) => a.or(b),
+        };
+        let target = match keys.get(i) {
+            None => return Parse::Incomplete,
+            Some(&next) if next == key => Target::Line,
+            Some(&Key::Char(c)) if c == 'i' || c == 'a' => match keys.get(i + 1) {
+                None => return Parse::Incomplete,
+                Some(&object) => match parse_object(object) {
+                    Some(object) => Target::Object(object, c == 'i'),
+                    None => return Parse::Invalid,
+                },
+            },
+            Some(_) => match parse_motion(keys, &mut i) {
+                Ok(motion) => Target::Motion(motion),
+                Err(parse) => return parse,
+            },
+        };
+        return Parse::Done(count, Action::Operate(operator, target));
+    }
+    let action = match key {
+        Key::Char('i') | Key::Char('a') if visual => match keys.get(i) {
+            None => return Parse::Incomplete,
+            Some(&object) => match parse_object(object) {
+                Some(object) => Action::Select(object, key == Key::Char('i')),
+                None => return Parse::Invalid,
+            },
+        },
+        Key::Char('x') if visual => Action::Operate(Operator::Delete, Target::Selection),
+        Key::Char('o') if visual => Action::SwapEnds,
+        Key::Char('i') => Action::Insert(InsertAt::Before),
+        Key::Char('a') => Action::Insert(InsertAt::After),
+        Key::Char('I') => Action::Insert(InsertAt::LineStart),
+        Key::Char('A') => Action::Insert(InsertAt::LineEnd),
+        Key::Char('o') => Action::Insert(InsertAt::LineBelow),
+        Key::Char('O') => Action::Insert(InsertAt::LineAbove),
+        Key::Char('x') => Action::Operate(Operator::Delete, Target::Motion(Motion::Right)),
+        Key::Char('X') => Action::Operate(Operator::Delete, Target::Motion(Motion::Left)),
+        Key::Char('s') => Action::Operate(Operator::Change, Target::Motion(Motion::Right)),
+        Key::Char('D') => Action::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+        Key::Char('C') => Action::Operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+        Key::Char('S') => Action::Operate(Operator::Change, Target::Line),
+        Key::Char('Y') => Action::Operate(Operator::Yank, Target::Line),
+        Key::Char('p') => Action::Paste(false),
+        Key::Char('P') => Action::Paste(true),
+        Key::Char('J') => Action::Join,
+        Key::Char('r') => match keys.get(i) {
+            None => return Parse::Incomplete,
+            Some(&Key::Char(c)) if c != '\n' => Action::Replace(c),
+            Some(_) => return Parse::Invalid,
+        },
+        Key::Char('q') => match keys.get(i) {
+            None => return Parse::Incomplete,
+            Some(&Key::Char(c)) if c.is_ascii_alphanumeric() => Action::RecordMacro(c),
+            Some(_) => return Parse::Invalid,
+        },
+        Key::Char('@') => match keys.get(i) {
+            None => return Parse::Incomplete,
+            Some(&Key::Char(c)) if c.is_ascii_alphanumeric() || c == '@' => Action::PlayMacro(c),
+            Some(_) => return Parse::Invalid,
+        },
+        Key::Char('m') => match keys.get(i) {
+            None => return Parse::Incomplete,
+            Some(&Key::Char(c)) if c.is_alphanumeric() => Action::SetMark(c),
+            Some(_) => return Parse::Invalid,
+        },
+        Key::Char(c) if c == '`' || c == '\'' => match keys.get(i) {
+            None => return Parse::Incomplete,
+            Some(&Key::Char(name)) if name.is_alphanumeric() => Action::JumpToMark(name, c == '`'),
+            Some(_) => return Parse::Invalid,
+        },
+        Key::Ctrl('o') => Action::JumpBack,
+        Key::Char('\t') => Action::JumpForward,
+        Key::Char('v') => Action::Visual(false),
+        Key::Char('V') => Action::Visual(true),
+        Key::Char(':') => Action::CommandLine,
+        Key::Char('.') => Action::Repeat,
+        Key::Char('u') => Action::Undo,
+        Key::Ctrl('r') => Action::Redo,
+        Key::Esc => Action::Escape,
+        _ => {
+            i -= 1;
+            match parse_motion(keys, &mut i) {
+                Ok(motion) => Action::Move(motion),
+                Err(parse) => return parse,
+            }
+        },
+    };
+    Parse::Done(count, action)
+}
+
+/// Reads a count from `keys`, starting at `i` and moving `i` past it.
+fn read_count(keys: &[Key], i: &mut usize) -> Option<usize> {
+    let mut count: Option<usize> = None;
+    while let Some(&Key::Char(c)) = keys.get(*i) {
+        match c.to_digit(10) {
+            // A 0 at the start is the motion to the start of the line.
+            Some(0) if count.is_none() => break,
+            Some(digit) => {
+                count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit as usize));
+                *i += 1;
+            },
+            None => break,
+        }
+    }
+    count
+}
+
+/// Returns the keys of a command without its counts, the one before it
+/// and the one after an operator, so that `5.` after `d3w` deletes 5 words.
+fn without_counts(keys: &[Key]) -> Vec<Key> {
+    let mut i = 0;
+    read_count(keys, &mut i);
+    let mut keys = keys[i..].to_vec();
+    if let Some(&Key::Char('d')) | Some(&Key::Char('c')) | Some(&Key::Char('y'))
+        | Some(&Key::Char('>')) | Some(&Key::Char('<')) = keys.first() {
+        let mut end = 1;
+        read_count(&keys, &mut end);
+        keys.drain(1..end);
+    }
+    keys
+}
+
+/// Reads a motion from `keys`, starting at `i`.
+fn parse_motion(keys: &[Key], i: &mut usize) -> Result<Motion, Parse> {
+    let key = *keys.get(*i).ok_or(Parse::Incomplete)?;
+    *i += 1;
+    let motion = match key {
+        Key::Char('h') | Key::Left | Key::Backspace => Motion::Left,
+        Key::Char('l') | Key::Right | Key::Char(' ') => Motion::Right,
+        Key::Char('k') | Key::Up => Motion::Up,
+        Key::Char('j') | Key::Down => Motion::Down,
+        Key::Char('\n') | Key::Char('+') => Motion::NextLine,
+        Key::Char('w') => Motion::WordForward(false),
+        Key::Char('W') => Motion::WordForward(true),
+        Key::Char('b') => Motion::WordBackward(false),
+        Key::Char('B') => Motion::WordBackward(true),
+        Key::Char('e') => Motion::WordEnd(false),
+        Key::Char('E') => Motion::WordEnd(true),
+        Key::Char('0') | Key::Home => Motion::LineStart,
+        Key::Char('^') => Motion::FirstNonBlank,
+        Key::Char('$') | Key::End => Motion::LineEnd,
+        Key::Char('G') => Motion::GotoLine(true),
+        Key::Char('%') => Motion::MatchingBracket,
+        Key::Char('g') => match keys.get(*i) {
+            None => return Err(Parse::Incomplete),
+            Some(&Key::Char('g')) => Motion::GotoLine(false),
+            Some(_) => return Err(Parse::Invalid),
+        },
+        Key::Char(c) if "ftFT".contains(c) => match keys.get(*i) {
+            None => return Err(Parse::Incomplete),
+            Some(&Key::Char(target)) => match c {
+                'f' => Motion::FindForward(target, false),
+                't' => Motion::FindForward(target, true),
+                'F' => Motion::FindBackward(target, false),
+                _ => Motion::FindBackward(target, true),
+            },
+            Some(_) => return Err(Parse::Invalid),
+        },
+        _ => return Err(Parse::Invalid),
+    };
+    Ok(motion)
+}
+
+/// Reads the key after `i` or `a` as a text object.
+fn parse_object(key: Key) -> Option<Object> {
+    let object = match key {
+        Key::Char('w') => Object::Word(false),
+        Key::Char('W') => Object::Word(true),
+        Key::Char('p') => Object::Paragraph,
+        Key::Char(c) if c == '"' || c == '\'' || c == '`' => Object::Quote(c),
+        Key::Char('(') | Key::Char(')') | Key::Char('b') => Object::Bracket('(', ')'),
+        Key::Char('{') | Key::Char('}') | Key::Char('B') => Object::Bracket('{', '}'),
+        Key::Char('[') | Key::Char(']') => Object::Bracket('[', ']'),
+        Key::Char('<') | Key::Char('>') => Object::Bracket('<', '>'),
+        _ => return None,
+    };
+    Some(object)
+}
+
+/// Works out where `motion` moves the cursor to from `cursor`.
+/// Returns None if the motion can't be done, like `f` for a character
+/// that isn't on the line.
+fn motion_target(lines: &[String], cursor: Cursor, motion: Motion, count: Option<usize>) -> Option<Cursor> {
+    let times = count.unwrap_or(1);
+    let mut at = cursor;
+    match motion {
+        Motion::Left => at.column = at.column.saturating_sub(times),
+        Motion::Right => at.column = cmp::min(at.column.saturating_add(times), line_len(&lines[at.line])),
+        Motion::Up | Motion::Down | Motion::NextLine => {
+            at.line = if motion == Motion::Up {
+                at.line.saturating_sub(times)
+            } else {
+                cmp::min(at.line.saturating_add(times), lines.len() - 1)
+            };
+            if motion == Motion::NextLine {
+                at.column = first_non_blank(&lines[at.line]);
+            } else {
+                at.column = cmp::min(at.column, line_len(&lines[at.line]));
+            }
+        },
+        Motion::WordForward(big) => {
+            for _ in 0..times {
+                let next = word_forward(lines, at, big);
+                // A huge count stops at the edge of the text.
+                if next == at {
+                    break;
+                }
+                at = next;
+            }
+        },
+        Motion::WordBackward(big) => {
+            for _ in 0..times {
+                let next = word_backward(lines, at, big);
+                if next == at {
+                    break;
+                }
+                at = next;
+            }
+        },
+        Motion::WordEnd(big) => {
+            for _ in 0..times {
+                let next = word_end(lines, at, big);
+                if next == at {
+                    break;
+                }
+                at = next;
+            }
+        },
+        Motion::LineStart => at.column = 0,
+        Motion::FirstNonBlank => at.column = first_non_blank(&lines[at.line]),
+        Motion::LineEnd => {
+            at.line = cmp::min(at.line.saturating_add(times - 1), lines.len() - 1);
+            at.column = line_len(&lines[at.line]).saturating_sub(1);
+        },
+        Motion::GotoLine(last) => {
+            at.line = match count {
+                Some(line) => cmp::min(line.saturating_sub(1), lines.len() - 1),
+                None if last => lines.len() - 1,
+                None => 0,
+            };
+            at.column = first_non_blank(&lines[at.line]);
+        },
+        Motion::FindForward(c, till) => {
+            let chars: Vec<char> = lines[at.line].chars().collect();
+            let mut column = at.column;
+            for _ in 0..times {
+                // `t` looks past the character right next to the cursor,
+                // so that repeating it doesn't get stuck.
+                let from = if till && column + 1 < chars.len() && chars[column + 1] == c { column + 2 } else { column + 1 };
+                column = from + chars.get(from..)?.iter().position(|&x| x == c)?;
+            }
+            at.column = if till { column - 1 } else { column };
+        },
+        Motion::FindBackward(c, till) => {
+            let chars: Vec<char> = lines[at.line].chars().collect();
+            let mut column = at.column;
+            for _ in 0..times {
+                let to = if till && column >= 2 && chars[column - 1] == c { column - 1 } else { column };
+                column = chars[..to].iter().rposition(|&x| x == c)?;
+            }
+            at.column = if till { column + 1 } else { column };
+        },
+        Motion::MatchingBracket => {
+            let column = lines[at.line].chars().skip(at.column).position(brackets::is_bracket)?;
+            at = brackets::partner(lines, Cursor::new(at.line, at.column + column))?;
+        },
+    }
+    Some(at)
+}
+
+/// Works out the range of text that `operator` works on with `target`.
+fn operator_range(lines: &[String], cursor: Cursor, operator: Operator, target: Target,
+                  count: Option<usize>) -> Option<Range> {
+    match target {
+        Target::Line => {
+            let last = cmp::min(cursor.line.saturating_add(count.unwrap_or(1) - 1), lines.len() - 1);
+            Some(Range { start: cursor, end: Cursor::new(last, 0), linewise: true })
+        },
+        Target::Object(object, inner) => object_range(lines, cursor, object, inner),
+        Target::Motion(motion) => {
+            let on_blank = char_at(lines, cursor).is_none_or(|c| class(c, false) == 0);
+            let target = match motion {
+                // `cw` on a word changes to the end of the word, and doesn't
+                // take the space after it like `dw` does.
+                Motion::WordForward(big) if operator == Operator::Change && !on_blank => {
+                    // On the last character of a word, that word counts as the first one,
+                    // where `e` would go on to the end of the next word.
+                    let kind = char_at(lines, cursor).map(|c| class(c, big));
+                    let next = Cursor::new(cursor.line, cursor.column + 1);
+                    let at_end = char_at(lines, next).map(|c| class(c, big)) != kind;
+                    let times = count.unwrap_or(1) - at_end as usize;
+                    let end = if times == 0 {
+                        cursor
+                    } else {
+                        motion_target(lines, cursor, Motion::WordEnd(big), Some(times))?
+                    };
+                    let end = Cursor::new(end.line, cmp::min(end.column + 1, line_len(&lines[end.line])));
+                    return Some(ordered_range(cursor, end, false));
+                },
+                Motion::WordForward(big) => {
+                    // The last word doesn't carry on to the next line.
+                    let mut at = cursor;
+                    for i in 0..count.unwrap_or(1) {
+                        let next = word_forward(lines, at, big);
+                        if next == at {
+                            break;
+                        }
+                        if i + 1 == count.unwrap_or(1) && next.line > at.line {
+                            at = Cursor::new(at.line, line_len(&lines[at.line]));
+                            if at == cursor {
+                                return None;
+                            }
+                        } else {
+                            at = next;
+                        }
+                    }
+                    return Some(ordered_range(cursor, at, false));
+                },
+                _ => motion,
+            };
+            motion_range(lines, cursor, target, count)
+        },
+        // The selection is worked out by the caller.
+        Target::Selection => None,
+    }
+}
+
+/// Works out the range of text between the cursor and where `motion` goes.
+fn motion_range(lines: &[String], cursor: Cursor, motion: Motion, count: Option<usize>) -> Option<Range> {
+    let target = motion_target(lines, cursor, motion, count)?;
+    if motion.linewise() {
+        let (start, end) = ordered(cursor, target);
+        return Some(Range { start: start, end: end, linewise: true });
+    }
+    let mut range = ordered_range(cursor, target, false);
+    if motion.inclusive() {
+        range.end.column = cmp::min(range.end.column + 1, line_len(&lines[range.end.line]));
+    }
+    if range.start == range.end {
+        return None;
+    }
+    Some(range)
+}
+
+/// Works out the range of a text object around the cursor.
+fn object_range(lines: &[String], cursor: Cursor, object: Object, inner: bool) -> Option<Range> {
+    let line: Vec<char> = lines[cursor.line].chars().collect();
+    match object {
+        Object::Word(big) => {
+            let c = *line.get(cursor.column)?;
+            let kind = class(c, big);
+            let same = |c: char| class(c, big) == kind;
+            let mut start = cursor.column;
+            while start > 0 && same(line[start - 1]) {
+                start -= 1;
+            }
+            let mut end = cursor.column + 1;
+            while end < line.len() && same(line[end]) {
+                end += 1;
+            }
+            if !inner {
+                // Take the space after the word, or before it if there isn't any.
+                let after = line[end..].iter().take_while(|c| c.is_whitespace()).count();
+                if after > 0 {
+                    end += after;
+                } else {
+                    while start > 0 && line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                }
+            }
+            Some(ordered_range(Cursor::new(cursor.line, start), Cursor::new(cursor.line, end), false))
+        },
+        Object::Quote(quote) => {
+            let quotes: Vec<usize> = line.iter().enumerate()
+                .filter(|&(_, &c)| c == quote)
+                .map(|(i, _)| i)
+                .collect();
+            // Find the pair of quotes around the cursor, or the first pair after it.
+            let pair = quotes.chunks(2)
+                .filter(|pair| pair.len() == 2)
+                .find(|pair| cursor.column <= pair[1])?;
+            let (open, close) = (pair[0], pair[1]);
+            let (start, end) = if inner { (open + 1, close) } else { (open, close + 1) };
+            Some(ordered_range(Cursor::new(cursor.line, start), Cursor::new(cursor.line, end), false))
+        },
+        Object::Bracket(open, close) => {
+            // Look backwards for the bracket that isn't closed before the cursor.
+            let mut depth = 0;
+            let mut at = cursor;
+            if char_at(lines, at) == Some(close) {
+                at = step_back(lines, at)?;
+            }
+            let start = loop {
+                match char_at(lines, at) {
+                    Some(c) if c == open && depth == 0 => break at,
+                    Some(c) if c == open => depth -= 1,
+                    Some(c) if c == close => depth += 1,
+                    _ => {},
+                }
+                at = step_back(lines, at)?;
+            };
+            // And forwards for the bracket that closes it.
+            let mut depth = 0;
+            let mut at = step(lines, start)?;
+            let end = loop {
+                match char_at(lines, at) {
+                    Some(c) if c == close && depth == 0 => break at,
+                    Some(c) if c == close => depth -= 1,
+                    Some(c) if c == open => depth += 1,
+                    _ => {},
+                }
+                at = step(lines, at)?;
+            };
+            if inner {
+                Some(ordered_range(step(lines, start)?, end, false))
+            } else {
+                Some(ordered_range(start, step(lines, end)?, false))
+            }
+        },
+        Object::Paragraph => {
+            let blank = |line: usize| lines[line].trim().is_empty();
+            let kind = blank(cursor.line);
+            let mut start = cursor.line;
+            while start > 0 && blank(start - 1) == kind {
+                start -= 1;
+            }
+            let mut end = cursor.line;
+            while end + 1 < lines.len() && blank(end + 1) == kind {
+                end += 1;
+            }
+            if !inner {
+                // Take the blank lines after the paragraph too.
+                while end + 1 < lines.len() && blank(end + 1) != kind {
+                    end += 1;
+                }
+            }
+            Some(Range { start: Cursor::new(start, 0), end: Cursor::new(end, 0), linewise: true })
+        },
+    }
+}
+
+/// Copies the text in `range` into a register.
+fn yank(buffer: &Buffer, range: Range) -> Register {
+    let text = if range.linewise {
+        buffer.lines()[range.start.line..range.end.line + 1].join("\n")
+    } else {
+        buffer.text_between(range.start, range.end)
+    };
+    Register { text: text, linewise: range.linewise }
+}
+
+/// Keeps the cursor on a character in normal mode, rather than past the end of the line.
+fn keep_on_text(backend: &mut Backend) {
+    let cursor = *backend.cursor();
+    let len = line_len(&backend.current_buffer().lines()[cursor.line]);
+    if len > 0 && cursor.column >= len {
+        backend.cursor_mut().column = len - 1;
+    }
+}
+
+/// Puts two positions in order.
+fn ordered(a: Cursor, b: Cursor) -> (Cursor, Cursor) {
+    if (a.line, a.column) <= (b.line, b.column) { (a, b) } else { (b, a) }
+}
+
+fn ordered_range(a: Cursor, b: Cursor, linewise: bool) -> Range {
+    let (start, end) = ordered(a, b);
+    Range { start: start, end: end, linewise: linewise }
+}
+
+/// Returns the column of the first character on `line` that isn't a space or a tab.
+fn first_non_blank(line: &str) -> usize {
+    line.chars().take_while(|&c| c == ' ' || c == '\t').count()
+}
+
+/// The kind of character that `c` is, for moving by words: 0 for whitespace,
+/// 1 for letters, digits and underscores, and 2 for anything else.
+/// When `big` is true everything that isn't whitespace is the same kind.
+fn class(c: char, big: bool) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if big || c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+/// Returns the character at `at`. The end of a line is a newline,
+/// and the end of the last line is nothing.
+fn char_at(lines: &[String], at: Cursor) -> Option<char> {
+    match lines[at.line].chars().nth(at.column) {
+        Some(c) => Some(c),
+        None if at.line + 1 < lines.len() => Some('\n'),
+        None => None,
+    }
+}
+
+/// Returns the position after `at`, going on to the next line at the end of one.
+fn step(lines: &[String], at: Cursor) -> Option<Cursor> {
+    if at.column < line_len(&lines[at.line]) {
+        Some(Cursor::new(at.line, at.column + 1))
+    } else if at.line + 1 < lines.len() {
+        Some(Cursor::new(at.line + 1, 0))
+    } else {
+        None
+    }
+}
+
+/// Returns the position before `at`, going back to the end of the previous line
+/// at the start of one.
+fn step_back(lines: &[String], at: Cursor) -> Option<Cursor> {
+    if at.column > 0 {
+        Some(Cursor::new(at.line, at.column - 1))
+    } else if at.line > 0 {
+        Some(Cursor::new(at.line - 1, line_len(&lines[at.line - 1])))
+    } else {
+        None
+    }
+}
+
+/// Is `at` on an empty line?
+fn on_empty_line(lines: &[String], at: Cursor) -> bool {
+    at.column == 0 && lines[at.line].is_empty()
+}
+
+/// Finds the start of the next word. Empty lines count as words.
+fn word_forward(lines: &[String], from: Cursor, big: bool) -> Cursor {
+    let mut at = from;
+    if let Some(c) = char_at(lines, at) {
+        let kind = class(c, big);
+        if kind != 0 {
+            while char_at(lines, at).is_some_and(|c| class(c, big) == kind) {
+                match step(lines, at) {
+                    Some(next) => at = next,
+                    None => return at,
+                }
+            }
+        }
+    }
+    while let Some(c) = char_at(lines, at) {
+        if class(c, big) != 0 || (at != from && on_empty_line(lines, at)) {
+            break;
+        }
+        match step(lines, at) {
+            Some(next) => at = next,
+            None => break,
+        }
+    }
+    at
+}
+
+/// Finds the end of the word after the cursor.
+fn word_end(lines: &[String], from: Cursor, big: bool) -> Cursor {
+    let mut at = match step(lines, from) {
+        Some(at) => at,
+        None => return from,
+    };
+    while char_at(lines, at).is_some_and(|c| class(c, big) == 0) {
+        match step(lines, at) {
+            Some(next) => at = next,
+            None => return at,
+        }
+    }
+    let kind = char_at(lines, at).map_or(0, |c| class(c, big));
+    while let Some(next) = step(lines, at) {
+        if char_at(lines, next).is_none_or(|c| class(c, big) != kind) {
+            break;
+        }
+        at = next;
+    }
+    at
+}
+
+/// Finds the start of the word before the cursor. Empty lines count as words.
+fn word_backward(lines: &[String], from: Cursor, big: bool) -> Cursor {
+    let mut at = match step_back(lines, from) {
+        Some(at) => at,
+        None => return from,
+    };
+    while char_at(lines, at).is_some_and(|c| class(c, big) == 0) && !on_empty_line(lines, at) {
+        match step_back(lines, at) {
+            Some(previous) => at = previous,
+            None => return at,
+        }
+    }
+    let kind = char_at(lines, at).map_or(0, |c| class(c, big));
+    if kind == 0 {
+        return at;
+    }
+    while let Some(previous) = step_back(lines, at) {
+        if char_at(lines, previous).is_none_or(|c| class(c, big) != kind) {
+            break;
+        }
+        at = previous;
+    }
+    at
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+
+    fn keys(text: &str) -> Vec<Key> {
+        text.chars().map(|c| match c {
+            '\x1b' => Key::Esc,
+            '\x12' => Key::Ctrl('r'),
+            c => Key::Char(c),
+        }).collect()
+    }
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(String::from).collect()
+    }
+
+    fn at(line: usize, column: usize) -> Cursor {
+        Cursor::new(line, column)
+    }
+
+    fn range(start: Cursor, end: Cursor) -> Option<Range> {
+        Some(Range { start: start, end: end, linewise: false })
+    }
+
+    /// Types `typed` in normal mode with `text` in the buffer and the cursor at the start.
+    /// Returns the text and where the cursor ends up.
+    fn type_keys(text: &str, typed: &str) -> (String, (usize, usize)) {
+        let mut backend = Backend::new(Vec::new(), Config::new()).unwrap();
+        backend.current_buffer_mut().insert_text_at(at(0, 0), text);
+        backend.current_buffer_mut().end_undo_group();
+        *backend.cursor_mut() = at(0, 0);
+        let mut vi = Vi::new();
+        for key in keys(typed) {
+            // Like the editor, undo each key on its own, except in insert mode.
+            if vi.mode() != Mode::Insert {
+                backend.end_undo_groups();
+            }
+            vi.handle_key(key, &mut backend);
+        }
+        let cursor = *backend.cursor();
+        (backend.current_lines().join("\n"), (cursor.line, cursor.column))
+    }
+
+    fn text_after(text: &str, typed: &str) -> String {
+        type_keys(text, typed).0
+    }
+
+    #[test]
+    fn parses_commands() {
+        let parse = |text: &str| parse(&keys(text), false);
+        let dw = Action::Operate(Operator::Delete, Target::Motion(Motion::WordForward(false)));
+        assert_eq!(parse("dw"), Parse::Done(None, dw));
+        assert_eq!(parse("3dw"), Parse::Done(Some(3), dw));
+        assert_eq!(parse("d3w"), Parse::Done(Some(3), dw));
+        assert_eq!(parse("2d3w"), Parse::Done(Some(6), dw));
+        assert_eq!(parse("dd"), Parse::Done(None, Action::Operate(Operator::Delete, Target::Line)));
+        assert_eq!(parse("ci("), Parse::Done(None, Action::Operate(Operator::Change,
+                                                                    Target::Object(Object::Bracket('(', ')'), true))));
+        assert_eq!(parse("d"), Parse::Incomplete);
+        assert_eq!(parse("3"), Parse::Incomplete);
+        assert_eq!(parse("di"), Parse::Incomplete);
+        assert_eq!(parse("dz"), Parse::Invalid);
+        assert_eq!(parse("diz"), Parse::Invalid);
+        assert_eq!(parse("0"), Parse::Done(None, Action::Move(Motion::LineStart)));
+        assert_eq!(parse("10j"), Parse::Done(Some(10), Action::Move(Motion::Down)));
+        assert_eq!(parse("gg"), Parse::Done(None, Action::Move(Motion::GotoLine(false))));
+        assert_eq!(parse("gx"), Parse::Invalid);
+        assert_eq!(parse("t,"), Parse::Done(None, Action::Move(Motion::FindForward(',', true))));
+        assert_eq!(parse("F,"), Parse::Done(None, Action::Move(Motion::FindBackward(',', false))));
+        assert_eq!(parse("ra"), Parse::Done(None, Action::Replace('a')));
+        assert_eq!(parse("r\n"), Parse::Invalid);
+        assert_eq!(parse("qa"), Parse::Done(None, Action::RecordMacro('a')));
+        assert_eq!(parse("3u"), Parse::Done(Some(3), Action::Undo));
+        assert_eq!(parse("\x12"), Parse::Done(None, Action::Redo));
+    }
+
+    #[test]
+    fn parses_visual_commands() {
+        let parse = |text: &str| parse(&keys(text), true);
+        assert_eq!(parse("d"), Parse::Done(None, Action::Operate(Operator::Delete, Target::Selection)));
+        assert_eq!(parse("x"), Parse::Done(None, Action::Operate(Operator::Delete, Target::Selection)));
+        assert_eq!(parse("o"), Parse::Done(None, Action::SwapEnds));
+        assert_eq!(parse("iw"), Parse::Done(None, Action::Select(Object::Word(false), true)));
+        assert_eq!(parse("a\""), Parse::Done(None, Action::Select(Object::Quote('"'), false)));
+    }
+
+    #[test]
+    fn reads_counts() {
+        let read = |text: &str| {
+            let mut i = 0;
+            let count = read_count(&keys(text), &mut i);
+            (count, i)
+        };
+        assert_eq!(read("12x"), (Some(12), 2));
+        assert_eq!(read("x"), (None, 0));
+        // A 0 on its own is a motion, but not after other digits.
+        assert_eq!(read("0"), (None, 0));
+        assert_eq!(read("10"), (Some(10), 2));
+        assert_eq!(read("99999999999999999999999999"), (Some(usize::MAX), 26));
+    }
+
+    #[test]
+    fn drops_counts() {
+        let drop = |text: &str| without_counts(&keys(text));
+        assert_eq!(drop("3d2w"), keys("dw"));
+        assert_eq!(drop("d3w"), keys("dw"));
+        assert_eq!(drop("5x"), keys("x"));
+        assert_eq!(drop("d0"), keys("d0"));
+        assert_eq!(drop("c2iwab\x1b"), keys("ciwab\x1b"));
+        assert_eq!(drop("10>>"), keys(">>"));
+    }
+
+    #[test]
+    fn moves_by_words_across_lines() {
+        let text = lines("one two\n  three\n\nfour");
+        let target = |cursor, motion, count| motion_target(&text, cursor, motion, count);
+        assert_eq!(target(at(0, 4), Motion::WordForward(false), None), Some(at(1, 2)));
+        // An empty line counts as a word.
+        assert_eq!(target(at(1, 2), Motion::WordForward(false), None), Some(at(2, 0)));
+        assert_eq!(target(at(0, 0), Motion::WordForward(false), Some(4)), Some(at(3, 0)));
+        assert_eq!(target(at(1, 2), Motion::WordBackward(false), None), Some(at(0, 4)));
+        assert_eq!(target(at(0, 4), Motion::WordEnd(false), None), Some(at(0, 6)));
+        assert_eq!(target(at(0, 6), Motion::WordEnd(false), None), Some(at(1, 6)));
+        // A huge count stops at the end of the text.
+        assert_eq!(target(at(0, 0), Motion::WordForward(false), Some(usize::MAX)), Some(at(3, 4)));
+        assert_eq!(target(at(3, 0), Motion::WordBackward(false), Some(usize::MAX)), Some(at(0, 0)));
+        let big = lines("a.b c");
+        assert_eq!(motion_target(&big, at(0, 0), Motion::WordForward(false), None), Some(at(0, 1)));
+        assert_eq!(motion_target(&big, at(0, 0), Motion::WordForward(true), None), Some(at(0, 4)));
+    }
+
+    #[test]
+    fn finds_characters() {
+        let text = lines("a,b,c");
+        let target = |column, motion, count| motion_target(&text, at(0, column), motion, count);
+        assert_eq!(target(0, Motion::FindForward(',', false), None), Some(at(0, 1)));
+        assert_eq!(target(0, Motion::FindForward(',', false), Some(2)), Some(at(0, 3)));
+        assert_eq!(target(0, Motion::FindForward(',', false), Some(3)), None);
+        assert_eq!(target(0, Motion::FindForward('z', false), None), None);
+        // `t` and `T` look past a match right next to the cursor, so repeating them moves on.
+        assert_eq!(target(2, Motion::FindForward(',', true), None), None);
+        assert_eq!(target(0, Motion::FindForward('c', true), None), Some(at(0, 3)));
+        assert_eq!(target(3, Motion::FindForward('c', true), None), None);
+        assert_eq!(target(4, Motion::FindBackward(',', true), None), Some(at(0, 2)));
+        assert_eq!(target(2, Motion::FindBackward(',', true), None), None);
+        assert_eq!(target(4, Motion::FindBackward(',', false), Some(2)), Some(at(0, 1)));
+    }
+
+    #[test]
+    fn works_out_operator_ranges() {
+        let text = lines("foo  bar\nbaz");
+        let word = Target::Motion(Motion::WordForward(false));
+        let range_of = |cursor, operator, target, count| operator_range(&text, cursor, operator, target, count);
+        // `dw` takes the space after the word, and `cw` doesn't.
+        assert_eq!(range_of(at(0, 0), Operator::Delete, word, None), range(at(0, 0), at(0, 5)));
+        assert_eq!(range_of(at(0, 0), Operator::Change, word, None), range(at(0, 0), at(0, 3)));
+        // On blanks, `cw` is like `dw`.
+        assert_eq!(range_of(at(0, 3), Operator::Change, word, None), range(at(0, 3), at(0, 5)));
+        // The last word of a line doesn't take the newline.
+        assert_eq!(range_of(at(0, 5), Operator::Delete, word, None), range(at(0, 5), at(0, 8)));
+        assert_eq!(range_of(at(0, 0), Operator::Delete, word, Some(2)), range(at(0, 0), at(0, 8)));
+        assert_eq!(range_of(at(0, 0), Operator::Delete, word, Some(3)), range(at(0, 0), at(1, 3)));
+        assert_eq!(range_of(at(0, 0), Operator::Delete, Target::Motion(Motion::LineEnd), None),
+                   range(at(0, 0), at(0, 8)));
+        assert_eq!(range_of(at(0, 2), Operator::Delete, Target::Line, Some(5)),
+                   Some(Range { start: at(0, 2), end: at(1, 0), linewise: true }));
+        assert_eq!(range_of(at(1, 0), Operator::Delete, Target::Motion(Motion::Up), None),
+                   Some(Range { start: at(0, 0), end: at(1, 0), linewise: true }));
+        assert_eq!(range_of(at(0, 0), Operator::Delete, Target::Motion(Motion::Left), None), None);
+    }
+
+    #[test]
+    fn works_out_object_ranges() {
+        let text = lines("say \"hi there\" (a (b)\n c) end\n\npara");
+        let object = |cursor, object, inner| object_range(&text, cursor, object, inner);
+        assert_eq!(object(at(0, 1), Object::Word(false), true), range(at(0, 0), at(0, 3)));
+        assert_eq!(object(at(0, 1), Object::Word(false), false), range(at(0, 0), at(0, 4)));
+        assert_eq!(object(at(0, 6), Object::Quote('"'), true), range(at(0, 5), at(0, 13)));
+        assert_eq!(object(at(0, 6), Object::Quote('"'), false), range(at(0, 4), at(0, 14)));
+        // Brackets can go over lines, and the nested pair is skipped.
+        assert_eq!(object(at(0, 17), Object::Bracket('(', ')'), true), range(at(0, 16), at(1, 2)));
+        assert_eq!(object(at(1, 1), Object::Bracket('(', ')'), false), range(at(0, 15), at(1, 3)));
+        assert_eq!(object(at(0, 0), Object::Bracket('(', ')'), true), None);
+        assert_eq!(object(at(1, 0), Object::Paragraph, true),
+                   Some(Range { start: at(0, 0), end: at(1, 0), linewise: true }));
+        assert_eq!(object(at(1, 0), Object::Paragraph, false),
+                   Some(Range { start: at(0, 0), end: at(2, 0), linewise: true }));
+    }
+
+    #[test]
+    fn changes_and_deletes_words() {
+        assert_eq!(text_after("foo bar", "cwX\x1b"), "X bar");
+        assert_eq!(text_after("foo bar", "dw"), "bar");
+        assert_eq!(text_after("foo bar\nbaz", "wdw"), "foo \nbaz");
+        assert_eq!(text_after("a b c d", "2cwX\x1b"), "X c d");
+        assert_eq!(text_after("a b c d", "cwX\x1b"), "X b c d");
+        assert_eq!(text_after("ab cd", "lcwX\x1b"), "aX cd");
+        assert_eq!(text_after("ab cd", "l2cwX\x1b"), "aX");
+    }
+
+    #[test]
+    fn joins_lines() {
+        assert_eq!(type_keys("a\n  b\nc\nd", "3J"), (String::from("a b c\nd"), (0, 3)));
+        assert_eq!(text_after("a\nb", "J"), "a b");
+        assert_eq!(text_after("a\nb", "1J"), "a b");
+        assert_eq!(text_after("a", "J"), "a");
+    }
+
+    #[test]
+    fn repeats_with_a_new_count() {
+        assert_eq!(text_after("1 2 3 4 5 6 7 8 9", "d3w."), "7 8 9");
+        assert_eq!(text_after("1 2 3 4 5 6 7 8 9", "d3w5."), "9");
+        assert_eq!(text_after("1 2 3 4 5 6 7 8 9", "2d2w."), "9");
+        assert_eq!(text_after("abcdef", "x2."), "def");
+        assert_eq!(text_after("a\nb\nc", "Ax\x1bj."), "ax\nbx\nc");
+    }
+
+    #[test]
+    fn undoes_and_redoes() {
+        assert_eq!(text_after("a\nb\nc", "dddd"), "c");
+        assert_eq!(text_after("a\nb\nc", "ddddu"), "b\nc");
+        assert_eq!(text_after("a\nb\nc", "dddd2u"), "a\nb\nc");
+        assert_eq!(text_after("abc", "ixy\x1bu"), "abc");
+        assert_eq!(text_after("abc", "xxuu\x12"), "bc");
+    }
+}
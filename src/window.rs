@@ -0,0 +1,369 @@
+use std::cmp;
+use cursor::Cursor;
+
+/// The smallest share of a split (in percent) that a pane can be resized to.
+const MIN_RATIO: i16 = 10;
+
+/// A rectangle on the screen, in terminal cells.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Rect {
+        Rect {
+            x: x,
+            y: y,
+            width: width,
+            height: height,
+        }
+    }
+}
+
+/// The ways a pane can be split in two.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Split {
+    /// One pane above the other, with a horizontal line between them.
+    Horizontal,
+    /// One pane beside the other, with a vertical line between them.
+    Vertical,
+}
+
+/// The directions that focus can move in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// A Pane shows part of a buffer.
+/// Every pane has its own cursor and viewport, so you can look at two
+/// different parts of the same buffer at once.
+#[derive(Clone, Debug)]
+pub struct Pane {
+    /// The id of the buffer being shown.
+    pub buffer: usize,
+    /// The cursor in the buffer.
+    /// When the pane has focus the buffer's own cursor is the real one,
+    /// and this is only updated when focus moves to another pane.
+    pub cursor: Cursor,
+    /// The first line of the buffer that is visible.
+    pub top: usize,
+    /// The first visual column of the buffer that is visible.
+    pub left: usize,
+}
+
+impl Pane {
+    pub fn new(buffer: usize, cursor: Cursor) -> Pane {
+        Pane {
+            buffer: buffer,
+            cursor: cursor,
+            top: 0,
+            left: 0,
+        }
+    }
+    /// Scrolls the viewport so that `line` and visual column `column` are
+    /// inside a viewport of `rows` by `columns`.
+    pub fn scroll_to(&mut self, line: usize, column: usize, rows: usize, columns: usize) {
+        if line < self.top {
+            self.top = line;
+        } else if rows > 0 && line >= self.top + rows {
+            self.top = line + 1 - rows;
+        }
+        if column < self.left {
+            self.left = column;
+        } else if columns > 0 && column >= self.left + columns {
+            self.left = column + 1 - columns;
+        }
+    }
+}
+
+/// A node in the layout tree.
+#[derive(Debug)]
+enum Node {
+    Pane(Pane),
+    Split {
+        split: Split,
+        /// The percentage of the space that the first child gets.
+        ratio: i16,
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+}
+
+/// The Layout decides how the screen is shared between panes.
+/// It is a tree where each split divides its space between two children.
+/// Panes are numbered in the order they appear in the tree, which is left
+/// to right and top to bottom.
+#[derive(Debug)]
+pub struct Layout {
+    root: Node,
+    /// The number of the pane with focus.
+    focused: usize,
+}
+
+impl Layout {
+    /// Creates a layout with a single pane showing `buffer`.
+    pub fn new(buffer: usize, cursor: Cursor) -> Layout {
+        Layout {
+            root: Node::Pane(Pane::new(buffer, cursor)),
+            focused: 0,
+        }
+    }
+    /// Returns all of the panes.
+    pub fn panes(&self) -> Vec<&Pane> {
+        let mut panes = Vec::new();
+        self.root.collect(&mut panes);
+        panes
+    }
+    /// Returns all of the panes as mutable references.
+    pub fn panes_mut(&mut self) -> Vec<&mut Pane> {
+        let mut panes = Vec::new();
+        self.root.collect_mut(&mut panes);
+        panes
+    }
+    /// Returns the number of the pane with focus.
+    pub fn focused(&self) -> usize {
+        self.focused
+    }
+    /// Returns the pane with focus.
+    pub fn focused_pane(&self) -> &Pane {
+        self.panes()[self.focused]
+    }
+    /// Returns the pane with focus as a mutable reference.
+    pub fn focused_pane_mut(&mut self) -> &mut Pane {
+        let focused = self.focused;
+        self.panes_mut().remove(focused)
+    }
+    /// Splits the focused pane in two. Both halves show the same buffer,
+    /// and the new half gets focus.
+    pub fn split(&mut self, split: Split) {
+        let focused = self.focused;
+        self.root.split(focused, split, &mut 0);
+        self.focused += 1;
+    }
+    /// Closes the focused pane and gives its space to its neighbour.
+    /// Returns false if it is the last pane, which can't be closed.
+    pub fn close(&mut self) -> bool {
+        if self.panes().len() == 1 {
+            return false;
+        }
+        let focused = self.focused;
+        self.root.remove(focused, &mut 0);
+        if self.focused > 0 {
+            self.focused -= 1;
+        }
+        true
+    }
+    /// Closes every pane except the focused one.
+    pub fn close_others(&mut self) {
+        let pane = self.focused_pane().clone();
+        self.root = Node::Pane(pane);
+        self.focused = 0;
+    }
+    /// Makes the focused pane bigger (or smaller, if `amount` is negative)
+    /// by `amount` percent of the split that it is in.
+    pub fn resize(&mut self, amount: i16) {
+        let focused = self.focused;
+        self.root.resize(focused, amount, &mut 0);
+    }
+    /// Gives focus to the pane with the given number.
+    pub fn focus(&mut self, pane: usize) {
+        if pane < self.panes().len() {
+            self.focused = pane;
+        }
+    }
+    /// Gives focus to the next pane, wrapping around at the end.
+    pub fn focus_next(&mut self) {
+        self.focused = (self.focused + 1) % self.panes().len();
+    }
+    /// Gives focus to the previous pane, wrapping around at the start.
+    pub fn focus_previous(&mut self) {
+        let len = self.panes().len();
+        self.focused = (self.focused + len - 1) % len;
+    }
+    /// Gives focus to the nearest pane in `direction` when the layout fills `area`.
+    pub fn focus_direction(&mut self, direction: Direction, area: Rect) {
+        let areas = self.areas(area);
+        let from = areas[self.focused];
+        let mut best: Option<(usize, usize)> = None;
+        for (i, to) in areas.iter().enumerate() {
+            // How far away the pane is, if it is in the right direction
+            // and lines up with the focused pane.
+            let distance = match direction {
+                Direction::Left if overlaps(from.y, from.height, to.y, to.height) && to.x < from.x =>
+                    from.x - to.x,
+                Direction::Right if overlaps(from.y, from.height, to.y, to.height) && to.x > from.x =>
+                    to.x - from.x,
+                Direction::Up if overlaps(from.x, from.width, to.x, to.width) && to.y < from.y =>
+                    from.y - to.y,
+                Direction::Down if overlaps(from.x, from.width, to.x, to.width) && to.y > from.y =>
+                    to.y - from.y,
+                _ => continue,
+            };
+            if best.is_none_or(|(_, d)| distance < d) {
+                best = Some((i, distance));
+            }
+        }
+        if let Some((i, _)) = best {
+            self.focused = i;
+        }
+    }
+    /// Works out the area of the screen that each pane gets when the layout fills `area`.
+    /// The areas are in the same order as `panes`. Panes that are to the left of
+    /// another pane include the column that separates them.
+    pub fn areas(&self, area: Rect) -> Vec<Rect> {
+        let mut areas = Vec::new();
+        self.root.areas(area, &mut areas);
+        areas
+    }
+}
+
+impl Node {
+    fn collect<'a>(&'a self, panes: &mut Vec<&'a Pane>) {
+        match *self {
+            Node::Pane(ref pane) => panes.push(pane),
+            Node::Split { ref first, ref second, .. } => {
+                first.collect(panes);
+                second.collect(panes);
+            },
+        }
+    }
+    fn collect_mut<'a>(&'a mut self, panes: &mut Vec<&'a mut Pane>) {
+        match *self {
+            Node::Pane(ref mut pane) => panes.push(pane),
+            Node::Split { ref mut first, ref mut second, .. } => {
+                first.collect_mut(panes);
+                second.collect_mut(panes);
+            },
+        }
+    }
+    /// Returns the number of panes in this node.
+    fn len(&self) -> usize {
+        match *self {
+            Node::Pane(_) => 1,
+            Node::Split { ref first, ref second, .. } => first.len() + second.len(),
+        }
+    }
+    /// Splits pane number `target`. `seen` counts the panes before this node.
+    /// Returns true once the pane has been split.
+    fn split(&mut self, target: usize, split: Split, seen: &mut usize) -> bool {
+        let pane = match *self {
+            Node::Pane(ref pane) => {
+                if *seen != target {
+                    *seen += 1;
+                    return false;
+                }
+                pane.clone()
+            },
+            Node::Split { ref mut first, ref mut second, .. } => {
+                return first.split(target, split, seen) || second.split(target, split, seen);
+            },
+        };
+        *self = Node::Split {
+            split: split,
+            ratio: 50,
+            first: Box::new(Node::Pane(pane.clone())),
+            second: Box::new(Node::Pane(pane)),
+        };
+        true
+    }
+    /// Is this node a single pane?
+    fn is_pane(&self) -> bool {
+        match *self {
+            Node::Pane(_) => true,
+            Node::Split { .. } => false,
+        }
+    }
+    /// Removes pane number `target`, replacing its parent split with its sibling.
+    /// Returns true once the pane has been removed.
+    fn remove(&mut self, target: usize, seen: &mut usize) -> bool {
+        let sibling = match *self {
+            Node::Pane(_) => {
+                *seen += 1;
+                return false;
+            },
+            Node::Split { ref mut first, ref mut second, .. } => {
+                if first.is_pane() && *seen == target {
+                    take_node(second)
+                } else if second.is_pane() && *seen + first.len() == target {
+                    take_node(first)
+                } else {
+                    return first.remove(target, seen) || second.remove(target, seen);
+                }
+            },
+        };
+        *self = sibling;
+        true
+    }
+    /// Resizes the innermost split containing pane number `target`.
+    /// Returns true once something has been resized.
+    fn resize(&mut self, target: usize, amount: i16, seen: &mut usize) -> bool {
+        match *self {
+            Node::Pane(_) => {
+                *seen += 1;
+                false
+            },
+            Node::Split { ref mut ratio, ref mut first, ref mut second, .. } => {
+                let start = *seen;
+                if first.resize(target, amount, seen) || second.resize(target, amount, seen) {
+                    return true;
+                }
+                let first_len = first.len();
+                if target >= start && target < start + first_len {
+                    *ratio = clamp_ratio(*ratio + amount);
+                    true
+                } else if target >= start + first_len && target < *seen {
+                    *ratio = clamp_ratio(*ratio - amount);
+                    true
+                } else {
+                    false
+                }
+            },
+        }
+    }
+    fn areas(&self, area: Rect, areas: &mut Vec<Rect>) {
+        match *self {
+            Node::Pane(_) => areas.push(area),
+            Node::Split { split, ratio, ref first, ref second } => {
+                let (a, b) = match split {
+                    Split::Horizontal => {
+                        let height = cmp::max(1, area.height * ratio as usize / 100);
+                        let height = cmp::min(height, area.height.saturating_sub(1));
+                        (Rect::new(area.x, area.y, area.width, height),
+                         Rect::new(area.x, area.y + height, area.width, area.height - height))
+                    },
+                    Split::Vertical => {
+                        let width = cmp::max(1, area.width * ratio as usize / 100);
+                        let width = cmp::min(width, area.width.saturating_sub(1));
+                        (Rect::new(area.x, area.y, width, area.height),
+                         Rect::new(area.x + width, area.y, area.width - width, area.height))
+                    },
+                };
+                first.areas(a, areas);
+                second.areas(b, areas);
+            },
+        }
+    }
+}
+
+/// Takes the node out of a box, leaving a placeholder behind.
+fn take_node(node: &mut Box<Node>) -> Node {
+    let placeholder = Node::Pane(Pane::new(0, Cursor::new(0, 0)));
+    *::std::mem::replace(node, Box::new(placeholder))
+}
+
+fn clamp_ratio(ratio: i16) -> i16 {
+    ratio.clamp(MIN_RATIO, 100 - MIN_RATIO)
+}
+
+/// Do the ranges `a..a+a_len` and `b..b+b_len` overlap?
+fn overlaps(a: usize, a_len: usize, b: usize, b_len: usize) -> bool {
+    a < b + b_len && b < a + a_len
+}